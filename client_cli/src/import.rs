@@ -0,0 +1,221 @@
+//! Imports bank statements and other CSV files with a header row, one transaction per row.
+
+use crate::{create_transaction, currencies, parse_amount, print, BookArg, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::Args;
+use finance_lib::client::FinanceClient;
+use finance_lib::{NewPosting, NewTransaction};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct CsvArgs {
+    #[command(flatten)]
+    book: BookArg,
+    /// The CSV file, `-` for standard input.
+    file: PathBuf,
+    /// Account receiving the amounts, like the bank account of a statement.
+    #[arg(long)]
+    account: String,
+    /// Account giving the amounts, like an account for sorting them out later.
+    #[arg(long)]
+    contra: String,
+    #[arg(long)]
+    currency: String,
+    #[arg(long, default_value = "date")]
+    date_column: String,
+    #[arg(long, default_value = "description")]
+    desc_column: String,
+    /// Amounts look like -12.50.
+    #[arg(long, default_value = "amount")]
+    amount_column: String,
+    /// See the strftime format of chrono.
+    #[arg(long, default_value = "%Y-%m-%d")]
+    date_format: String,
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+    /// Post the transactions rather than leaving them drafts.
+    #[arg(long)]
+    post: bool,
+}
+
+#[derive(Serialize)]
+struct Imported {
+    /// Line of the row in the file.
+    line: u64,
+    id: i64,
+}
+
+/// Idempotency key of a row, the same whenever the row is imported into the same accounts.
+/// `occurrence` tells apart rows that are alike, like two coffees on one day.
+fn row_key(args: &CsvArgs, row: &[&str], occurrence: usize) -> String {
+    let mut hash = Sha256::new();
+    for part in [&args.book.book, &args.account, &args.contra, &args.currency] {
+        hash.update(part.as_bytes());
+        hash.update([0]);
+    }
+    for field in row {
+        hash.update(field.as_bytes());
+        hash.update([0]);
+    }
+    hash.update(occurrence.to_le_bytes());
+    format!("csv-{}", hex::encode(hash.finalize()))
+}
+
+/// Keys of the rows of one file, in the order they are read.
+#[derive(Default)]
+struct RowKeys {
+    seen: HashMap<String, usize>,
+}
+
+impl RowKeys {
+    fn next(&mut self, args: &CsvArgs, row: &[&str]) -> String {
+        let occurrence = self.seen.entry(row.join("\u{0}")).or_default();
+        let key = row_key(args, row, *occurrence);
+        *occurrence += 1;
+        key
+    }
+}
+
+/// The postings of a row moving `amount` from the contra account to the account. `None` if the
+/// amount cannot be negated.
+fn row_postings(args: &CsvArgs, amount: i32) -> Option<[NewPosting; 2]> {
+    let posting = |account_name: &str, amount: i32| NewPosting {
+        valuta: None,
+        account_name: account_name.to_owned(),
+        currency: args.currency.clone(),
+        amount,
+        budget: false,
+    };
+    Some([
+        posting(&args.account, amount),
+        posting(&args.contra, amount.checked_neg()?),
+    ])
+}
+
+fn parse_date(text: &str, format: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, format)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, format)
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+pub async fn csv(client: &FinanceClient, json: bool, args: &CsvArgs) -> Result<()> {
+    let input: Box<dyn Read> = if args.file.as_os_str() == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&args.file)?)
+    };
+    let delimiter = u8::try_from(args.delimiter)
+        .map_err(|_| format!("The delimiter '{}' is not a single byte", args.delimiter))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(input);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim() == name)
+            .ok_or_else(|| format!("There is no column '{}'", name))
+    };
+    let (date_column, desc_column, amount_column) = (
+        column(&args.date_column)?,
+        column(&args.desc_column)?,
+        column(&args.amount_column)?,
+    );
+
+    let book_name = &args.book.book;
+    let currencies = currencies(client, book_name).await?;
+    let mut keys = RowKeys::default();
+    let mut imported = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|at| at.line()).unwrap_or_default();
+        let field = |column: usize| record.get(column).unwrap_or_default().trim();
+        let time = parse_date(field(date_column), &args.date_format)
+            .ok_or_else(|| format!("Line {}: '{}' is not a date", line, field(date_column)))?;
+        let amount = parse_amount(field(amount_column), &args.currency, &currencies)
+            .map_err(|error| format!("Line {}: {}", line, error))?;
+        let postings = row_postings(args, amount).ok_or_else(|| {
+            format!(
+                "Line {}: the amount {} is out of range",
+                line,
+                field(amount_column)
+            )
+        })?;
+        let row: Vec<&str> = record.iter().collect();
+        let key = keys.next(args, &row);
+
+        let transaction = NewTransaction {
+            description: Some(field(desc_column).to_owned()).filter(|desc| !desc.is_empty()),
+            time: Some(time),
+            posted: None,
+        };
+        let id = create_transaction(client, book_name, &transaction, &postings, &key, args.post)
+            .await
+            .map_err(|error| format!("Line {}: {}", line, error))?;
+        imported.push(Imported { line, id });
+    }
+    print(json, &imported, |imported| {
+        format!("Imported {} transactions.", imported.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(account: &str) -> CsvArgs {
+        CsvArgs {
+            book: BookArg {
+                book: "Household".to_owned(),
+            },
+            file: PathBuf::from("-"),
+            account: account.to_owned(),
+            contra: "Unsorted".to_owned(),
+            currency: "EUR".to_owned(),
+            date_column: "date".to_owned(),
+            desc_column: "description".to_owned(),
+            amount_column: "amount".to_owned(),
+            date_format: "%Y-%m-%d".to_owned(),
+            delimiter: ',',
+            post: false,
+        }
+    }
+
+    #[test]
+    fn alike_rows_get_keys_of_their_own() {
+        let bank = args("Bank");
+        let coffee = ["2024-01-03", "Coffee", "-3.20"];
+        let rows = [coffee, ["2024-01-03", "Bread", "-2.10"], coffee];
+        let import = || {
+            let mut keys = RowKeys::default();
+            rows.iter()
+                .map(|row| keys.next(&bank, row))
+                .collect::<Vec<_>>()
+        };
+        let keys = import();
+        assert_ne!(keys[0], keys[2]);
+        assert_ne!(keys[0], keys[1]);
+        // Importing the file again sends the same keys, so nothing is booked twice.
+        assert_eq!(import(), keys);
+        let mut other_account = RowKeys::default();
+        assert_ne!(other_account.next(&args("Card"), &coffee), keys[0]);
+    }
+
+    #[test]
+    fn amounts_that_cannot_be_negated_are_refused() {
+        let bank = args("Bank");
+        let [account, contra] = row_postings(&bank, -1250).unwrap();
+        assert_eq!((account.amount, contra.amount), (-1250, 1250));
+        assert_eq!(contra.account_name, "Unsorted");
+        assert!(row_postings(&bank, i32::MIN).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! Exports a book as a journal of ledger-cli, or as JSON.
+
+use crate::{currencies, print, BookArg, Result};
+use clap::Args;
+use finance_lib::client::FinanceClient;
+use finance_lib::{amount, Posting, Transaction};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Args)]
+pub struct LedgerArgs {
+    #[command(flatten)]
+    book: BookArg,
+    /// Leave out draft transactions.
+    #[arg(long)]
+    posted_only: bool,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    transaction: Transaction,
+    postings: Vec<Posting>,
+}
+
+/// Transactions fetched per request.
+const PAGE_SIZE: i64 = 200;
+
+fn format_entry(entry: &Entry, currencies: &HashMap<String, i32>) -> String {
+    let transaction = &entry.transaction;
+    let date = transaction.time.map(|time| time.date()).unwrap_or_default();
+    let state = if transaction.posted == Some(true) {
+        "*"
+    } else {
+        "!"
+    };
+    let mut lines = vec![format!(
+        "{} {} ({}) {}",
+        date,
+        state,
+        transaction.id,
+        transaction.description.as_deref().unwrap_or_default()
+    )
+    .trim_end()
+    .to_owned()];
+    if let Some(reverses) = transaction.reverses {
+        lines.push(format!("    ; Reverses {}", reverses));
+    }
+    for posting in &entry.postings {
+        let account = if posting.budget == Some(true) {
+            format!("({})", posting.account_name)
+        } else {
+            posting.account_name.clone()
+        };
+        let decimal_points = currencies
+            .get(&posting.currency)
+            .copied()
+            .unwrap_or_default();
+        let mut line = format!(
+            "    {:<40} {:>14} {}",
+            account,
+            amount::format(posting.amount.into(), decimal_points),
+            posting.currency
+        );
+        if let Some(valuta) = posting.valuta.filter(|valuta| valuta.date() != date) {
+            line.push_str(&format!("  ; [={}]", valuta.date()));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+pub async fn export(client: &FinanceClient, json: bool, args: &LedgerArgs) -> Result<()> {
+    let book_name = &args.book.book;
+    let currencies = currencies(client, book_name).await?;
+    let mut entries = vec![];
+    for transaction_id in client.all_transactions(book_name, PAGE_SIZE).await? {
+        let transaction = client
+            .get_transaction(book_name, transaction_id)
+            .await?
+            .value;
+        if args.posted_only && transaction.posted != Some(true) {
+            continue;
+        }
+        let mut postings = vec![];
+        for posting_id in client.get_postings(book_name, transaction_id).await? {
+            let posting = client
+                .get_posting(book_name, transaction_id, posting_id)
+                .await?;
+            postings.push(posting.value);
+        }
+        entries.push(Entry {
+            transaction,
+            postings,
+        });
+    }
+    entries.sort_by_key(|entry| (entry.transaction.time, entry.transaction.id));
+    print(json, &entries, |entries| {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|entry| format_entry(entry, &currencies))
+            .collect();
+        entries.join("\n\n")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn posting(account_name: &str, amount: i32) -> Posting {
+        Posting {
+            id: 0,
+            valuta: None,
+            account_name: account_name.to_owned(),
+            currency: "EUR".to_owned(),
+            amount,
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn entries_look_like_ledger_journals() {
+        let day = |day| {
+            NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+        };
+        let mut budget = posting("Budget:Food", -1250);
+        budget.budget = Some(true);
+        let mut late = posting("Expenses:Food", 1250);
+        late.valuta = day(5);
+        let entry = Entry {
+            transaction: Transaction {
+                id: 7,
+                time: day(3),
+                description: Some("Market".to_owned()),
+                posted: Some(true),
+                reverses: Some(6),
+            },
+            postings: vec![posting("Assets:Bank", -1250), late, budget],
+        };
+        let currencies = HashMap::from([("EUR".to_owned(), 2)]);
+        let expected = [
+            "2024-01-03 * (7) Market",
+            "    ; Reverses 6",
+            "    Assets:Bank                                      -12.50 EUR",
+            "    Expenses:Food                                     12.50 EUR  ; [=2024-01-05]",
+            "    (Budget:Food)                                    -12.50 EUR",
+        ];
+        assert_eq!(format_entry(&entry, &currencies), expected.join("\n"));
+
+        let draft = Entry {
+            transaction: Transaction {
+                id: 8,
+                time: day(4),
+                description: None,
+                posted: None,
+                reverses: None,
+            },
+            postings: vec![],
+        };
+        assert_eq!(format_entry(&draft, &currencies), "2024-01-04 ! (8)");
+    }
+}
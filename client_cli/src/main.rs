@@ -0,0 +1,420 @@
+//! `finance`, a command-line client of the server for scripts and cron jobs. It reads the server
+//! from `FINANCE_URL`, the token from `FINANCE_TOKEN` and the book from `FINANCE_BOOK`, unless
+//! they are given as options. With `--json`, results are printed as JSON, and errors as the
+//! error body of the server on standard error. Failures exit with status 1.
+//!
+//! ```sh
+//! finance books list
+//! finance tx add --book home --date 2023-06-01 --desc Groceries \
+//!     --posting 'Assets:Bank -12.50 EUR' --posting 'Expenses:Food 12.50 EUR'
+//! finance balance Assets:Bank --as-of 2023-06-30 --json
+//! ```
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use clap::{Args, Parser, Subcommand};
+use finance_lib::client::{ClientError, FinanceClient};
+use finance_lib::{amount, Book, Interval, NewPosting, NewTransaction, ReportQuery};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::process::ExitCode;
+
+mod import;
+mod ledger;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "finance",
+    version,
+    about = "Command-line client of the finance server"
+)]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        env = "FINANCE_URL",
+        default_value = "http://127.0.0.1:8080/"
+    )]
+    url: String,
+    #[arg(long, global = true, env = "FINANCE_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Print results as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists and creates books.
+    #[command(subcommand)]
+    Books(BooksCommand),
+    /// Lists the accounts of a book.
+    #[command(subcommand)]
+    Accounts(AccountsCommand),
+    /// Creates transactions.
+    #[command(subcommand)]
+    Tx(TxCommand),
+    /// Balance of an account and the accounts below it, per currency.
+    Balance(BalanceArgs),
+    /// Creates transactions from files.
+    #[command(subcommand)]
+    Import(ImportCommand),
+    /// Writes a book in the format of other tools.
+    #[command(subcommand)]
+    Export(ExportCommand),
+}
+
+#[derive(Subcommand)]
+enum BooksCommand {
+    /// Names of the books, or the books themselves as JSON.
+    List,
+    /// Creates an empty book.
+    Create {
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountsCommand {
+    /// Names of the accounts of the book.
+    List(BookArg),
+}
+
+#[derive(Subcommand)]
+enum TxCommand {
+    /// Creates a transaction with its postings and prints its id.
+    Add(TxAddArgs),
+}
+
+#[derive(Subcommand)]
+enum ImportCommand {
+    /// Books every row of a CSV file with a header row as a transaction moving its amount from
+    /// `--contra` to `--account`. Importing a file again within 30 days, like after a failure,
+    /// skips the rows imported already.
+    Csv(import::CsvArgs),
+}
+
+#[derive(Subcommand)]
+enum ExportCommand {
+    /// Prints the transactions of the book as a journal of ledger-cli, oldest first. Posted
+    /// transactions are cleared, drafts pending and budget postings virtual.
+    Ledger(ledger::LedgerArgs),
+}
+
+#[derive(Args)]
+pub struct BookArg {
+    #[arg(long, env = "FINANCE_BOOK")]
+    pub book: String,
+}
+
+#[derive(Args)]
+struct TxAddArgs {
+    #[command(flatten)]
+    book: BookArg,
+    /// Like 2023-06-01 or 2023-06-01 12:00, the server takes the current time without it.
+    #[arg(long, value_parser = parse_time)]
+    date: Option<NaiveDateTime>,
+    /// Description of the transaction.
+    #[arg(long)]
+    desc: Option<String>,
+    /// Account, amount and currency, like 'Assets:Bank -12.50 EUR'. Repeat it for every posting.
+    #[arg(long = "posting", value_parser = parse_posting, required = true)]
+    postings: Vec<PostingArg>,
+    /// Post the transaction rather than leaving it a draft.
+    #[arg(long)]
+    post: bool,
+    /// Creates the transaction only once, however often the command is retried with the key.
+    /// Without one, a key is made up and shown if the command fails.
+    #[arg(long)]
+    idempotency_key: Option<String>,
+}
+
+#[derive(Clone)]
+struct PostingArg {
+    account_name: String,
+    amount: String,
+    currency: String,
+}
+
+#[derive(Args)]
+struct BalanceArgs {
+    #[command(flatten)]
+    book: BookArg,
+    account: String,
+    /// Counts what was posted up to this date or time, by default everything up to now.
+    #[arg(long, value_parser = parse_time)]
+    as_of: Option<NaiveDateTime>,
+    /// Count budget postings too.
+    #[arg(long)]
+    budget: bool,
+}
+
+#[derive(Serialize)]
+struct Balance {
+    currency: String,
+    /// In the smallest unit of the currency.
+    amount: i64,
+    formatted: String,
+}
+
+/// Reads times like `2023-06-01 12:00` or dates like `2023-06-01`, which start at midnight.
+pub fn parse_time(text: &str) -> std::result::Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+        .ok_or_else(|| format!("'{}' is not like 2023-06-01 or 2023-06-01 12:00", text))
+}
+
+/// Splits a posting at its last two spaces, account names may contain spaces themselves.
+fn parse_posting(text: &str) -> std::result::Result<PostingArg, String> {
+    let invalid = || format!("'{}' is not like 'Assets:Bank -12.50 EUR'", text);
+    let (rest, currency) = text.trim().rsplit_once(' ').ok_or_else(invalid)?;
+    let (account_name, amount) = rest.trim_end().rsplit_once(' ').ok_or_else(invalid)?;
+    Ok(PostingArg {
+        account_name: account_name.trim().to_owned(),
+        amount: amount.to_owned(),
+        currency: currency.to_owned(),
+    })
+}
+
+/// Decimal points of each currency of a book.
+pub async fn currencies(client: &FinanceClient, book_name: &str) -> Result<HashMap<String, i32>> {
+    let mut currencies = HashMap::new();
+    for symbol in client.get_currencies(book_name).await? {
+        let currency = client.get_currency(book_name, &symbol).await?;
+        currencies.insert(symbol, currency.decimal_points);
+    }
+    Ok(currencies)
+}
+
+/// Amount of `text` in the smallest unit of `currency`.
+pub fn parse_amount(text: &str, currency: &str, currencies: &HashMap<String, i32>) -> Result<i32> {
+    let decimal_points = currencies
+        .get(currency)
+        .ok_or_else(|| format!("Unknown currency '{}'", currency))?;
+    amount::parse(text, *decimal_points)
+        .and_then(|amount| i32::try_from(amount).ok())
+        .ok_or_else(|| format!("Invalid amount '{}' of {}", text, currency).into())
+}
+
+/// Prints `value` as JSON, or else as the text made of it unless that is empty.
+fn print<T: Serialize>(json: bool, value: &T, text: impl FnOnce(&T) -> String) -> Result<()> {
+    let output = if json {
+        serde_json::to_string_pretty(value)?
+    } else {
+        text(value)
+    };
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+    Ok(())
+}
+
+/// Creates a transaction with its postings, returns its id. Retrying with the same key creates
+/// only what is missing. Postings use the key followed by their index.
+pub async fn create_transaction(
+    client: &FinanceClient,
+    book_name: &str,
+    transaction: &NewTransaction,
+    postings: &[NewPosting],
+    key: &str,
+    post: bool,
+) -> Result<i64> {
+    let transaction_id = client
+        .create_transaction_once(book_name, transaction, key)
+        .await?;
+    for (i, posting) in postings.iter().enumerate() {
+        let key = format!("{}-{}", key, i);
+        client
+            .create_posting_once(book_name, transaction_id, posting, &key)
+            .await?;
+    }
+    if post {
+        // A retry may find the transaction posted already.
+        let stored = client.get_transaction(book_name, transaction_id).await?;
+        if stored.posted != Some(true) {
+            client.post_transaction(book_name, transaction_id).await?;
+        }
+    }
+    Ok(transaction_id)
+}
+
+async fn add_transaction(client: &FinanceClient, json: bool, args: &TxAddArgs) -> Result<()> {
+    let book_name = &args.book.book;
+    let currencies = currencies(client, book_name).await?;
+    let mut postings = vec![];
+    for posting in &args.postings {
+        postings.push(NewPosting {
+            valuta: None,
+            account_name: posting.account_name.clone(),
+            currency: posting.currency.clone(),
+            amount: parse_amount(&posting.amount, &posting.currency, &currencies)?,
+            budget: false,
+        });
+    }
+    let transaction = NewTransaction {
+        description: args.desc.clone(),
+        time: args.date,
+        posted: None,
+    };
+    // Without a key of the user one is made up, and told on failure so a retry can use it.
+    let key = match &args.idempotency_key {
+        Some(key) => key.clone(),
+        None => format!(
+            "cli-{}-{}",
+            chrono::Utc::now().format("%Y%m%d%H%M%S%f"),
+            std::process::id()
+        ),
+    };
+    let created =
+        create_transaction(client, book_name, &transaction, &postings, &key, args.post).await;
+    let transaction_id = match created {
+        Ok(transaction_id) => transaction_id,
+        Err(error) if args.idempotency_key.is_none() => {
+            return Err(format!(
+                "{}. Retry with --idempotency-key {} to create only what is missing.",
+                error, key
+            )
+            .into())
+        }
+        Err(error) => return Err(error),
+    };
+    print(json, &json!({ "id": transaction_id }), |_| {
+        transaction_id.to_string()
+    })
+}
+
+async fn balance(client: &FinanceClient, json: bool, args: &BalanceArgs) -> Result<()> {
+    let book_name = &args.book.book;
+    // A date counts as a whole day.
+    let to = match args.as_of {
+        Some(as_of) if as_of.time() == chrono::NaiveTime::MIN => as_of + Duration::days(1),
+        Some(as_of) => as_of,
+        None => chrono::Utc::now().naive_utc(),
+    };
+    let query = ReportQuery {
+        from: Some(to - Duration::days(1)),
+        to: Some(to),
+        interval: Some(Interval::Day),
+        account: Some(args.account.clone()),
+        budget: Some(args.budget),
+        ..Default::default()
+    };
+    // The balance at the end of the last period is the one up to `to`.
+    let mut balances: BTreeMap<String, i64> = BTreeMap::new();
+    for point in client.balance_report(book_name, &query).await? {
+        balances.insert(point.currency, point.balance);
+    }
+    let currencies = currencies(client, book_name).await?;
+    let balances: Vec<Balance> = balances
+        .into_iter()
+        .map(|(currency, amount)| {
+            let decimal_points = currencies.get(&currency).copied().unwrap_or_default();
+            Balance {
+                formatted: amount::format(amount, decimal_points),
+                currency,
+                amount,
+            }
+        })
+        .collect();
+    print(json, &balances, |balances| {
+        let lines: Vec<String> = balances
+            .iter()
+            .map(|balance| format!("{} {}", balance.formatted, balance.currency))
+            .collect();
+        lines.join("\n")
+    })
+}
+
+async fn run(cli: &Cli) -> Result<()> {
+    let url = cli
+        .url
+        .parse()
+        .map_err(|error| format!("Invalid url '{}': {}", cli.url, error))?;
+    let mut client = FinanceClient::new(url);
+    client.set_token(cli.token.clone());
+    let client = &client;
+    let json = cli.json;
+    match &cli.command {
+        Command::Books(BooksCommand::List) => {
+            let mut books = vec![];
+            for name in client.get_books().await? {
+                books.push(client.get_book(&name).await?.value);
+            }
+            print(json, &books, |books| {
+                let names: Vec<&str> = books.iter().map(|book| book.name.as_str()).collect();
+                names.join("\n")
+            })
+        }
+        Command::Books(BooksCommand::Create { name, description }) => {
+            let book = Book {
+                name: name.clone(),
+                description: description.clone(),
+                immutable: None,
+                lock_date: None,
+            };
+            client.create_book(&book).await?;
+            print(json, &book, |_| String::new())
+        }
+        Command::Accounts(AccountsCommand::List(book)) => {
+            let accounts = client.get_accounts(&book.book).await?;
+            print(json, &accounts, |accounts| accounts.join("\n"))
+        }
+        Command::Tx(TxCommand::Add(args)) => add_transaction(client, json, args).await,
+        Command::Balance(args) => balance(client, json, args).await,
+        Command::Import(ImportCommand::Csv(args)) => import::csv(client, json, args).await,
+        Command::Export(ExportCommand::Ledger(args)) => ledger::export(client, json, args).await,
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error = match run(&cli).await {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(error) => error,
+    };
+    if cli.json {
+        let body = match error.downcast_ref::<ClientError>() {
+            Some(ClientError::Api(api_error)) => json!(api_error),
+            _ => json!({ "message": error.to_string() }),
+        };
+        eprintln!("{}", body);
+    } else {
+        eprintln!("finance: {}", error);
+    }
+    ExitCode::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postings_split_at_their_last_two_spaces() {
+        let posting = parse_posting("Assets:Bank -12.50 EUR").unwrap();
+        assert_eq!(posting.account_name, "Assets:Bank");
+        assert_eq!(posting.amount, "-12.50");
+        assert_eq!(posting.currency, "EUR");
+        let posting = parse_posting(" Expenses:Eating out  3 EUR ").unwrap();
+        assert_eq!(posting.account_name, "Expenses:Eating out");
+        assert_eq!(posting.amount, "3");
+        assert!(parse_posting("Assets:Bank -12.50").is_err());
+
+        let currencies = HashMap::from([("EUR".to_owned(), 2)]);
+        assert_eq!(parse_amount("-12.50", "EUR", &currencies).unwrap(), -1250);
+        assert!(parse_amount("12.50", "USD", &currencies).is_err());
+        assert!(parse_amount("99999999999", "EUR", &currencies).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Configuration file of the TUI, read from `FINANCE_TUI_CONFIG` or else
+//! `$XDG_CONFIG_HOME/finance/tui.toml`, which defaults to `~/.config/finance/tui.toml`.
+//!
+//! ```toml
+//! [[profile]]
+//! name = "home"
+//! url = "https://finance.example.org/"
+//! token_command = "pass show finance/home"
+//!
+//! [[profile]]
+//! name = "work"
+//! url = "https://finance.example.com/"
+//! user = "alice"
+//!
+//! [keys]
+//! new = ["a"]
+//! ```
+//!
+//! See [`crate::keymap`] for the key bindings.
+
+use crate::keymap::{Action, Key};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::Command;
+
+const DEFAULT_URL: &str = "http://127.0.0.1:8080/";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<Profile>,
+    /// Keys replacing the default keys of actions.
+    #[serde(default)]
+    pub keys: HashMap<Action, Vec<Key>>,
+}
+
+/// A server along with how to authenticate at it. Without `token` and `token_command`, the TUI
+/// asks for the password of `user` and logs in.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    pub url: String,
+    pub token: Option<String>,
+    /// Shell command printing the token, for keeping it in a password manager.
+    pub token_command: Option<String>,
+    pub user: Option<String>,
+}
+
+impl Profile {
+    /// Profile of `FINANCE_URL` and `FINANCE_TOKEN`, used without a configuration file.
+    fn from_env() -> Self {
+        Self {
+            name: "default".to_owned(),
+            url: env::var("FINANCE_URL").unwrap_or_else(|_| DEFAULT_URL.to_owned()),
+            token: env::var("FINANCE_TOKEN").ok(),
+            token_command: None,
+            user: None,
+        }
+    }
+
+    /// The configured token, or the output of the token command.
+    pub fn token(&self) -> Result<Option<String>, Box<dyn Error>> {
+        if let Some(token) = &self.token {
+            return Ok(Some(token.clone()));
+        }
+        let command = match &self.token_command {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let output = Command::new("sh").arg("-c").arg(command).output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "The token command of profile '{}' failed: {}",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(Some(String::from_utf8(output.stdout)?.trim().to_owned()))
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("FINANCE_TUI_CONFIG") {
+        return Some(path.into());
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("finance").join("tui.toml"))
+}
+
+impl Config {
+    /// Reads the configuration file. Without one, or without profiles in it, the environment
+    /// makes up the only profile.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut config = match path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(text) => toml::from_str(&text)
+                    .map_err(|error| format!("Invalid config {}: {}", path.display(), error))?,
+                Err(error) if error.kind() == ErrorKind::NotFound => Config::default(),
+                Err(error) => return Err(error.into()),
+            },
+            None => Config::default(),
+        };
+        if config.profiles.is_empty() {
+            config.profiles.push(Profile::from_env());
+        }
+        Ok(config)
+    }
+}
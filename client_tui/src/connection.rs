@@ -0,0 +1,49 @@
+use crate::config::Profile;
+use finance_lib::client::{FinanceClient, Result};
+use finance_lib::Login;
+use std::error::Error;
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+
+/// Client of the server of a profile. Requests block the interface until they are answered.
+pub struct Connection {
+    runtime: Runtime,
+    client: FinanceClient,
+    pub profile: Profile,
+}
+
+impl Connection {
+    pub fn open(profile: &Profile) -> std::result::Result<Self, Box<dyn Error>> {
+        let url = profile
+            .url
+            .parse()
+            .map_err(|error| format!("Invalid url of profile '{}': {}", profile.name, error))?;
+        let mut client = FinanceClient::new(url);
+        client.set_token(profile.token()?);
+        Ok(Self {
+            runtime: Builder::new_current_thread().enable_all().build()?,
+            client,
+            profile: profile.clone(),
+        })
+    }
+
+    /// Whether requests carry a token, otherwise the user has to log in first.
+    pub fn has_token(&self) -> bool {
+        self.client.token().is_some()
+    }
+
+    /// Logs in with a password and uses the session token from then on.
+    pub fn login(&mut self, name: &str, password: &str) -> Result<()> {
+        let login = Login {
+            name: name.to_owned(),
+            password: password.to_owned(),
+        };
+        let created = self.run(|client| client.login(&login))?;
+        self.client.set_token(Some(created.secret));
+        Ok(())
+    }
+
+    pub fn run<'a, F: Future>(&'a self, request: impl FnOnce(&'a FinanceClient) -> F) -> F::Output {
+        self.runtime.block_on(request(&self.client))
+    }
+}
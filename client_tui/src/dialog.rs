@@ -0,0 +1,188 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+/// What a key pressed in a dialog did to it.
+pub enum DialogResult {
+    Open,
+    Submitted,
+    Cancelled,
+}
+
+pub struct Field {
+    pub label: &'static str,
+    pub value: String,
+    /// Shown as stars, like passwords.
+    pub secret: bool,
+}
+
+/// Popup form of text fields. Tab and the arrow keys move between the fields, Enter submits the
+/// form and Esc cancels it. Without fields it asks for confirmation of its title.
+pub struct Form {
+    title: String,
+    fields: Vec<Field>,
+    focus: usize,
+    error: Option<String>,
+}
+
+impl Form {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            fields: vec![],
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn field(mut self, label: &'static str, value: impl Into<String>) -> Self {
+        self.fields.push(Field {
+            label,
+            value: value.into(),
+            secret: false,
+        });
+        self
+    }
+
+    pub fn secret(mut self, label: &'static str) -> Self {
+        self.fields.push(Field {
+            label,
+            value: String::new(),
+            secret: true,
+        });
+        self
+    }
+
+    /// Moves the cursor to the field labeled `label`.
+    pub fn focus(mut self, label: &str) -> Self {
+        if let Some(focus) = self.fields.iter().position(|field| field.label == label) {
+            self.focus = focus;
+        }
+        self
+    }
+
+    /// Shows why the form could not be submitted, until the next key.
+    pub fn set_error(&mut self, error: impl Into<String>) {
+        self.error = Some(error.into());
+    }
+
+    /// Value of the field labeled `label`, empty if there is none.
+    pub fn value(&self, label: &str) -> &str {
+        self.fields
+            .iter()
+            .find(|field| field.label == label)
+            .map(|field| match field.secret {
+                true => field.value.as_str(),
+                false => field.value.trim(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// Like [`Form::value`], `None` for an empty field.
+    pub fn optional(&self, label: &str) -> Option<String> {
+        Some(self.value(label))
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> DialogResult {
+        self.error = None;
+        match key.code {
+            KeyCode::Esc => return DialogResult::Cancelled,
+            KeyCode::Enter => return DialogResult::Submitted,
+            KeyCode::Tab | KeyCode::Down if !self.fields.is_empty() => {
+                self.focus = (self.focus + 1) % self.fields.len();
+            }
+            KeyCode::BackTab | KeyCode::Up if !self.fields.is_empty() => {
+                self.focus = (self.focus + self.fields.len() - 1) % self.fields.len();
+            }
+            KeyCode::Backspace => {
+                if let Some(field) = self.fields.get_mut(self.focus) {
+                    field.value.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(field) = self.fields.get_mut(self.focus) {
+                    field.value.push(c);
+                }
+            }
+            _ => {}
+        }
+        DialogResult::Open
+    }
+
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let label_width = self
+            .fields
+            .iter()
+            .map(|field| field.label.len())
+            .max()
+            .unwrap_or_default();
+        let mut lines: Vec<Spans> = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let style = if i == self.focus {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                let value = if field.secret {
+                    "*".repeat(field.value.chars().count())
+                } else {
+                    field.value.clone()
+                };
+                Spans::from(vec![
+                    Span::styled(format!("{:>1$}: ", field.label, label_width), style),
+                    Span::raw(value),
+                ])
+            })
+            .collect();
+        lines.push(Spans::from(""));
+        if let Some(error) = &self.error {
+            lines.push(Spans::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        lines.push(Spans::from(if self.fields.is_empty() {
+            "Enter: yes, Esc: no"
+        } else {
+            "Enter: save, Esc: cancel"
+        }));
+
+        let popup = centered(60, lines.len() as u16 + 2, area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(lines).block(
+            Block::default()
+                .title(self.title.as_str())
+                .borders(Borders::ALL),
+        );
+        f.render_widget(paragraph, popup);
+        if let Some(field) = self.fields.get(self.focus) {
+            f.set_cursor(
+                popup.x + 1 + label_width as u16 + 2 + field.value.chars().count() as u16,
+                popup.y + 1 + self.focus as u16,
+            );
+        }
+    }
+}
+
+/// Rectangle of at most `width` by `height` in the middle of `area`.
+pub fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
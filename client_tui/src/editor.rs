@@ -0,0 +1,548 @@
+use crate::dialog::{centered, DialogResult};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use finance_lib::amount;
+use finance_lib::client::{FinanceClient, Result, Tagged};
+use finance_lib::{
+    NewPosting, NewTransaction, Posting, PostingPatch, Transaction, TransactionPatch,
+};
+use std::collections::{BTreeMap, HashMap};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+const LABEL_WIDTH: usize = 13;
+const ACCOUNT_WIDTH: usize = 32;
+const AMOUNT_WIDTH: usize = 14;
+/// Date and description come before the cells of the postings.
+const HEADER_CELLS: usize = 2;
+const COLUMNS: usize = 3;
+
+#[derive(Default)]
+struct PostingRow {
+    /// The posting as it is stored, `None` for a new one.
+    stored: Option<Tagged<Posting>>,
+    account: String,
+    amount: String,
+    currency: String,
+}
+
+impl PostingRow {
+    fn is_empty(&self) -> bool {
+        // A prefilled currency alone does not make a posting.
+        self.account.is_empty() && self.amount.is_empty()
+    }
+
+    fn cell(&mut self, column: usize) -> &mut String {
+        match column {
+            0 => &mut self.account,
+            1 => &mut self.amount,
+            _ => &mut self.currency,
+        }
+    }
+}
+
+/// Whether the amounts of the postings add up to zero in every currency.
+pub enum Balance {
+    Balanced,
+    /// What is missing per currency, formatted.
+    Unbalanced(Vec<(String, String)>),
+    Invalid(String),
+}
+
+/// Form creating or editing a transaction along with its postings. Account and currency cells
+/// suggest the first matching name, which Right accepts.
+pub struct TransactionEditor {
+    /// The transaction as it is stored, `None` for a new one.
+    stored: Option<Tagged<Transaction>>,
+    date: String,
+    description: String,
+    rows: Vec<PostingRow>,
+    /// Stored postings whose rows were removed.
+    removed: Vec<i64>,
+    focus: usize,
+    accounts: Vec<String>,
+    currencies: HashMap<String, i32>,
+    error: Option<String>,
+}
+
+impl TransactionEditor {
+    pub fn new(accounts: Vec<String>, currencies: HashMap<String, i32>) -> Self {
+        let mut editor = Self {
+            stored: None,
+            date: chrono::Local::now().date_naive().to_string(),
+            description: String::new(),
+            rows: vec![],
+            removed: vec![],
+            focus: 1,
+            accounts,
+            currencies,
+            error: None,
+        };
+        editor.add_row();
+        editor.add_row();
+        editor
+    }
+
+    pub fn edit(
+        transaction: Tagged<Transaction>,
+        postings: Vec<Tagged<Posting>>,
+        accounts: Vec<String>,
+        currencies: HashMap<String, i32>,
+    ) -> Self {
+        let rows = postings
+            .into_iter()
+            .map(|posting| PostingRow {
+                account: posting.account_name.clone(),
+                amount: amount::format(
+                    posting.amount.into(),
+                    currencies
+                        .get(&posting.currency)
+                        .copied()
+                        .unwrap_or_default(),
+                ),
+                currency: posting.currency.clone(),
+                stored: Some(posting),
+            })
+            .collect();
+        Self {
+            date: transaction
+                .time
+                .map(|time| format_time(&time))
+                .unwrap_or_default(),
+            description: transaction.description.clone().unwrap_or_default(),
+            stored: Some(transaction),
+            rows,
+            removed: vec![],
+            focus: 1,
+            accounts,
+            currencies,
+            error: None,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.stored {
+            Some(transaction) => format!("Edit transaction {}", transaction.id),
+            None => "New transaction".to_owned(),
+        }
+    }
+
+    fn cells(&self) -> usize {
+        HEADER_CELLS + self.rows.len() * COLUMNS
+    }
+
+    /// Row and column of the focused cell, if it belongs to a posting.
+    fn focused_posting(&self) -> Option<(usize, usize)> {
+        let cell = self.focus.checked_sub(HEADER_CELLS)?;
+        Some((cell / COLUMNS, cell % COLUMNS))
+    }
+
+    fn focused_text(&mut self) -> &mut String {
+        match self.focused_posting() {
+            Some((row, column)) => self.rows[row].cell(column),
+            None if self.focus == 0 => &mut self.date,
+            None => &mut self.description,
+        }
+    }
+
+    /// Adds a posting, prefilled with what the others are missing if it is a single currency.
+    fn add_row(&mut self) {
+        let mut row = PostingRow::default();
+        if let Balance::Unbalanced(missing) = self.balance() {
+            if let [(currency, amount)] = missing.as_slice() {
+                row.currency = currency.clone();
+                row.amount = amount.clone();
+            }
+        } else if self.currencies.len() == 1 {
+            row.currency = self.currencies.keys().next().cloned().unwrap_or_default();
+        }
+        self.rows.push(row);
+    }
+
+    fn remove_row(&mut self) {
+        if let Some((row, column)) = self.focused_posting() {
+            let removed = self.rows.remove(row);
+            if let Some(posting) = removed.stored {
+                self.removed.push(posting.id);
+            }
+            if self.rows.is_empty() {
+                self.focus = 1;
+            } else {
+                self.focus = HEADER_CELLS + row.min(self.rows.len() - 1) * COLUMNS + column;
+            }
+        }
+    }
+
+    /// First account or currency starting with the text of the focused cell.
+    fn suggestion(&self) -> Option<&str> {
+        let (row, column) = self.focused_posting()?;
+        let row = &self.rows[row];
+        let (text, mut names): (&str, Box<dyn Iterator<Item = &String>>) = match column {
+            0 => (&row.account, Box::new(self.accounts.iter())),
+            2 => (&row.currency, Box::new(self.currencies.keys())),
+            _ => return None,
+        };
+        if text.is_empty() {
+            return None;
+        }
+        let lowercase = text.to_lowercase();
+        names
+            .find(|name| name.to_lowercase().starts_with(&lowercase) && name.as_str() != text)
+            .map(String::as_str)
+    }
+
+    pub fn balance(&self) -> Balance {
+        let mut sums: BTreeMap<&str, i64> = BTreeMap::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            if row.is_empty() {
+                continue;
+            }
+            let decimal_points = match self.currencies.get(&row.currency) {
+                Some(decimal_points) => *decimal_points,
+                None => return Balance::Invalid(format!("Unknown currency in posting {}.", i + 1)),
+            };
+            match amount::parse(&row.amount, decimal_points) {
+                Some(amount) => *sums.entry(&row.currency).or_default() += amount,
+                None => return Balance::Invalid(format!("Invalid amount in posting {}.", i + 1)),
+            }
+        }
+        let missing: Vec<(String, String)> = sums
+            .into_iter()
+            .filter(|(_, sum)| *sum != 0)
+            .map(|(currency, sum)| {
+                let decimal_points = self.currencies[currency];
+                (currency.to_owned(), amount::format(-sum, decimal_points))
+            })
+            .collect();
+        if missing.is_empty() {
+            Balance::Balanced
+        } else {
+            Balance::Unbalanced(missing)
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> DialogResult {
+        self.error = None;
+        let cells = self.cells();
+        match key.code {
+            KeyCode::Esc => return DialogResult::Cancelled,
+            KeyCode::Enter => return DialogResult::Submitted,
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.remove_row()
+            }
+            KeyCode::Tab => {
+                let last_is_empty = self.rows.last().is_some_and(PostingRow::is_empty);
+                if self.focus + 1 == cells && !last_is_empty {
+                    self.add_row();
+                }
+                self.focus = (self.focus + 1) % self.cells();
+            }
+            KeyCode::BackTab => self.focus = (self.focus + cells - 1) % cells,
+            KeyCode::Down => {
+                self.focus = match self.focused_posting() {
+                    Some(_) if self.focus + COLUMNS < cells => self.focus + COLUMNS,
+                    Some(_) => self.focus,
+                    None if self.focus == 0 => 1,
+                    None => HEADER_CELLS.min(cells - 1),
+                }
+            }
+            KeyCode::Up => {
+                self.focus = match self.focused_posting() {
+                    Some((0, _)) => 1,
+                    Some(_) => self.focus - COLUMNS,
+                    None => 0,
+                }
+            }
+            KeyCode::Right => {
+                if let Some(suggestion) = self.suggestion().map(str::to_owned) {
+                    *self.focused_text() = suggestion;
+                }
+            }
+            KeyCode::Backspace => {
+                self.focused_text().pop();
+            }
+            KeyCode::Char(c) => self.focused_text().push(c),
+            _ => {}
+        }
+        DialogResult::Open
+    }
+
+    /// Checks the form, on failure it stays open showing why.
+    pub fn draft(&mut self) -> Option<Draft> {
+        let result = self.to_draft();
+        if let Err(error) = &result {
+            self.error = Some(error.clone());
+        }
+        result.ok()
+    }
+
+    fn to_draft(&self) -> std::result::Result<Draft, String> {
+        let time = match self.date.trim() {
+            "" => None,
+            date => {
+                Some(parse_time(date).ok_or("Dates look like 2023-06-01 or 2023-06-01 12:00.")?)
+            }
+        };
+        if let Balance::Invalid(error) = self.balance() {
+            return Err(error);
+        }
+        let mut postings = vec![];
+        for (i, row) in self.rows.iter().enumerate() {
+            if row.is_empty() {
+                continue;
+            }
+            if !self.accounts.contains(&row.account) {
+                return Err(format!("Unknown account in posting {}.", i + 1));
+            }
+            let decimal_points = self.currencies[&row.currency];
+            let amount = amount::parse(&row.amount, decimal_points)
+                .and_then(|amount| i32::try_from(amount).ok())
+                .ok_or(format!("Invalid amount in posting {}.", i + 1))?;
+            postings.push(DraftPosting {
+                stored: row
+                    .stored
+                    .as_ref()
+                    .map(|posting| (posting.id, posting.etag.clone())),
+                unchanged: row.stored.as_ref().is_some_and(|posting| {
+                    posting.account_name == row.account
+                        && posting.currency == row.currency
+                        && posting.amount == amount
+                }),
+                posting: NewPosting {
+                    valuta: row.stored.as_ref().and_then(|posting| posting.valuta),
+                    account_name: row.account.clone(),
+                    currency: row.currency.clone(),
+                    amount,
+                    budget: row
+                        .stored
+                        .as_ref()
+                        .and_then(|posting| posting.budget)
+                        .unwrap_or_default(),
+                },
+            });
+        }
+        Ok(Draft {
+            stored: self
+                .stored
+                .as_ref()
+                .map(|transaction| (transaction.id, transaction.etag.clone())),
+            time,
+            description: Some(self.description.trim().to_owned()).filter(|d| !d.is_empty()),
+            postings,
+            removed: self.removed.clone(),
+        })
+    }
+
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let label = |text: &str, cell: usize| {
+            let style = if self.focus == cell {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Span::styled(format!("{:>1$}: ", text, LABEL_WIDTH), style)
+        };
+        let mut lines = vec![
+            Spans::from(vec![label("Date", 0), Span::raw(self.date.clone())]),
+            Spans::from(vec![
+                label("Description", 1),
+                Span::raw(self.description.clone()),
+            ]),
+            Spans::from(""),
+            Spans::from(Span::styled(
+                format!(
+                    "{:<a$} {:>m$} Currency",
+                    "Account",
+                    "Amount",
+                    a = ACCOUNT_WIDTH,
+                    m = AMOUNT_WIDTH
+                ),
+                Style::default().add_modifier(Modifier::UNDERLINED),
+            )),
+        ];
+        let suggestion = self.suggestion();
+        for (i, row) in self.rows.iter().enumerate() {
+            let cells = [
+                (row.account.clone(), ACCOUNT_WIDTH, 0),
+                (format!("{:>1$}", row.amount, AMOUNT_WIDTH), AMOUNT_WIDTH, 1),
+                (row.currency.clone(), 0, 2),
+            ];
+            let mut spans = vec![];
+            for (text, width, column) in cells {
+                let focused = self.focused_posting() == Some((i, column));
+                let mut used = text.chars().count();
+                spans.push(Span::raw(text.clone()));
+                if let (true, Some(suggestion)) = (focused, suggestion) {
+                    let rest: String = suggestion.chars().skip(used).collect();
+                    used += rest.chars().count();
+                    spans.push(Span::styled(rest, Style::default().fg(Color::DarkGray)));
+                }
+                spans.push(Span::raw(" ".repeat(width.saturating_sub(used) + 1)));
+            }
+            lines.push(Spans::from(spans));
+        }
+        lines.push(Spans::from(""));
+        lines.push(Spans::from(match &self.error {
+            Some(error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+            None => balance_span(&self.balance()),
+        }));
+        lines.push(Spans::from(
+            "Tab: next field, Right: complete, Ctrl+D: remove posting, Enter: save, Esc: cancel",
+        ));
+
+        let popup = centered(90, lines.len() as u16 + 2, area);
+        f.render_widget(Clear, popup);
+        let paragraph =
+            Paragraph::new(lines).block(Block::default().title(self.title()).borders(Borders::ALL));
+        f.render_widget(paragraph, popup);
+
+        let (x, y) = match self.focused_posting() {
+            Some((row, column)) => {
+                let text = &self.rows[row];
+                let x = match column {
+                    0 => text.account.chars().count(),
+                    1 => ACCOUNT_WIDTH + 1 + AMOUNT_WIDTH,
+                    _ => ACCOUNT_WIDTH + AMOUNT_WIDTH + 2 + text.currency.chars().count(),
+                };
+                (x, 4 + row)
+            }
+            None if self.focus == 0 => (LABEL_WIDTH + 2 + self.date.chars().count(), 0),
+            None => (LABEL_WIDTH + 2 + self.description.chars().count(), 1),
+        };
+        f.set_cursor(popup.x + 1 + x as u16, popup.y + 1 + y as u16);
+    }
+}
+
+pub fn balance_span(balance: &Balance) -> Span<'static> {
+    match balance {
+        Balance::Balanced => Span::styled("Balanced", Style::default().fg(Color::Green)),
+        Balance::Unbalanced(missing) => {
+            let missing: Vec<String> = missing
+                .iter()
+                .map(|(currency, amount)| format!("{} {}", amount, currency))
+                .collect();
+            Span::styled(
+                format!("Unbalanced, missing {}", missing.join(", ")),
+                Style::default().fg(Color::Yellow),
+            )
+        }
+        Balance::Invalid(error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+    }
+}
+
+pub fn format_time(time: &NaiveDateTime) -> String {
+    if time.time() == NaiveTime::MIN {
+        time.date().to_string()
+    } else {
+        time.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+fn parse_time(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+struct DraftPosting {
+    /// Id and tag of the posting as it is stored.
+    stored: Option<(i64, Option<String>)>,
+    unchanged: bool,
+    posting: NewPosting,
+}
+
+/// A checked transaction of the editor, ready to be saved.
+pub struct Draft {
+    stored: Option<(i64, Option<String>)>,
+    time: Option<NaiveDateTime>,
+    description: Option<String>,
+    postings: Vec<DraftPosting>,
+    removed: Vec<i64>,
+}
+
+impl Draft {
+    /// The transaction and its postings if the draft creates one, otherwise the draft back.
+    pub fn into_new(self) -> std::result::Result<(NewTransaction, Vec<NewPosting>), Self> {
+        if self.stored.is_some() {
+            return Err(self);
+        }
+        let transaction = NewTransaction {
+            description: self.description,
+            time: self.time,
+            posted: None,
+        };
+        let postings = self
+            .postings
+            .into_iter()
+            .map(|draft| draft.posting)
+            .collect();
+        Ok((transaction, postings))
+    }
+
+    /// Creates or updates the transaction and its postings, returns the id of the transaction.
+    pub async fn save(self, client: &FinanceClient, book_name: &str) -> Result<i64> {
+        let transaction_id = match self.stored {
+            Some((transaction_id, etag)) => {
+                let patch = TransactionPatch {
+                    time: self.time,
+                    description: Some(self.description),
+                };
+                client
+                    .patch_transaction(book_name, transaction_id, &patch, etag.as_deref())
+                    .await?;
+                transaction_id
+            }
+            None => {
+                let transaction = NewTransaction {
+                    description: self.description,
+                    time: self.time,
+                    posted: None,
+                };
+                client.create_transaction(book_name, &transaction).await?
+            }
+        };
+        for posting_id in self.removed {
+            client
+                .delete_posting(book_name, transaction_id, posting_id)
+                .await?;
+        }
+        for draft in self.postings {
+            match draft.stored {
+                Some(_) if draft.unchanged => {}
+                Some((posting_id, etag)) => {
+                    let patch = PostingPatch {
+                        account_name: Some(draft.posting.account_name),
+                        currency: Some(draft.posting.currency),
+                        amount: Some(draft.posting.amount),
+                        ..Default::default()
+                    };
+                    client
+                        .patch_posting(
+                            book_name,
+                            transaction_id,
+                            posting_id,
+                            &patch,
+                            etag.as_deref(),
+                        )
+                        .await?;
+                }
+                None => {
+                    client
+                        .create_posting(book_name, transaction_id, &draft.posting)
+                        .await?;
+                }
+            }
+        }
+        Ok(transaction_id)
+    }
+}
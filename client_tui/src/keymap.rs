@@ -0,0 +1,265 @@
+//! Key bindings of the TUI. The `[keys]` table of the configuration file replaces the keys of
+//! actions, which also takes these keys away from the other actions:
+//!
+//! ```toml
+//! [keys]
+//! quit = ["q", "ctrl+c"]
+//! new = ["a"]
+//! ```
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// What a key does. Views give the actions they handle their own meaning, like [`Action::New`]
+/// creating a book in the books view and a transaction in the transactions view.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Help,
+    Command,
+    Books,
+    Accounts,
+    Currencies,
+    Transactions,
+    Reports,
+    Sync,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Collapse,
+    Expand,
+    Open,
+    Back,
+    New,
+    Edit,
+    Delete,
+    Post,
+    Reload,
+}
+
+impl Action {
+    /// Actions handled the same way in every view.
+    pub const GLOBAL: [(Action, &'static str); 9] = [
+        (Action::Quit, "quit"),
+        (Action::Help, "show this help"),
+        (Action::Command, "open the command palette"),
+        (Action::Books, "show the books"),
+        (Action::Accounts, "show the accounts"),
+        (Action::Currencies, "show the currencies"),
+        (Action::Transactions, "show the transactions"),
+        (Action::Reports, "show the reports"),
+        (
+            Action::Sync,
+            "send the queued transactions and refresh the local copy",
+        ),
+    ];
+}
+
+/// A key along with its modifiers, written like `ctrl+d`, `G`, `F1` or `pagedown`.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(try_from = "String")]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    fn ctrl(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::CONTROL,
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        // Shift is part of characters already, as in `G`, but not of other keys.
+        let (code, shift) = match event.code {
+            KeyCode::BackTab => (KeyCode::Tab, KeyModifiers::SHIFT),
+            KeyCode::Char(c) => (KeyCode::Char(c), KeyModifiers::NONE),
+            code => (code, event.modifiers & KeyModifiers::SHIFT),
+        };
+        let modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT) | shift;
+        Self { code, modifiers }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 14] = [
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("backspace", KeyCode::Backspace),
+    ("tab", KeyCode::Tab),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("delete", KeyCode::Delete),
+];
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text.as_str();
+        // A lone `+` is a key, `ctrl++` is that key with control.
+        while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier '{}' in key '{}'", modifier, text)),
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let name = rest.to_lowercase();
+                let function = name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                    .map(KeyCode::F);
+                NAMED_KEYS
+                    .iter()
+                    .find(|(key_name, _)| *key_name == name)
+                    .map(|(_, code)| *code)
+                    .or(function)
+                    .ok_or_else(|| format!("Unknown key '{}'", text))?
+            }
+        };
+        let mut key = Self { code, modifiers };
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                key.code = KeyCode::Char(c.to_ascii_uppercase());
+                key.modifiers.remove(KeyModifiers::SHIFT);
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(number) => write!(f, "F{}", number),
+            code => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, named)| *named == code)
+                    .map(|(name, _)| *name)
+                    .unwrap_or("?");
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// The keys of every action.
+pub struct Keymap {
+    bindings: BTreeMap<Action, Vec<Key>>,
+}
+
+impl Keymap {
+    /// The default bindings, which include vim-like navigation, with `configured` replacing
+    /// them.
+    pub fn new(configured: &HashMap<Action, Vec<Key>>) -> Self {
+        let mut bindings = Self::defaults();
+        for keys in bindings.values_mut() {
+            keys.retain(|key| !configured.values().flatten().any(|taken| taken == key));
+        }
+        for (action, keys) in configured {
+            bindings.insert(*action, keys.clone());
+        }
+        Self { bindings }
+    }
+
+    fn defaults() -> BTreeMap<Action, Vec<Key>> {
+        use Action::*;
+        let key = Key::new;
+        let char = |c| Key::new(KeyCode::Char(c));
+        BTreeMap::from([
+            (Quit, vec![char('q')]),
+            (Help, vec![char('?')]),
+            (Command, vec![char(':')]),
+            (Books, vec![key(KeyCode::F(1))]),
+            (Accounts, vec![key(KeyCode::F(2))]),
+            (Currencies, vec![key(KeyCode::F(3))]),
+            (Transactions, vec![key(KeyCode::F(4))]),
+            (Reports, vec![key(KeyCode::F(5))]),
+            (Sync, vec![char('s')]),
+            (Up, vec![key(KeyCode::Up), char('k')]),
+            (Down, vec![key(KeyCode::Down), char('j')]),
+            (
+                PageUp,
+                vec![key(KeyCode::PageUp), Key::ctrl('b'), Key::ctrl('u')],
+            ),
+            (
+                PageDown,
+                vec![key(KeyCode::PageDown), Key::ctrl('f'), Key::ctrl('d')],
+            ),
+            (Top, vec![key(KeyCode::Home), char('g')]),
+            (Bottom, vec![key(KeyCode::End), char('G')]),
+            (Collapse, vec![key(KeyCode::Left), char('h')]),
+            (Expand, vec![key(KeyCode::Right), char('l')]),
+            (Open, vec![key(KeyCode::Enter)]),
+            (Back, vec![key(KeyCode::Esc), key(KeyCode::Backspace)]),
+            (New, vec![char('n')]),
+            (Edit, vec![char('e')]),
+            (Delete, vec![char('d')]),
+            (Post, vec![char('p')]),
+            (Reload, vec![char('r')]),
+        ])
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        let key = Key::from(key);
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// The keys of `action` for showing them, like `Up, k`.
+    pub fn keys(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .bindings
+            .get(&action)
+            .into_iter()
+            .flatten()
+            .map(Key::to_string)
+            .collect();
+        keys.join(", ")
+    }
+}
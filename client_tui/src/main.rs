@@ -1,21 +1,38 @@
+use config::Config;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    cursor,
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use dialog::{centered, DialogResult};
+use keymap::{Action, Keymap};
 use num_enum::IntoPrimitive;
+use palette::{Command, Palette};
+use reports::ReportsView;
 use std::error::Error;
 use std::io;
+use std::panic;
+use store::Store;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
-    style::{Modifier, Style},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, Tabs},
+    widgets::{Block, Borders, Clear, Paragraph, Tabs},
     Frame, Terminal,
 };
-use views::{AccountsView, BooksView, CurrenciesView, PostingsView, TransactionsView, View};
+use views::{AccountsView, BooksView, Context, CurrenciesView, Status, TransactionsView, View};
 
+mod config;
+mod connection;
+mod dialog;
+mod editor;
+mod keymap;
+mod palette;
+mod reports;
+mod startup;
+mod store;
 mod views;
 
 #[derive(IntoPrimitive, Clone)]
@@ -25,7 +42,7 @@ enum AppView {
     Accounts,
     Currencies,
     Transactions,
-    Postings,
+    Reports,
 }
 
 impl AppView {
@@ -34,8 +51,8 @@ impl AppView {
             Self::Books => "Books",
             Self::Currencies => "Currencies",
             Self::Transactions => "Transactions",
-            Self::Postings => "Postings",
             Self::Accounts => "Accounts",
+            Self::Reports => "Reports",
         }
         .to_owned()
     }
@@ -43,23 +60,32 @@ impl AppView {
 
 struct App {
     pub current_view: AppView,
+    context: Context,
+    keymap: Keymap,
+    /// Whether the help overlay is shown.
+    help: bool,
+    palette: Option<Palette>,
 
     books_view: BooksView,
     accounts_view: AccountsView,
     currencies_view: CurrenciesView,
     transactions_view: TransactionsView,
-    postings_view: PostingsView,
+    reports_view: ReportsView,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(context: Context, keymap: Keymap) -> Self {
         Self {
             current_view: AppView::Books,
+            context,
+            keymap,
+            help: false,
+            palette: None,
             books_view: BooksView::new(),
             accounts_view: AccountsView::new(),
             currencies_view: CurrenciesView::new(),
             transactions_view: TransactionsView::new(),
-            postings_view: PostingsView::new(),
+            reports_view: ReportsView::new(),
         }
     }
 
@@ -68,7 +94,14 @@ impl App {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(1)
-            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Min(0),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
             .split(size);
         let block = Block::default();
         f.render_widget(block, size);
@@ -77,29 +110,283 @@ impl App {
             AppView::Accounts,
             AppView::Currencies,
             AppView::Transactions,
-            AppView::Postings,
+            AppView::Reports,
         ]
         .iter()
         .map(|t| Spans::from(Span::styled((*t).get_as_string(), Style::default())))
         .collect();
+        let title = match &self.context.book {
+            Some(book) => format!("Finance - {}", book),
+            None => "Finance".to_owned(),
+        };
+        let title = format!("{} ({}: help)", title, self.keymap.keys(Action::Help));
         let tabs = Tabs::new(tab_titles)
-            .block(Block::default().borders(Borders::ALL).title("Finance"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .style(Style::default())
             .highlight_style(Style::default().add_modifier(Modifier::BOLD))
             .select(self.current_view.clone().into());
         f.render_widget(tabs, chunks[0]);
 
+        let context = &self.context;
         match self.current_view {
-            AppView::Books => self.books_view.draw(f, chunks[1]),
-            AppView::Accounts => self.accounts_view.draw(f, chunks[1]),
-            AppView::Currencies => self.currencies_view.draw(f, chunks[1]),
-            AppView::Transactions => self.transactions_view.draw(f, chunks[1]),
-            AppView::Postings => self.postings_view.draw(f, chunks[1]),
+            AppView::Books => self.books_view.draw(f, chunks[1], context),
+            AppView::Accounts => self.accounts_view.draw(f, chunks[1], context),
+            AppView::Currencies => self.currencies_view.draw(f, chunks[1], context),
+            AppView::Transactions => self.transactions_view.draw(f, chunks[1], context),
+            AppView::Reports => self.reports_view.draw(f, chunks[1], context),
+        };
+
+        f.render_widget(Paragraph::new(self.status_bar()), chunks[2]);
+
+        if let Some(palette) = &self.palette {
+            palette.draw(f, chunks[1]);
+        }
+        if self.help {
+            self.draw_help(f, chunks[1]);
+        }
+    }
+
+    /// Lists the keys of the actions of the current view, then those of all views.
+    fn draw_help<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let mut help = match self.current_view {
+            AppView::Books => self.books_view.help(),
+            AppView::Accounts => self.accounts_view.help(),
+            AppView::Currencies => self.currencies_view.help(),
+            AppView::Transactions => self.transactions_view.help(),
+            AppView::Reports => self.reports_view.help(),
         };
+        help.extend(Action::GLOBAL);
+        let keys: Vec<String> = help
+            .iter()
+            .map(|(action, _)| self.keymap.keys(*action))
+            .collect();
+        let width = keys.iter().map(String::len).max().unwrap_or_default();
+        let lines: Vec<Spans> = keys
+            .iter()
+            .zip(&help)
+            .map(|(keys, (_, description))| {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{:>1$}  ", keys, width),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(*description),
+                ])
+            })
+            .collect();
+        let popup = centered(70, lines.len() as u16 + 2, area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(lines).block(
+            Block::default()
+                .title(format!("Keys of {}", self.current_view.get_as_string()))
+                .borders(Borders::ALL),
+        );
+        f.render_widget(paragraph, popup);
+    }
+
+    /// Profile and book in use, followed by the last notice or error.
+    fn status_bar(&self) -> Spans<'static> {
+        let mut spans = vec![
+            Span::styled(
+                format!(" {} ", self.context.connection.profile.name),
+                Style::default().add_modifier(Modifier::REVERSED),
+            ),
+            Span::raw(" "),
+            Span::raw(self.context.book.clone().unwrap_or("no book".to_owned())),
+            Span::raw("  "),
+        ];
+        if self.context.offline {
+            spans.push(Span::styled(
+                " offline ",
+                Style::default().fg(Color::Black).bg(Color::Yellow),
+            ));
+            spans.push(Span::raw("  "));
+        }
+        match self.context.store.queue.len() {
+            0 => {}
+            queued => spans.push(Span::raw(format!("{} queued  ", queued))),
+        }
+        match &self.context.status {
+            Some(Status::Notice(notice)) => spans.push(Span::raw(notice.clone())),
+            Some(Status::Error(error)) => {
+                spans.push(Span::styled(error.clone(), Style::default().fg(Color::Red)))
+            }
+            None => {}
+        }
+        Spans::from(spans)
+    }
+
+    /// Greets the user and sends what was queued, or shows why the server cannot be used.
+    fn check_connection(&mut self) {
+        let hello = self.context.connection.run(|client| client.hello());
+        if let Some(hello) = self.context.report(hello) {
+            let url = &self.context.connection.profile.url;
+            self.context
+                .notify(format!("{} Connected to {}.", hello.trim(), url));
+            if !self.context.store.queue.is_empty() {
+                self.context.sync();
+            }
+        }
+    }
+
+    /// Makes the views load again, like after going offline or coming back online.
+    fn reload_views(&mut self) {
+        self.books_view = BooksView::new();
+        self.accounts_view = AccountsView::new();
+        self.currencies_view = CurrenciesView::new();
+        self.transactions_view = TransactionsView::new();
+        self.reports_view = ReportsView::new();
+        self.show(self.current_view.clone());
+    }
+
+    fn show(&mut self, view: AppView) {
+        self.current_view = view;
+        let context = &mut self.context;
+        match self.current_view {
+            AppView::Books => self.books_view.activate(context),
+            AppView::Accounts => self.accounts_view.activate(context),
+            AppView::Currencies => self.currencies_view.activate(context),
+            AppView::Transactions => self.transactions_view.activate(context),
+            AppView::Reports => self.reports_view.activate(context),
+        }
+    }
+
+    /// Returns whether the app goes on, rather than quitting.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let offline = self.context.offline;
+        let goes_on = self.dispatch_key(key);
+        if self.context.offline != offline {
+            self.reload_views();
+        }
+        goes_on
+    }
+
+    fn dispatch_key(&mut self, key: KeyEvent) -> bool {
+        if self.help {
+            self.help = false;
+            return true;
+        }
+        if let Some(mut palette) = self.palette.take() {
+            match palette.handle_key(key) {
+                DialogResult::Open => self.palette = Some(palette),
+                DialogResult::Submitted => match palette.command() {
+                    Some(command) => return self.run_command(command),
+                    None => {
+                        self.context.status = Some(Status::Error("Unknown command.".to_owned()))
+                    }
+                },
+                DialogResult::Cancelled => {}
+            }
+            return true;
+        }
+
+        let context = &mut self.context;
+        let used = match self.current_view {
+            AppView::Books => self.books_view.handle_key(key, context),
+            AppView::Accounts => self.accounts_view.handle_key(key, context),
+            AppView::Currencies => self.currencies_view.handle_key(key, context),
+            AppView::Transactions => self.transactions_view.handle_key(key, context),
+            AppView::Reports => self.reports_view.handle_key(key, context),
+        };
+        match self.keymap.action(key) {
+            Some(action) if !used => self.run_action(action),
+            _ => true,
+        }
+    }
+
+    /// Returns whether the app goes on, rather than quitting.
+    fn run_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Quit => return false,
+            Action::Help => self.help = true,
+            Action::Command => self.open_palette(),
+            Action::Books => self.show(AppView::Books),
+            Action::Accounts => self.show(AppView::Accounts),
+            Action::Currencies => self.show(AppView::Currencies),
+            Action::Transactions => self.show(AppView::Transactions),
+            Action::Reports => self.show(AppView::Reports),
+            Action::Sync => {
+                self.context.sync();
+                self.reload_views();
+            }
+            action => {
+                let context = &mut self.context;
+                match self.current_view {
+                    AppView::Books => self.books_view.handle_action(action, context),
+                    AppView::Accounts => self.accounts_view.handle_action(action, context),
+                    AppView::Currencies => self.currencies_view.handle_action(action, context),
+                    AppView::Transactions => self.transactions_view.handle_action(action, context),
+                    AppView::Reports => self.reports_view.handle_action(action, context),
+                };
+            }
+        }
+        true
+    }
+
+    /// Opens the command palette, suggesting the books and the accounts of the active book.
+    fn open_palette(&mut self) {
+        if self.context.offline {
+            let store = &self.context.store;
+            let books = store
+                .books()
+                .iter()
+                .map(|snapshot| snapshot.book.name.clone())
+                .collect();
+            let accounts = match &self.context.book {
+                Some(book_name) => store
+                    .snapshot(book_name)
+                    .map(|snapshot| snapshot.accounts.clone())
+                    .unwrap_or_default(),
+                None => vec![],
+            };
+            self.palette = Some(Palette::new(books, accounts));
+            return;
+        }
+        let books = self.context.connection.run(|client| client.get_books());
+        let books = self.context.report(books).unwrap_or_default();
+        let accounts = match self.context.book.clone() {
+            Some(book_name) => {
+                let accounts = self
+                    .context
+                    .connection
+                    .run(|client| client.get_accounts(&book_name));
+                self.context.report(accounts).unwrap_or_default()
+            }
+            None => vec![],
+        };
+        self.palette = Some(Palette::new(books, accounts));
+    }
+
+    /// Returns whether the app goes on, rather than quitting.
+    fn run_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Run(action) => return self.run_action(action),
+            Command::NewTransaction => {
+                self.show(AppView::Transactions);
+                if !self
+                    .transactions_view
+                    .handle_action(Action::New, &mut self.context)
+                {
+                    self.context.status = Some(Status::Error("Open a book first.".to_owned()));
+                }
+            }
+            Command::SwitchBook(book_name) => {
+                self.context.open_book(&book_name);
+                self.show(self.current_view.clone());
+            }
+            Command::GoToAccount(account_name) => {
+                self.show(AppView::Accounts);
+                self.accounts_view
+                    .open_account(&account_name, &mut self.context);
+            }
+        }
+        true
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    restore_terminal_on_panic();
     enable_raw_mode()?;
 
     let mut stdout = io::stdout();
@@ -107,40 +394,56 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    run_app(&mut terminal, App::new())?;
+    let result = run(&mut terminal, &config);
 
+    restore_terminal()?;
+    result
+}
+
+fn restore_terminal() -> io::Result<()> {
     disable_raw_mode()?;
     execute!(
-        terminal.backend_mut(),
+        io::stdout(),
         LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-    Ok(())
+        DisableMouseCapture,
+        cursor::Show
+    )
+}
+
+/// Restores the terminal before the panic is printed, which raw mode would garble.
+fn restore_terminal_on_panic() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        hook(info);
+    }));
+}
+
+fn run<B: Backend>(terminal: &mut Terminal<B>, config: &Config) -> Result<(), Box<dyn Error>> {
+    let keymap = Keymap::new(&config.keys);
+    let connection = match startup::connect(terminal, config)? {
+        Some(connection) => connection,
+        None => return Ok(()),
+    };
+    let store = Store::load(&connection.profile.name)?;
+    let context = Context {
+        connection,
+        book: None,
+        status: None,
+        store,
+        offline: false,
+    };
+    run_app(terminal, App::new(context, keymap))
 }
 
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), Box<dyn Error>> {
+    app.check_connection();
+    app.show(AppView::Books);
     loop {
         terminal.draw(|f| App::draw(&mut app, f))?;
         if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('q') => return Ok(()),
-                KeyCode::F(1) => {
-                    app.current_view = AppView::Books;
-                }
-                KeyCode::F(2) => {
-                    app.current_view = AppView::Accounts;
-                }
-                KeyCode::F(3) => {
-                    app.current_view = AppView::Currencies;
-                }
-                KeyCode::F(4) => {
-                    app.current_view = AppView::Transactions;
-                }
-                KeyCode::F(5) => {
-                    app.current_view = AppView::Postings;
-                }
-                _ => {}
+            if !app.handle_key(key) {
+                return Ok(());
             }
         }
     }
@@ -0,0 +1,172 @@
+use crate::dialog::DialogResult;
+use crate::keymap::Action;
+use crossterm::event::{KeyCode, KeyEvent};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+/// Most suggestions shown at once.
+const SUGGESTIONS: usize = 8;
+
+#[derive(Clone)]
+pub enum Command {
+    Run(Action),
+    NewTransaction,
+    SwitchBook(String),
+    GoToAccount(String),
+}
+
+/// Commands running an action, by name.
+const ACTIONS: [(&str, Action); 9] = [
+    ("books", Action::Books),
+    ("accounts", Action::Accounts),
+    ("currencies", Action::Currencies),
+    ("transactions", Action::Transactions),
+    ("reports", Action::Reports),
+    ("sync", Action::Sync),
+    ("reload", Action::Reload),
+    ("help", Action::Help),
+    ("quit", Action::Quit),
+];
+
+impl Command {
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some(book_name) = text.strip_prefix("switch book ") {
+            return Some(Self::SwitchBook(book_name.trim().to_owned()));
+        }
+        if let Some(account_name) = text.strip_prefix("go to account ") {
+            return Some(Self::GoToAccount(account_name.trim().to_owned()));
+        }
+        if text == "new transaction" {
+            return Some(Self::NewTransaction);
+        }
+        ACTIONS
+            .iter()
+            .find(|(name, _)| *name == text)
+            .map(|(_, action)| Self::Run(*action))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Run(action) => ACTIONS
+                .iter()
+                .find(|(_, named)| named == action)
+                .map(|(name, _)| (*name).to_owned())
+                .unwrap_or_default(),
+            Self::NewTransaction => "new transaction".to_owned(),
+            Self::SwitchBook(book_name) => format!("switch book {}", book_name),
+            Self::GoToAccount(account_name) => format!("go to account {}", account_name),
+        }
+    }
+}
+
+/// Line for typing a command, with the known commands containing all typed words as
+/// suggestions. Up and Down choose a suggestion, Tab completes it and Enter runs it.
+pub struct Palette {
+    input: String,
+    commands: Vec<Command>,
+    state: ListState,
+}
+
+impl Palette {
+    /// Suggests switching to `books` and going to `accounts` along with the fixed commands.
+    pub fn new(books: Vec<String>, accounts: Vec<String>) -> Self {
+        let mut commands = vec![Command::NewTransaction];
+        commands.extend(books.into_iter().map(Command::SwitchBook));
+        commands.extend(accounts.into_iter().map(Command::GoToAccount));
+        commands.extend(ACTIONS.iter().map(|(_, action)| Command::Run(*action)));
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            input: String::new(),
+            commands,
+            state,
+        }
+    }
+
+    fn suggestions(&self) -> Vec<&Command> {
+        let input = self.input.to_lowercase();
+        self.commands
+            .iter()
+            .filter(|command| {
+                let name = command.name().to_lowercase();
+                input.split_whitespace().all(|word| name.contains(word))
+            })
+            .collect()
+    }
+
+    /// The chosen suggestion, otherwise the typed command if it is one.
+    pub fn command(&self) -> Option<Command> {
+        let suggestions = self.suggestions();
+        match self.state.selected().and_then(|i| suggestions.get(i)) {
+            Some(command) => Some((*command).clone()),
+            None => Command::parse(&self.input),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> DialogResult {
+        let len = self.suggestions().len();
+        let selected = self.state.selected().unwrap_or_default();
+        match key.code {
+            KeyCode::Esc => return DialogResult::Cancelled,
+            KeyCode::Enter => return DialogResult::Submitted,
+            KeyCode::Up => self.state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self
+                .state
+                .select(Some((selected + 1).min(len.saturating_sub(1)))),
+            KeyCode::Tab => {
+                if let Some(command) = self.command() {
+                    self.input = command.name();
+                }
+            }
+            KeyCode::Backspace => {
+                if self.input.pop().is_none() {
+                    return DialogResult::Cancelled;
+                }
+                self.state.select(Some(0));
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.state.select(Some(0));
+            }
+            _ => {}
+        }
+        DialogResult::Open
+    }
+
+    /// Draws the palette at the bottom of `area`.
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let suggestions = self.suggestions();
+        let shown = suggestions.len().min(SUGGESTIONS) as u16;
+        let height = (shown + 3).min(area.height);
+        let popup = Rect::new(area.x, area.y + area.height - height, area.width, height);
+        f.render_widget(Clear, popup);
+        let block = Block::default().title("Command").borders(Borders::ALL);
+        let inner = block.inner(popup);
+        f.render_widget(block, popup);
+        if inner.height == 0 {
+            return;
+        }
+
+        let input = Paragraph::new(Spans::from(vec![
+            Span::raw(":"),
+            Span::raw(self.input.as_str()),
+        ]));
+        f.render_widget(input, Rect::new(inner.x, inner.y, inner.width, 1));
+        let items: Vec<ListItem> = suggestions
+            .iter()
+            .map(|command| ListItem::new(command.name()))
+            .collect();
+        let list =
+            List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let list_area = Rect::new(inner.x, inner.y + 1, inner.width, inner.height - 1);
+        f.render_stateful_widget(list, list_area, &mut self.state.clone());
+        f.set_cursor(inner.x + 1 + self.input.chars().count() as u16, inner.y);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::keymap::Action;
+use crate::views::{load_currencies, Context, View};
+use chrono::{Datelike, Months, NaiveDateTime, NaiveTime, Utc};
+use finance_lib::{BalancePoint, FlowAmount, Interval, ReportQuery};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols,
+    text::Span,
+    widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, List, ListItem, ListState},
+    Frame,
+};
+
+/// Months the charts cover, up to the chosen month.
+const MONTHS: u32 = 12;
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Green,
+    Color::Red,
+    Color::Blue,
+];
+
+/// Charts of a top-level account of the active book: its balance over time, what was posted to
+/// it each month and, for the chosen month, to each account below it.
+pub struct ReportsView {
+    book: Option<String>,
+    accounts: Vec<String>,
+    currencies: HashMap<String, i32>,
+    state: ListState,
+    /// Start of the chosen month.
+    month: NaiveDateTime,
+    balances: Vec<BalancePoint>,
+    /// Sums of the accounts one level below the chosen one.
+    flows: Vec<FlowAmount>,
+}
+
+/// Start of the month containing `time`.
+fn month_of(time: NaiveDateTime) -> NaiveDateTime {
+    let date = time.date();
+    date.with_day(1).unwrap_or(date).and_time(NaiveTime::MIN)
+}
+
+impl ReportsView {
+    pub fn new() -> Self {
+        Self {
+            book: None,
+            accounts: vec![],
+            currencies: HashMap::new(),
+            state: ListState::default(),
+            month: month_of(Utc::now().naive_utc()),
+            balances: vec![],
+            flows: vec![],
+        }
+    }
+
+    fn load(&mut self, context: &mut Context) {
+        self.book = context.book.clone();
+        self.accounts.clear();
+        let book_name = match &self.book {
+            Some(book_name) if !context.offline => book_name.clone(),
+            _ => return,
+        };
+        self.currencies = load_currencies(context, &book_name).unwrap_or_default();
+        let accounts = context
+            .connection
+            .run(|client| client.get_accounts(&book_name));
+        let top_level: BTreeSet<String> = context
+            .report(accounts)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|account_name| account_name.split(':').next())
+            .map(str::to_owned)
+            .collect();
+        self.accounts = top_level.into_iter().collect();
+        let selected = self.state.selected().unwrap_or_default();
+        self.state.select(match self.accounts.len() {
+            0 => None,
+            len => Some(selected.min(len - 1)),
+        });
+        self.load_reports(context);
+    }
+
+    fn selected(&self) -> Option<&String> {
+        self.accounts.get(self.state.selected()?)
+    }
+
+    /// Loads the reports of the chosen account and month.
+    fn load_reports(&mut self, context: &mut Context) {
+        self.balances.clear();
+        self.flows.clear();
+        let (book_name, account_name) = match (&self.book, self.selected()) {
+            (Some(book_name), Some(account_name)) => (book_name.clone(), account_name.clone()),
+            _ => return,
+        };
+        let end = self.month + Months::new(1);
+        let query = ReportQuery {
+            from: Some(end - Months::new(MONTHS)),
+            to: Some(end),
+            interval: Some(Interval::Month),
+            account: Some(account_name),
+            depth: Some(2),
+            budget: None,
+        };
+        let reports = context.connection.run(|client| async {
+            let balances = client.balance_report(&book_name, &query).await?;
+            let flows = client.flow_report(&book_name, &query).await?;
+            Ok((balances, flows))
+        });
+        if let Some((balances, flows)) = context.report(reports) {
+            self.balances = balances;
+            self.flows = flows;
+        }
+    }
+
+    fn decimal_points(&self, currency: &str) -> i32 {
+        self.currencies.get(currency).copied().unwrap_or_default()
+    }
+
+    /// `amount` in whole units of `currency`.
+    fn units(&self, amount: i64, currency: &str) -> f64 {
+        amount as f64 / 10f64.powi(self.decimal_points(currency))
+    }
+
+    /// The currency with the most posted, which the bar charts show.
+    fn main_currency(&self) -> Option<String> {
+        let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+        for flow in &self.flows {
+            *totals.entry(&flow.currency).or_default() += flow.amount.unsigned_abs();
+        }
+        totals
+            .into_iter()
+            .max_by_key(|(_, total)| *total)
+            .map(|(currency, _)| currency.to_owned())
+    }
+
+    fn draw_balances<B: Backend>(&self, f: &mut Frame<B>, area: Rect, account_name: &str) {
+        let mut by_currency: BTreeMap<&str, Vec<(f64, f64)>> = BTreeMap::new();
+        let mut months: Vec<NaiveDateTime> = vec![];
+        for point in &self.balances {
+            if !months.contains(&point.period) {
+                months.push(point.period);
+            }
+            let x = (months.len() - 1) as f64;
+            let y = self.units(point.balance, &point.currency);
+            by_currency.entry(&point.currency).or_default().push((x, y));
+        }
+        let values = by_currency.values().flatten().map(|(_, y)| *y);
+        let low = values.clone().fold(0f64, f64::min);
+        let high = values.fold(0f64, f64::max);
+        let datasets = by_currency
+            .iter()
+            .zip(COLORS.iter().cycle())
+            .map(|((currency, points), color)| {
+                Dataset::default()
+                    .name(*currency)
+                    .marker(symbols::Marker::Braille)
+                    .style(Style::default().fg(*color))
+                    .data(points)
+            })
+            .collect();
+        let month_label = |month: Option<&NaiveDateTime>| {
+            Span::raw(
+                month
+                    .map(|month| month.format("%Y-%m").to_string())
+                    .unwrap_or_default(),
+            )
+        };
+        let chart = Chart::new(datasets)
+            .block(
+                Block::default()
+                    .title(format!("Balance of {}", account_name))
+                    .borders(Borders::ALL),
+            )
+            .x_axis(
+                Axis::default()
+                    .bounds([0.0, months.len().saturating_sub(1).max(1) as f64])
+                    .labels(vec![
+                        month_label(months.first()),
+                        month_label(months.last()),
+                    ]),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([low, high.max(low + 1.0)])
+                    .labels(vec![
+                        Span::raw(format!("{:.0}", low)),
+                        Span::raw(format!("{:.0}", high)),
+                    ]),
+            );
+        f.render_widget(chart, area);
+    }
+
+    /// Bars of `sums` in whole units of `currency`, without their sign.
+    fn draw_bars<B: Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        title: String,
+        currency: &str,
+        sums: &[(String, i64)],
+    ) {
+        let bars: Vec<(&str, u64)> = sums
+            .iter()
+            .map(|(label, amount)| {
+                (
+                    label.as_str(),
+                    self.units(*amount, currency).abs().round() as u64,
+                )
+            })
+            .collect();
+        let width = match bars.len() as u16 {
+            0 => 1,
+            len => (area.width.saturating_sub(2) / len)
+                .saturating_sub(1)
+                .clamp(1, 12),
+        };
+        let chart = BarChart::default()
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .data(&bars)
+            .bar_width(width)
+            .bar_style(Style::default().fg(Color::Cyan))
+            .value_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_widget(chart, area);
+    }
+}
+
+impl View for ReportsView {
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, context: &Context) {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(24), Constraint::Min(0)].as_ref())
+            .split(area);
+        let title = match &context.book {
+            Some(_) if context.offline => "Reports (needs the server)",
+            Some(_) => "Reports",
+            None => "Reports (open a book first)",
+        };
+        let items: Vec<ListItem> = self
+            .accounts
+            .iter()
+            .map(|account_name| ListItem::new(account_name.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, columns[0], &mut self.state);
+
+        let account_name = match self.selected() {
+            Some(account_name) => account_name.clone(),
+            None => return,
+        };
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(columns[1]);
+        self.draw_balances(f, rows[0], &account_name);
+
+        let currency = match self.main_currency() {
+            Some(currency) => currency,
+            None => return,
+        };
+        let charts = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(rows[1]);
+        let mut monthly: BTreeMap<NaiveDateTime, i64> = (1..=MONTHS)
+            .map(|months| (self.month + Months::new(1) - Months::new(months), 0))
+            .collect();
+        let mut below: Vec<(String, i64)> = vec![];
+        for flow in self.flows.iter().filter(|flow| flow.currency == currency) {
+            if let Some(sum) = monthly.get_mut(&flow.period) {
+                *sum += flow.amount;
+            }
+            if flow.period == self.month {
+                let label = flow.account_name.rsplit(':').next().unwrap_or_default();
+                below.push((label.to_owned(), flow.amount));
+            }
+        }
+        let monthly: Vec<(String, i64)> = monthly
+            .into_iter()
+            .map(|(month, amount)| (month.format("%b").to_string(), amount))
+            .collect();
+        let title = format!("Posted to {} per month ({})", account_name, currency);
+        self.draw_bars(f, charts[0], title, &currency, &monthly);
+        let title = format!("{} by account ({})", self.month.format("%B %Y"), currency);
+        self.draw_bars(f, charts[1], title, &currency, &below);
+    }
+
+    fn activate(&mut self, context: &mut Context) {
+        if self.book != context.book {
+            self.state.select(None);
+            self.load(context);
+        }
+    }
+
+    fn handle_action(&mut self, action: Action, context: &mut Context) -> bool {
+        if self.book.is_none() {
+            return false;
+        }
+        let len = self.accounts.len();
+        let selected = self.state.selected().unwrap_or_default();
+        match action {
+            Action::Up => self.state.select(Some(selected.saturating_sub(1))),
+            Action::Down => self
+                .state
+                .select(Some((selected + 1).min(len.saturating_sub(1)))),
+            Action::Collapse => self.month = self.month - Months::new(1),
+            Action::Expand => self.month = self.month + Months::new(1),
+            Action::Reload => {
+                self.load(context);
+                return true;
+            }
+            _ => return false,
+        }
+        self.load_reports(context);
+        true
+    }
+
+    fn help(&self) -> Vec<(Action, &'static str)> {
+        if self.book.is_none() {
+            return vec![];
+        }
+        vec![
+            (Action::Up, "chart the previous account"),
+            (Action::Down, "chart the next account"),
+            (Action::Collapse, "show the month before"),
+            (Action::Expand, "show the month after"),
+            (Action::Reload, "reload the reports"),
+        ]
+    }
+}
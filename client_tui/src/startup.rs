@@ -0,0 +1,127 @@
+use crate::config::{Config, Profile};
+use crate::connection::Connection;
+use crate::dialog::{DialogResult, Form};
+use crossterm::event::{self, Event, KeyCode};
+use std::error::Error;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Terminal,
+};
+
+/// Connects to the profile the user picks, directly if there is only one, and logs in if the
+/// profile has no token. `None` if the user quit instead.
+pub fn connect<B: Backend>(
+    terminal: &mut Terminal<B>,
+    config: &Config,
+) -> Result<Option<Connection>, Box<dyn Error>> {
+    let mut state = ListState::default();
+    state.select(Some(0));
+    let mut error: Option<String> = None;
+    loop {
+        let pick = config.profiles.len() > 1 || error.is_some();
+        if pick && !pick_profile(terminal, &config.profiles, &mut state, error.as_deref())? {
+            return Ok(None);
+        }
+        let profile = &config.profiles[state.selected().unwrap_or_default()];
+        let mut connection = match Connection::open(profile) {
+            Ok(connection) => connection,
+            Err(open_error) => {
+                error = Some(open_error.to_string());
+                continue;
+            }
+        };
+        if !connection.has_token() {
+            if let Some(user) = &profile.user {
+                if !log_in(terminal, &mut connection, user)? {
+                    error = Some(format!("Not logged in to '{}'.", profile.name));
+                    continue;
+                }
+            }
+        }
+        return Ok(Some(connection));
+    }
+}
+
+/// Returns whether the user picked a profile rather than quitting.
+fn pick_profile<B: Backend>(
+    terminal: &mut Terminal<B>,
+    profiles: &[Profile],
+    state: &mut ListState,
+    error: Option<&str>,
+) -> Result<bool, Box<dyn Error>> {
+    loop {
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                .split(f.size());
+            let items: Vec<ListItem> = profiles
+                .iter()
+                .map(|profile| {
+                    ListItem::new(Spans::from(vec![
+                        Span::styled(
+                            profile.name.clone(),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(format!("  {}", profile.url)),
+                    ]))
+                })
+                .collect();
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .title("Profiles (Enter: connect, q: quit)")
+                        .borders(Borders::ALL),
+                )
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(list, chunks[0], state);
+            let error =
+                Paragraph::new(error.unwrap_or_default()).style(Style::default().fg(Color::Red));
+            f.render_widget(error, chunks[1]);
+        })?;
+        if let Event::Key(key) = event::read()? {
+            let selected = state.selected().unwrap_or_default();
+            match key.code {
+                KeyCode::Up => state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Down => state.select(Some((selected + 1).min(profiles.len() - 1))),
+                KeyCode::Enter => return Ok(true),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Asks for the password of `user`. Returns whether the login succeeded rather than the user
+/// giving up.
+fn log_in<B: Backend>(
+    terminal: &mut Terminal<B>,
+    connection: &mut Connection,
+    user: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let mut form = Form::new(format!("Log in to {}", connection.profile.name))
+        .field("User", user)
+        .secret("Password")
+        .focus("Password");
+    loop {
+        terminal.draw(|f| form.draw(f, f.size()))?;
+        if let Event::Key(key) = event::read()? {
+            match form.handle_key(key) {
+                DialogResult::Open => {}
+                DialogResult::Submitted => {
+                    let user = form.value("User").to_owned();
+                    match connection.login(&user, form.value("Password")) {
+                        Ok(()) => return Ok(true),
+                        Err(error) => form.set_error(error.to_string()),
+                    }
+                }
+                DialogResult::Cancelled => return Ok(false),
+            }
+        }
+    }
+}
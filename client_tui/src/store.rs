@@ -0,0 +1,330 @@
+//! Local copies of the books last opened and the transactions entered while the server could not
+//! be reached, kept per profile in `$XDG_DATA_HOME/finance/tui/<profile>.json`, which defaults to
+//! `~/.local/share/finance/tui/<profile>.json`.
+
+use chrono::{NaiveDateTime, Utc};
+use finance_lib::client::{FinanceClient, Result};
+use finance_lib::{Book, NewPosting, NewTransaction, Posting, RegisterEntry, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+/// Books kept, the ones opened last.
+const MAX_BOOKS: usize = 5;
+
+/// Copy of a book as it was when it was last opened.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub book: Book,
+    /// Decimal points of each currency.
+    pub currencies: HashMap<String, i32>,
+    pub accounts: Vec<String>,
+    /// Transactions with postings, in the order of the journal.
+    pub transactions: Vec<(Transaction, Vec<Posting>)>,
+    pub saved_at: NaiveDateTime,
+}
+
+impl Snapshot {
+    pub async fn fetch(client: &FinanceClient, book_name: &str) -> Result<Self> {
+        let book = client.get_book(book_name).await?.value;
+        let mut currencies = HashMap::new();
+        for symbol in client.get_currencies(book_name).await? {
+            let currency = client.get_currency(book_name, &symbol).await?;
+            currencies.insert(symbol, currency.decimal_points);
+        }
+        let accounts = client.get_accounts(book_name).await?;
+        // The registers of all accounts hold every posting along with its transaction, in far
+        // fewer requests than fetching the transactions one by one.
+        let mut transactions: BTreeMap<i64, (Transaction, Vec<Posting>)> = BTreeMap::new();
+        for account_name in &accounts {
+            for entry in client.account_register(book_name, account_name).await? {
+                let (_, postings) = transactions
+                    .entry(entry.transaction.id)
+                    .or_insert_with(|| (entry.transaction, vec![]));
+                postings.push(entry.posting);
+            }
+        }
+        let transactions = transactions
+            .into_values()
+            .map(|(transaction, mut postings)| {
+                postings.sort_by_key(|posting| posting.id);
+                (transaction, postings)
+            })
+            .collect();
+        Ok(Self {
+            book,
+            currencies,
+            accounts,
+            transactions,
+            saved_at: Utc::now().naive_utc(),
+        })
+    }
+
+    /// The postings of an account with their transactions, ordered like the register of the
+    /// server.
+    pub fn register(&self, account_name: &str) -> Vec<RegisterEntry> {
+        let mut transactions: Vec<&(Transaction, Vec<Posting>)> =
+            self.transactions.iter().collect();
+        transactions.sort_by_key(|(transaction, _)| (transaction.time, transaction.id));
+        transactions
+            .into_iter()
+            .flat_map(|(transaction, postings)| {
+                postings
+                    .iter()
+                    .filter(|posting| posting.account_name == account_name)
+                    .map(|posting| RegisterEntry {
+                        transaction: transaction.clone(),
+                        posting: posting.clone(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A transaction entered offline, created once the server can be reached.
+#[derive(Serialize, Deserialize)]
+pub struct Pending {
+    /// Idempotency key of the transaction, its postings use it followed by their index. Sending
+    /// the transaction again after a failure only creates what is missing.
+    pub key: String,
+    pub book_name: String,
+    pub transaction: NewTransaction,
+    pub postings: Vec<NewPosting>,
+    /// Why the server refused it the last time. It stays queued until it is sent or discarded.
+    pub conflict: Option<String>,
+}
+
+impl Pending {
+    pub fn new(book_name: &str, transaction: NewTransaction, postings: Vec<NewPosting>) -> Self {
+        Self {
+            key: format!(
+                "tui-{}-{}",
+                Utc::now().format("%Y%m%d%H%M%S%f"),
+                std::process::id()
+            ),
+            book_name: book_name.to_owned(),
+            transaction,
+            postings,
+            conflict: None,
+        }
+    }
+
+    /// Creates the transaction and its postings, returns the id of the transaction.
+    pub async fn send(&self, client: &FinanceClient) -> Result<i64> {
+        let transaction_id = client
+            .create_transaction_once(&self.book_name, &self.transaction, &self.key)
+            .await?;
+        for (i, posting) in self.postings.iter().enumerate() {
+            client
+                .create_posting_once(
+                    &self.book_name,
+                    transaction_id,
+                    posting,
+                    &self.posting_key(i),
+                )
+                .await?;
+        }
+        Ok(transaction_id)
+    }
+
+    fn posting_key(&self, index: usize) -> String {
+        format!("{}-{}", self.key, index)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Store {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// The book opened last comes first.
+    books: Vec<Snapshot>,
+    /// Transactions waiting to be sent, oldest first.
+    pub queue: Vec<Pending>,
+}
+
+fn path(profile_name: &str) -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(
+        data_home
+            .join("finance")
+            .join("tui")
+            .join(format!("{}.json", profile_name)),
+    )
+}
+
+impl Store {
+    /// Reads the store of a profile, which is empty until something is kept.
+    pub fn load(profile_name: &str) -> std::result::Result<Self, Box<dyn Error>> {
+        let path = path(profile_name);
+        let mut store = match &path {
+            Some(path) => match fs::read(path) {
+                Ok(json) => serde_json::from_slice(&json)
+                    .map_err(|error| format!("Invalid local copy {}: {}", path.display(), error))?,
+                Err(error) if error.kind() == ErrorKind::NotFound => Store::default(),
+                Err(error) => return Err(error.into()),
+            },
+            None => Store::default(),
+        };
+        store.path = path;
+        Ok(store)
+    }
+
+    /// Writes the store, replacing the file at once so an interruption cannot garble it.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let written = path.with_extension("json.new");
+        fs::write(&written, serde_json::to_vec(self)?)?;
+        fs::rename(written, path)
+    }
+
+    pub fn books(&self) -> &[Snapshot] {
+        &self.books
+    }
+
+    pub fn snapshot(&self, book_name: &str) -> Option<&Snapshot> {
+        self.books
+            .iter()
+            .find(|snapshot| snapshot.book.name == book_name)
+    }
+
+    /// Keeps the copy of a book as the one opened last, forgetting the oldest ones.
+    pub fn keep(&mut self, snapshot: Snapshot) {
+        self.books
+            .retain(|kept| kept.book.name != snapshot.book.name);
+        self.books.insert(0, snapshot);
+        self.books.truncate(MAX_BOOKS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(id: i64, time: Option<NaiveDateTime>) -> Transaction {
+        Transaction {
+            id,
+            time,
+            description: None,
+            posted: None,
+            reverses: None,
+        }
+    }
+
+    fn posting(id: i64, account_name: &str, amount: i32) -> Posting {
+        Posting {
+            id,
+            valuta: None,
+            account_name: account_name.to_owned(),
+            currency: "EUR".to_owned(),
+            amount,
+            budget: None,
+        }
+    }
+
+    fn snapshot(name: &str, transactions: Vec<(Transaction, Vec<Posting>)>) -> Snapshot {
+        Snapshot {
+            book: Book {
+                name: name.to_owned(),
+                description: None,
+                immutable: None,
+                lock_date: None,
+            },
+            currencies: HashMap::from([("EUR".to_owned(), 2)]),
+            accounts: vec!["Cash".to_owned(), "Groceries".to_owned()],
+            transactions,
+            saved_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn register_orders_by_time_then_id() {
+        let day = |day| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        };
+        let snapshot = snapshot(
+            "Household",
+            vec![
+                (
+                    transaction(1, day(3)),
+                    vec![posting(1, "Cash", -100), posting(2, "Groceries", 100)],
+                ),
+                (transaction(2, day(1)), vec![posting(3, "Cash", -200)]),
+                (transaction(3, day(3)), vec![posting(4, "Cash", -300)]),
+                (transaction(4, None), vec![posting(5, "Cash", -400)]),
+                (transaction(5, day(2)), vec![posting(6, "Groceries", 500)]),
+            ],
+        );
+        let amounts: Vec<i32> = snapshot
+            .register("Cash")
+            .iter()
+            .map(|entry| entry.posting.amount)
+            .collect();
+        assert_eq!(amounts, [-400, -200, -100, -300]);
+        assert!(snapshot.register("Savings").is_empty());
+    }
+
+    #[test]
+    fn keep_puts_the_book_opened_last_first() {
+        let mut store = Store::default();
+        for i in 0..MAX_BOOKS + 1 {
+            store.keep(snapshot(&format!("Book {}", i), vec![]));
+        }
+        store.keep(snapshot("Book 3", vec![]));
+        let names: Vec<&str> = store
+            .books()
+            .iter()
+            .map(|snapshot| snapshot.book.name.as_str())
+            .collect();
+        assert_eq!(names, ["Book 3", "Book 5", "Book 4", "Book 2", "Book 1"]);
+        assert!(store.snapshot("Book 0").is_none());
+    }
+
+    #[test]
+    fn resent_transactions_reuse_their_keys() {
+        let new_posting = |account_name: &str, amount| NewPosting {
+            valuta: None,
+            account_name: account_name.to_owned(),
+            currency: "EUR".to_owned(),
+            amount,
+            budget: false,
+        };
+        let pending = Pending::new(
+            "Household",
+            NewTransaction {
+                description: Some("Market".to_owned()),
+                time: None,
+                posted: None,
+            },
+            vec![new_posting("Cash", -100), new_posting("Groceries", 100)],
+        );
+        // The queue is saved between attempts, possibly by another run of the program.
+        let saved: Pending =
+            serde_json::from_slice(&serde_json::to_vec(&pending).unwrap()).unwrap();
+        assert_eq!(saved.key, pending.key);
+        let keys: Vec<String> = (0..pending.postings.len())
+            .map(|i| pending.posting_key(i))
+            .collect();
+        let resent: Vec<String> = (0..saved.postings.len())
+            .map(|i| saved.posting_key(i))
+            .collect();
+        assert_eq!(keys, resent);
+        assert_ne!(keys[0], keys[1]);
+        assert!(keys
+            .iter()
+            .all(|key| key.starts_with(&format!("{}-", pending.key))));
+    }
+}
@@ -1,42 +1,884 @@
+use crate::connection::Connection;
+use crate::dialog::{DialogResult, Form};
+use crate::editor::{format_time, Draft, TransactionEditor};
+use crate::keymap::Action;
+use crate::store::{Pending, Snapshot, Store};
+use crossterm::event::KeyEvent;
+use finance_lib::amount;
+use finance_lib::client::{ClientError, Result, Tagged};
+use finance_lib::{
+    Book, BookPatch, CurrencyAmount, ErrorCode, Page, Posting, RegisterEntry, Transaction,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use tui::{
     backend::Backend,
-    layout::Rect,
-    widgets::{Block, Borders},
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Row, Table, TableState},
     Frame,
 };
 
+pub enum Status {
+    Notice(String),
+    Error(String),
+}
+
+/// State shared by all views.
+pub struct Context {
+    pub connection: Connection,
+    /// Book the other views show, chosen in the books view.
+    pub book: Option<String>,
+    /// Last notice or error, shown in the status bar.
+    pub status: Option<Status>,
+    /// Copies of the books opened last and the transactions waiting to be sent.
+    pub store: Store,
+    /// Whether the server could not be reached. The views then show the copies of the store
+    /// until the next sync.
+    pub offline: bool,
+}
+
+impl Context {
+    pub fn notify(&mut self, notice: impl Into<String>) {
+        self.status = Some(Status::Notice(notice.into()));
+    }
+
+    /// Shows the error of a failed request in the status bar.
+    pub fn report<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                let error = match &error {
+                    ClientError::Http(_) => {
+                        self.offline = true;
+                        format!(
+                            "Cannot reach {}, working offline: {}",
+                            self.connection.profile.url, error
+                        )
+                    }
+                    ClientError::Api(api_error)
+                        if api_error.code == ErrorCode::NotAuthenticated =>
+                    {
+                        format!(
+                            "Not authenticated, check the token of profile '{}': {}",
+                            self.connection.profile.name, error
+                        )
+                    }
+                    ClientError::Api(_) => error.to_string(),
+                };
+                self.status = Some(Status::Error(error));
+                None
+            }
+        }
+    }
+
+    /// Shows that the server is needed, returns whether it is offline.
+    pub fn refuse_offline(&mut self) -> bool {
+        if self.offline {
+            self.status = Some(Status::Error(
+                "Not possible offline, sync once the server can be reached.".to_owned(),
+            ));
+        }
+        self.offline
+    }
+
+    pub fn save_store(&mut self) {
+        if let Err(error) = self.store.save() {
+            self.status = Some(Status::Error(format!(
+                "Cannot save the local copy: {}",
+                error
+            )));
+        }
+    }
+
+    /// Makes a book the active one, keeping a copy of it for offline use.
+    pub fn open_book(&mut self, book_name: &str) {
+        if self.offline {
+            if self.store.snapshot(book_name).is_none() {
+                self.status = Some(Status::Error(format!(
+                    "There is no local copy of book '{}'.",
+                    book_name
+                )));
+                return;
+            }
+        } else {
+            let snapshot = self
+                .connection
+                .run(|client| Snapshot::fetch(client, book_name));
+            match self.report(snapshot) {
+                Some(snapshot) => self.store.keep(snapshot),
+                None => return,
+            }
+        }
+        self.book = Some(book_name.to_owned());
+        self.notify(format!("Opened book '{}'.", book_name));
+        self.save_store();
+    }
+
+    /// Creates a transaction, or queues it while offline and when the server cannot be reached.
+    pub fn create_transaction(&mut self, pending: Pending) {
+        if !self.offline {
+            let sent = self.connection.run(|client| pending.send(client));
+            match sent {
+                Ok(transaction_id) => {
+                    self.notify(format!("Saved transaction {}.", transaction_id));
+                    return;
+                }
+                Err(ClientError::Http(error)) => {
+                    self.report::<()>(Err(ClientError::Http(error)));
+                }
+                Err(error) => {
+                    self.report::<()>(Err(error));
+                    return;
+                }
+            }
+        }
+        self.store.queue.push(pending);
+        self.notify("Queued the transaction until the next sync.");
+        self.save_store();
+    }
+
+    /// Reconnects, sends the queued transactions and refreshes the copy of the active book.
+    /// Transactions the server refuses stay queued along with the reason.
+    pub fn sync(&mut self) {
+        let hello = self.connection.run(|client| client.hello());
+        if self.report(hello).is_none() {
+            return;
+        }
+        self.offline = false;
+        let mut sent = 0;
+        let mut queue = vec![];
+        for mut pending in std::mem::take(&mut self.store.queue) {
+            if self.offline {
+                queue.push(pending);
+                continue;
+            }
+            match self.connection.run(|client| pending.send(client)) {
+                Ok(_) => sent += 1,
+                Err(ClientError::Http(error)) => {
+                    self.report::<()>(Err(ClientError::Http(error)));
+                    queue.push(pending);
+                }
+                Err(error) => {
+                    pending.conflict = Some(error.to_string());
+                    queue.push(pending);
+                }
+            }
+        }
+        self.store.queue = queue;
+        if let (false, Some(book_name)) = (self.offline, self.book.clone()) {
+            let snapshot = self
+                .connection
+                .run(|client| Snapshot::fetch(client, &book_name));
+            if let Some(snapshot) = self.report(snapshot) {
+                self.store.keep(snapshot);
+            }
+        }
+        if !self.offline {
+            self.notify(match (sent, self.store.queue.len()) {
+                (0, 0) => "Synced.".to_owned(),
+                (sent, 0) => format!("Synced, sent {} queued transactions.", sent),
+                (sent, refused) => format!(
+                    "Sent {} queued transactions, the server refused {}.",
+                    sent, refused
+                ),
+            });
+        }
+        self.save_store();
+    }
+}
+
 pub trait View {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect);
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, context: &Context);
+
+    /// Called whenever the view is shown.
+    fn activate(&mut self, _context: &mut Context) {}
+
+    /// Passes the key to the dialog of the view, which takes all keys while it is open. Returns
+    /// whether there was one.
+    fn handle_key(&mut self, _key: KeyEvent, _context: &mut Context) -> bool {
+        false
+    }
+
+    /// Returns whether the view used the action.
+    fn handle_action(&mut self, _action: Action, _context: &mut Context) -> bool {
+        false
+    }
+
+    /// The actions the view uses just now, with what they do, for the help overlay.
+    fn help(&self) -> Vec<(Action, &'static str)> {
+        vec![]
+    }
+}
+
+/// Selection by some rows, or to the first or last row.
+fn movement(action: Action) -> Option<isize> {
+    match action {
+        Action::Up => Some(-1),
+        Action::Down => Some(1),
+        Action::PageUp => Some(-10),
+        Action::PageDown => Some(10),
+        Action::Top => Some(isize::MIN),
+        Action::Bottom => Some(isize::MAX),
+        _ => None,
+    }
 }
 
-pub struct BooksView {}
+const MOVEMENT_HELP: [(Action, &str); 6] = [
+    (Action::Up, "select the previous row"),
+    (Action::Down, "select the next row"),
+    (Action::PageUp, "select ten rows up"),
+    (Action::PageDown, "select ten rows down"),
+    (Action::Top, "select the first row"),
+    (Action::Bottom, "select the last row"),
+];
+
+enum BookDialog {
+    Create(Form),
+    Edit {
+        name: String,
+        etag: Option<String>,
+        form: Form,
+    },
+    Delete {
+        name: String,
+        form: Form,
+    },
+}
+
+pub struct BooksView {
+    books: Option<Vec<Tagged<Book>>>,
+    state: ListState,
+    dialog: Option<BookDialog>,
+}
 
 impl BooksView {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            books: None,
+            state: ListState::default(),
+            dialog: None,
+        }
+    }
+
+    fn load(&mut self, context: &mut Context) {
+        let books = if context.offline {
+            let mut books: Vec<Tagged<Book>> = context
+                .store
+                .books()
+                .iter()
+                .map(|snapshot| untagged(snapshot.book.clone()))
+                .collect();
+            books.sort_by(|a, b| a.name.cmp(&b.name));
+            books
+        } else {
+            let books = context.connection.run(|client| async move {
+                let mut books = vec![];
+                for name in client.get_books().await? {
+                    books.push(client.get_book(&name).await?);
+                }
+                Ok(books)
+            });
+            context.report(books).unwrap_or_default()
+        };
+        let selected = self.state.selected().unwrap_or_default();
+        self.state.select(match books.len() {
+            0 => None,
+            len => Some(selected.min(len - 1)),
+        });
+        self.books = Some(books);
+    }
+
+    fn selected(&self) -> Option<&Tagged<Book>> {
+        self.books.as_ref()?.get(self.state.selected()?)
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        let len = self.books.as_ref().map(Vec::len).unwrap_or_default();
+        if let Some(selected) = self.state.selected() {
+            let selected = selected
+                .saturating_add_signed(by)
+                .min(len.saturating_sub(1));
+            self.state.select(Some(selected));
+        }
+    }
+
+    fn submit(&mut self, dialog: &BookDialog, context: &mut Context) {
+        match dialog {
+            BookDialog::Create(form) => {
+                let book = Book {
+                    name: form.value("Name").to_owned(),
+                    description: form.optional("Description"),
+                    immutable: None,
+                    lock_date: None,
+                };
+                let result = context.connection.run(|client| client.create_book(&book));
+                if context.report(result).is_some() {
+                    context.notify(format!("Created book '{}'.", book.name));
+                }
+            }
+            BookDialog::Edit { name, etag, form } => {
+                let patch = BookPatch {
+                    name: Some(form.value("Name").to_owned()).filter(|new| new != name),
+                    description: Some(form.optional("Description")),
+                    immutable: None,
+                };
+                let new_name = patch.name.clone();
+                let result = context
+                    .connection
+                    .run(|client| client.patch_book(name, &patch, etag.as_deref()));
+                if context.report(result).is_some() {
+                    if let Some(new_name) = new_name {
+                        if context.book.as_ref() == Some(name) {
+                            context.book = Some(new_name);
+                        }
+                    }
+                    context.notify(format!("Saved book '{}'.", name));
+                }
+            }
+            BookDialog::Delete { name, .. } => {
+                let result = context.connection.run(|client| client.delete_book(name));
+                if context.report(result).is_some() {
+                    if context.book.as_ref() == Some(name) {
+                        context.book = None;
+                    }
+                    context.notify(format!("Moved book '{}' to the trash.", name));
+                }
+            }
+        }
+        self.load(context);
     }
 }
 
 impl View for BooksView {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        let b = Block::default().title("Books").borders(Borders::ALL);
-        f.render_widget(b, area);
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, context: &Context) {
+        let items: Vec<ListItem> = self
+            .books
+            .iter()
+            .flatten()
+            .map(|book| {
+                let active = context.book.as_ref() == Some(&book.name);
+                let mut spans = vec![Span::styled(
+                    format!("{} {}", if active { "*" } else { " " }, book.name),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )];
+                if let Some(description) = &book.description {
+                    spans.push(Span::raw(format!("  {}", description)));
+                }
+                if book.immutable == Some(true) {
+                    spans.push(Span::raw("  [immutable]"));
+                }
+                if let Some(lock_date) = book.lock_date {
+                    spans.push(Span::raw(format!("  [closed before {}]", lock_date.date())));
+                }
+                ListItem::new(Spans::from(spans))
+            })
+            .collect();
+        let title = if context.offline {
+            "Books (local copies)"
+        } else {
+            "Books"
+        };
+        let list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut self.state);
+
+        match &self.dialog {
+            Some(BookDialog::Create(form))
+            | Some(BookDialog::Edit { form, .. })
+            | Some(BookDialog::Delete { form, .. }) => form.draw(f, area),
+            None => {}
+        }
+    }
+
+    fn activate(&mut self, context: &mut Context) {
+        if self.books.is_none() {
+            self.load(context);
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, context: &mut Context) -> bool {
+        if let Some(mut dialog) = self.dialog.take() {
+            let form = match &mut dialog {
+                BookDialog::Create(form)
+                | BookDialog::Edit { form, .. }
+                | BookDialog::Delete { form, .. } => form,
+            };
+            match form.handle_key(key) {
+                DialogResult::Open => self.dialog = Some(dialog),
+                DialogResult::Submitted => self.submit(&dialog, context),
+                DialogResult::Cancelled => {}
+            }
+            return true;
+        }
+        false
+    }
+
+    fn handle_action(&mut self, action: Action, context: &mut Context) -> bool {
+        if let Some(by) = movement(action) {
+            self.move_selection(by);
+            return true;
+        }
+        match action {
+            Action::Open => {
+                if let Some(book) = self.selected() {
+                    let book_name = book.name.clone();
+                    context.open_book(&book_name);
+                }
+            }
+            Action::New | Action::Edit | Action::Delete if context.refuse_offline() => {}
+            Action::New => {
+                let form = Form::new("New book")
+                    .field("Name", "")
+                    .field("Description", "");
+                self.dialog = Some(BookDialog::Create(form));
+            }
+            Action::Edit => {
+                if let Some(book) = self.selected() {
+                    let form = Form::new(format!("Edit book '{}'", book.name))
+                        .field("Name", book.name.as_str())
+                        .field("Description", book.description.clone().unwrap_or_default());
+                    self.dialog = Some(BookDialog::Edit {
+                        name: book.name.clone(),
+                        etag: book.etag.clone(),
+                        form,
+                    });
+                }
+            }
+            Action::Delete => {
+                if let Some(book) = self.selected() {
+                    let form = Form::new(format!("Delete book '{}'?", book.name));
+                    self.dialog = Some(BookDialog::Delete {
+                        name: book.name.clone(),
+                        form,
+                    });
+                }
+            }
+            Action::Reload => self.load(context),
+            _ => return false,
+        }
+        true
+    }
+
+    fn help(&self) -> Vec<(Action, &'static str)> {
+        let mut help = MOVEMENT_HELP.to_vec();
+        help.extend([
+            (Action::Open, "open the book in the other views"),
+            (Action::New, "create a book"),
+            (Action::Edit, "edit the book"),
+            (Action::Delete, "move the book to the trash"),
+            (Action::Reload, "reload the books"),
+        ]);
+        help
     }
 }
 
-pub struct AccountsView {}
+/// Separates the levels of account names, like in `Expenses:Food`.
+const ACCOUNT_SEPARATOR: char = ':';
+
+/// Sums per currency.
+type Balances = BTreeMap<String, i64>;
+
+fn sum(amounts: &[CurrencyAmount]) -> Balances {
+    let mut balances = Balances::new();
+    for amount in amounts {
+        *balances.entry(amount.currency.clone()).or_default() += amount.amount.unwrap_or_default();
+    }
+    balances
+}
+
+/// Balance of each account of a local copy, with and without budget postings.
+fn snapshot_balances(snapshot: &Snapshot) -> Vec<(String, Balances, Balances)> {
+    snapshot
+        .accounts
+        .iter()
+        .map(|account_name| {
+            let mut value = Balances::new();
+            let mut real_value = Balances::new();
+            let postings = snapshot
+                .transactions
+                .iter()
+                .flat_map(|(_, postings)| postings)
+                .filter(|posting| &posting.account_name == account_name);
+            for posting in postings {
+                let amount = i64::from(posting.amount);
+                *value.entry(posting.currency.clone()).or_default() += amount;
+                if posting.budget != Some(true) {
+                    *real_value.entry(posting.currency.clone()).or_default() += amount;
+                }
+            }
+            (account_name.clone(), value, real_value)
+        })
+        .collect()
+}
+
+/// An account or a level of account names above accounts, with the balances of everything below.
+struct AccountRow {
+    name: String,
+    depth: usize,
+    is_account: bool,
+    has_children: bool,
+    value: Balances,
+    real_value: Balances,
+}
+
+struct Register {
+    account_name: String,
+    entries: Vec<RegisterEntry>,
+    state: TableState,
+}
+
+/// Tree of the accounts of the active book with their balances. Enter opens the register of an
+/// account.
+pub struct AccountsView {
+    book: Option<String>,
+    rows: Vec<AccountRow>,
+    collapsed: HashSet<String>,
+    currencies: HashMap<String, i32>,
+    state: TableState,
+    register: Option<Register>,
+}
 
 impl AccountsView {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            book: None,
+            rows: vec![],
+            collapsed: HashSet::new(),
+            currencies: HashMap::new(),
+            state: TableState::default(),
+            register: None,
+        }
+    }
+
+    fn load(&mut self, context: &mut Context) {
+        self.book = context.book.clone();
+        self.rows.clear();
+        self.register = None;
+        let book_name = match &self.book {
+            Some(book_name) => book_name.clone(),
+            None => return,
+        };
+        self.currencies = load_currencies(context, &book_name).unwrap_or_default();
+        let accounts = if context.offline {
+            context
+                .store
+                .snapshot(&book_name)
+                .map(snapshot_balances)
+                .unwrap_or_default()
+        } else {
+            let accounts = context.connection.run(|client| async move {
+                let mut accounts = vec![];
+                for account_name in client.get_accounts(&book_name).await? {
+                    let value = client.account_value(&book_name, &account_name).await?;
+                    let real_value = client.real_account_value(&book_name, &account_name).await?;
+                    accounts.push((account_name, sum(&value), sum(&real_value)));
+                }
+                Ok(accounts)
+            });
+            context.report(accounts).unwrap_or_default()
+        };
+
+        // Sorting by levels keeps every level right above what is below it.
+        let mut rows: BTreeMap<Vec<String>, AccountRow> = BTreeMap::new();
+        for (account_name, value, real_value) in accounts {
+            let levels: Vec<String> = account_name
+                .split(ACCOUNT_SEPARATOR)
+                .map(str::to_owned)
+                .collect();
+            for depth in 0..levels.len() {
+                let row = rows
+                    .entry(levels[..=depth].to_vec())
+                    .or_insert_with(|| AccountRow {
+                        name: levels[..=depth].join(&ACCOUNT_SEPARATOR.to_string()),
+                        depth,
+                        is_account: false,
+                        has_children: false,
+                        value: Balances::new(),
+                        real_value: Balances::new(),
+                    });
+                if depth + 1 == levels.len() {
+                    row.is_account = true;
+                } else {
+                    row.has_children = true;
+                }
+                for (currency, amount) in &value {
+                    *row.value.entry(currency.clone()).or_default() += amount;
+                }
+                for (currency, amount) in &real_value {
+                    *row.real_value.entry(currency.clone()).or_default() += amount;
+                }
+            }
+        }
+        self.rows = rows.into_values().collect();
+        let visible = self.visible().len();
+        let selected = self.state.selected().unwrap_or_default();
+        self.state.select(match visible {
+            0 => None,
+            len => Some(selected.min(len - 1)),
+        });
+    }
+
+    /// Rows not hidden below a collapsed level.
+    fn visible(&self) -> Vec<&AccountRow> {
+        self.rows
+            .iter()
+            .filter(|row| {
+                !self.collapsed.iter().any(|collapsed| {
+                    row.name
+                        .strip_prefix(collapsed.as_str())
+                        .is_some_and(|rest| rest.starts_with(ACCOUNT_SEPARATOR))
+                })
+            })
+            .collect()
+    }
+
+    fn selected(&self) -> Option<&AccountRow> {
+        self.visible().get(self.state.selected()?).copied()
+    }
+
+    fn format_balances(&self, balances: &Balances) -> String {
+        let formatted: Vec<String> = balances
+            .iter()
+            .filter(|(_, amount)| **amount != 0)
+            .map(|(currency, amount)| self.format_amount(*amount, currency))
+            .collect();
+        formatted.join(", ")
+    }
+
+    fn format_amount(&self, amount: i64, currency: &str) -> String {
+        let decimal_points = self.currencies.get(currency).copied().unwrap_or_default();
+        format!("{} {}", amount::format(amount, decimal_points), currency)
+    }
+
+    /// Opens the register of an account of the active book.
+    pub fn open_account(&mut self, account_name: &str, context: &mut Context) {
+        let book_name = match &self.book {
+            Some(book_name) => book_name,
+            None => return,
+        };
+        let entries = match context.store.snapshot(book_name) {
+            Some(snapshot) if context.offline => Some(snapshot.register(account_name)),
+            _ => {
+                let entries = context
+                    .connection
+                    .run(|client| client.account_register(book_name, account_name));
+                context.report(entries)
+            }
+        };
+        if let Some(entries) = entries {
+            let mut state = TableState::default();
+            state.select(entries.len().checked_sub(1));
+            self.register = Some(Register {
+                account_name: account_name.to_owned(),
+                entries,
+                state,
+            });
+        }
+    }
+
+    fn draw_register<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let register = match &mut self.register {
+            Some(register) => register,
+            None => return,
+        };
+        let mut running = Balances::new();
+        let mut rows = vec![];
+        for entry in &register.entries {
+            let posting = &entry.posting;
+            let balance = running.entry(posting.currency.clone()).or_default();
+            *balance += i64::from(posting.amount);
+            let decimal_points = self
+                .currencies
+                .get(&posting.currency)
+                .copied()
+                .unwrap_or_default();
+            let mut description = entry.transaction.description.clone().unwrap_or_default();
+            if posting.budget == Some(true) {
+                description.push_str(" (budget)");
+            }
+            rows.push(Row::new(vec![
+                entry
+                    .transaction
+                    .time
+                    .map(|time| format_time(&time))
+                    .unwrap_or_default(),
+                description,
+                format!(
+                    "{} {}",
+                    amount::format(posting.amount.into(), decimal_points),
+                    posting.currency
+                ),
+                format!(
+                    "{} {}",
+                    amount::format(*balance, decimal_points),
+                    posting.currency
+                ),
+            ]));
+        }
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec!["Date", "Description", "Amount", "Balance"])
+                    .style(Style::default().add_modifier(Modifier::UNDERLINED)),
+            )
+            .block(
+                Block::default()
+                    .title(format!("Register of {}", register.account_name))
+                    .borders(Borders::ALL),
+            )
+            .widths(&[
+                Constraint::Length(16),
+                Constraint::Percentage(50),
+                Constraint::Length(20),
+                Constraint::Length(20),
+            ])
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(table, area, &mut register.state);
     }
 }
 
 impl View for AccountsView {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        let b = Block::default().title("Accounts").borders(Borders::ALL);
-        f.render_widget(b, area);
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, context: &Context) {
+        if self.register.is_some() {
+            return self.draw_register(f, area);
+        }
+        let title = match &context.book {
+            Some(_) if context.offline => "Accounts (local copy)",
+            Some(_) => "Accounts",
+            None => "Accounts (open a book first)",
+        };
+        let rows: Vec<Row> = self
+            .visible()
+            .into_iter()
+            .map(|row| {
+                let marker = match (row.has_children, self.collapsed.contains(&row.name)) {
+                    (false, _) => " ",
+                    (true, false) => "-",
+                    (true, true) => "+",
+                };
+                let label = row
+                    .name
+                    .rsplit(ACCOUNT_SEPARATOR)
+                    .next()
+                    .unwrap_or_default();
+                let style = if row.is_account {
+                    Style::default()
+                } else {
+                    Style::default().add_modifier(Modifier::DIM)
+                };
+                Row::new(vec![
+                    format!("{}{} {}", "  ".repeat(row.depth), marker, label),
+                    self.format_balances(&row.value),
+                    self.format_balances(&row.real_value),
+                ])
+                .style(style)
+            })
+            .collect();
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec!["Account", "Balance", "Without budget"])
+                    .style(Style::default().add_modifier(Modifier::UNDERLINED)),
+            )
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .widths(&[
+                Constraint::Percentage(40),
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+            ])
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(table, area, &mut self.state);
+    }
+
+    fn activate(&mut self, context: &mut Context) {
+        if self.book != context.book {
+            self.state.select(None);
+            self.load(context);
+        }
+    }
+
+    fn handle_action(&mut self, action: Action, context: &mut Context) -> bool {
+        if let Some(register) = &mut self.register {
+            if let Some(by) = movement(action) {
+                select_by(&mut register.state, by, register.entries.len());
+                return true;
+            }
+            match action {
+                Action::Back => self.register = None,
+                Action::Reload => {
+                    let account_name = register.account_name.clone();
+                    self.open_account(&account_name, context);
+                }
+                _ => return false,
+            }
+            return true;
+        }
+        if self.book.is_none() {
+            return false;
+        }
+
+        if let Some(by) = movement(action) {
+            let len = self.visible().len();
+            select_by(&mut self.state, by, len);
+            return true;
+        }
+        match action {
+            Action::Collapse => {
+                if let Some(row) = self.selected().filter(|row| row.has_children) {
+                    self.collapsed.insert(row.name.clone());
+                }
+            }
+            Action::Expand => {
+                if let Some(row) = self.selected() {
+                    let name = row.name.clone();
+                    self.collapsed.remove(&name);
+                }
+            }
+            Action::Open => {
+                if let Some(row) = self.selected().filter(|row| row.is_account) {
+                    let account_name = row.name.clone();
+                    self.open_account(&account_name, context);
+                }
+            }
+            Action::Reload => self.load(context),
+            _ => return false,
+        }
+        true
+    }
+
+    fn help(&self) -> Vec<(Action, &'static str)> {
+        let mut help = MOVEMENT_HELP.to_vec();
+        if self.register.is_some() {
+            help.extend([
+                (Action::Back, "go back to the accounts"),
+                (Action::Reload, "reload the register"),
+            ]);
+        } else if self.book.is_some() {
+            help.extend([
+                (Action::Collapse, "hide the accounts below"),
+                (Action::Expand, "show the accounts below"),
+                (Action::Open, "open the register of the account"),
+                (Action::Reload, "reload the accounts"),
+            ]);
+        }
+        help
+    }
+}
+
+/// An entity of a local copy, which has no tag.
+fn untagged<T>(value: T) -> Tagged<T> {
+    Tagged { value, etag: None }
+}
+
+fn select_by(state: &mut TableState, by: isize, len: usize) {
+    if len == 0 {
+        return;
     }
+    let selected = state
+        .selected()
+        .unwrap_or_default()
+        .saturating_add_signed(by);
+    state.select(Some(selected.min(len - 1)));
 }
 
 pub struct CurrenciesView {}
@@ -48,38 +890,496 @@ impl CurrenciesView {
 }
 
 impl View for CurrenciesView {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, _context: &Context) {
         let b = Block::default().title("Currencies").borders(Borders::ALL);
         f.render_widget(b, area);
     }
 }
 
-pub struct TransactionsView {}
+const PAGE_SIZE: i64 = 50;
+
+/// Decimal points of each currency of a book.
+pub fn load_currencies(context: &mut Context, book_name: &str) -> Option<HashMap<String, i32>> {
+    if context.offline {
+        let snapshot = context.store.snapshot(book_name)?;
+        return Some(snapshot.currencies.clone());
+    }
+    let currencies = context.connection.run(|client| async move {
+        let mut currencies = HashMap::new();
+        for symbol in client.get_currencies(book_name).await? {
+            let currency = client.get_currency(book_name, &symbol).await?;
+            currencies.insert(symbol, currency.decimal_points);
+        }
+        Ok(currencies)
+    });
+    context.report(currencies)
+}
+
+/// Journal of the transactions of the active book, oldest first, followed by the transactions
+/// waiting to be sent. The selected transaction shows its postings.
+pub struct TransactionsView {
+    book: Option<String>,
+    transactions: Vec<Tagged<Transaction>>,
+    /// Whether all pages are loaded.
+    complete: bool,
+    postings: HashMap<i64, Vec<Tagged<Posting>>>,
+    accounts: Vec<String>,
+    currencies: HashMap<String, i32>,
+    state: ListState,
+    editor: Option<TransactionEditor>,
+    confirm_delete: Option<Form>,
+}
 
 impl TransactionsView {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            book: None,
+            transactions: vec![],
+            complete: false,
+            postings: HashMap::new(),
+            accounts: vec![],
+            currencies: HashMap::new(),
+            state: ListState::default(),
+            editor: None,
+            confirm_delete: None,
+        }
+    }
+
+    /// Loads the book from the start, keeping at least as many transactions as were loaded.
+    fn load(&mut self, context: &mut Context) {
+        let loaded = self.transactions.len();
+        self.book = context.book.clone();
+        self.transactions.clear();
+        self.postings.clear();
+        self.complete = false;
+        let book_name = match &self.book {
+            Some(book_name) => book_name.clone(),
+            None => return,
+        };
+        if context.offline {
+            self.load_snapshot(context);
+        } else {
+            let accounts = context
+                .connection
+                .run(|client| client.get_accounts(&book_name));
+            self.accounts = context.report(accounts).unwrap_or_default();
+            self.currencies = load_currencies(context, &book_name).unwrap_or_default();
+        }
+        while !self.complete && (self.transactions.is_empty() || self.transactions.len() < loaded) {
+            if !self.load_page(context) {
+                break;
+            }
+        }
+        let selected = self.state.selected().unwrap_or_default();
+        self.state.select(match self.len(context) {
+            0 => None,
+            len => Some(selected.min(len - 1)),
+        });
+        self.load_postings(context);
+    }
+
+    /// Takes the whole journal from the local copy of the book.
+    fn load_snapshot(&mut self, context: &Context) {
+        self.complete = true;
+        let snapshot = match self
+            .book
+            .as_deref()
+            .and_then(|book| context.store.snapshot(book))
+        {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        self.accounts = snapshot.accounts.clone();
+        self.currencies = snapshot.currencies.clone();
+        for (transaction, postings) in &snapshot.transactions {
+            let postings = postings.iter().cloned().map(untagged).collect();
+            self.postings.insert(transaction.id, postings);
+            self.transactions.push(untagged(transaction.clone()));
+        }
+    }
+
+    /// Positions of the queued transactions of the book in the queue, shown once the journal is
+    /// complete.
+    fn queued(&self, context: &Context) -> Vec<usize> {
+        if !self.complete {
+            return vec![];
+        }
+        context
+            .store
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, pending)| self.book.as_ref() == Some(&pending.book_name))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Rows of the journal, including the queued transactions.
+    fn len(&self, context: &Context) -> usize {
+        self.transactions.len() + self.queued(context).len()
+    }
+
+    /// Position of the selected transaction in the queue, if it is a queued one.
+    fn selected_queued(&self, context: &Context) -> Option<usize> {
+        let row = self
+            .state
+            .selected()?
+            .checked_sub(self.transactions.len())?;
+        self.queued(context).get(row).copied()
+    }
+
+    /// Returns whether the page could be loaded.
+    fn load_page(&mut self, context: &mut Context) -> bool {
+        let book_name = match &self.book {
+            Some(book_name) => book_name,
+            None => return false,
+        };
+        let page = Page {
+            offset: Some(self.transactions.len() as i64),
+            limit: Some(PAGE_SIZE),
+        };
+        let transactions = context.connection.run(|client| async move {
+            let mut transactions = vec![];
+            for transaction_id in client.get_transactions(book_name, page).await? {
+                transactions.push(client.get_transaction(book_name, transaction_id).await?);
+            }
+            Ok(transactions)
+        });
+        match context.report(transactions) {
+            Some(transactions) => {
+                self.complete = (transactions.len() as i64) < PAGE_SIZE;
+                self.transactions.extend(transactions);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn selected(&self) -> Option<&Tagged<Transaction>> {
+        self.transactions.get(self.state.selected()?)
+    }
+
+    /// Loads the postings of the selected transaction unless they already are.
+    fn load_postings(&mut self, context: &mut Context) {
+        let (book_name, transaction_id) = match (&self.book, self.selected()) {
+            (Some(book_name), Some(transaction)) => (book_name, transaction.id),
+            _ => return,
+        };
+        if self.postings.contains_key(&transaction_id) {
+            return;
+        }
+        let postings = context.connection.run(|client| async move {
+            let mut postings = vec![];
+            for posting_id in client.get_postings(book_name, transaction_id).await? {
+                postings.push(
+                    client
+                        .get_posting(book_name, transaction_id, posting_id)
+                        .await?,
+                );
+            }
+            Ok(postings)
+        });
+        if let Some(postings) = context.report(postings) {
+            self.postings.insert(transaction_id, postings);
+        }
+    }
+
+    fn move_selection(&mut self, by: isize, context: &mut Context) {
+        let selected = match self.state.selected() {
+            Some(selected) => selected.saturating_add_signed(by),
+            None => return,
+        };
+        while selected >= self.transactions.len() && !self.complete {
+            if !self.load_page(context) {
+                break;
+            }
+        }
+        self.state
+            .select(Some(selected.min(self.len(context).saturating_sub(1))));
+        self.load_postings(context);
+    }
+
+    fn format_posting(
+        &self,
+        account_name: &str,
+        amount: i32,
+        currency: &str,
+        budget: bool,
+    ) -> String {
+        let decimal_points = self.currencies.get(currency).copied().unwrap_or_default();
+        format!(
+            "      {:<32} {:>14} {}{}",
+            account_name,
+            amount::format(amount.into(), decimal_points),
+            currency,
+            if budget { "  (budget)" } else { "" }
+        )
+    }
+
+    fn save(&mut self, draft: Draft, context: &mut Context) {
+        let book_name = match &self.book {
+            Some(book_name) => book_name.clone(),
+            None => return,
+        };
+        let draft = match draft.into_new() {
+            Ok((transaction, postings)) => {
+                context.create_transaction(Pending::new(&book_name, transaction, postings));
+                self.load(context);
+                return;
+            }
+            Err(draft) => draft,
+        };
+        let result = context
+            .connection
+            .run(|client| draft.save(client, &book_name));
+        if let Some(transaction_id) = context.report(result) {
+            context.notify(format!("Saved transaction {}.", transaction_id));
+        }
+        self.load(context);
+    }
+
+    /// Deletes the selected transaction, or discards it if it is a queued one.
+    fn delete(&mut self, context: &mut Context) {
+        match self.selected_queued(context) {
+            Some(i) => {
+                context.store.queue.remove(i);
+                context.notify("Discarded the queued transaction.");
+                context.save_store();
+                self.load(context);
+            }
+            None => self.post_or_delete(true, context),
+        }
+    }
+
+    /// Posts or deletes the selected transaction and reloads the journal.
+    fn post_or_delete(&mut self, delete: bool, context: &mut Context) {
+        if let (Some(book_name), Some(transaction)) = (self.book.clone(), self.selected()) {
+            let transaction_id = transaction.id;
+            let result = context.connection.run(|client| async move {
+                if delete {
+                    client.delete_transaction(&book_name, transaction_id).await
+                } else {
+                    client.post_transaction(&book_name, transaction_id).await
+                }
+            });
+            if context.report(result).is_some() {
+                let done = if delete { "Deleted" } else { "Posted" };
+                context.notify(format!("{} transaction {}.", done, transaction_id));
+            }
+        }
+        self.load(context);
     }
 }
 
 impl View for TransactionsView {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        let b = Block::default().title("Transactions").borders(Borders::ALL);
-        f.render_widget(b, area);
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, context: &Context) {
+        let title = match &context.book {
+            Some(_) if context.offline => "Transactions (local copy)",
+            Some(_) => "Transactions",
+            None => "Transactions (open a book first)",
+        };
+        let selected = self.state.selected();
+        let items: Vec<ListItem> = self
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(i, transaction)| {
+                let date = transaction
+                    .time
+                    .map(|time| format_time(&time))
+                    .unwrap_or_else(|| "no date".to_owned());
+                let mut spans = vec![
+                    Span::raw(format!("{:<16} ", date)),
+                    Span::styled(
+                        transaction.description.clone().unwrap_or_default(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                ];
+                if transaction.posted != Some(true) {
+                    spans.push(Span::raw("  [draft]"));
+                }
+                if let Some(reverses) = transaction.reverses {
+                    spans.push(Span::raw(format!("  [reverses {}]", reverses)));
+                }
+                let mut lines = vec![Spans::from(spans)];
+                if selected == Some(i) {
+                    for posting in self.postings.get(&transaction.id).into_iter().flatten() {
+                        lines.push(Spans::from(self.format_posting(
+                            &posting.account_name,
+                            posting.amount,
+                            &posting.currency,
+                            posting.budget == Some(true),
+                        )));
+                    }
+                }
+                ListItem::new(lines)
+            })
+            .collect();
+        let queued = self.queued(context).into_iter().enumerate().map(|(n, i)| {
+            let pending = &context.store.queue[i];
+            let date = pending
+                .transaction
+                .time
+                .map(|time| format_time(&time))
+                .unwrap_or_else(|| "no date".to_owned());
+            let state = match &pending.conflict {
+                Some(conflict) => Span::styled(
+                    format!("  [not sent: {}]", conflict),
+                    Style::default().fg(Color::Red),
+                ),
+                None => Span::styled("  [queued]", Style::default().fg(Color::Yellow)),
+            };
+            let mut lines = vec![Spans::from(vec![
+                Span::raw(format!("{:<16} ", date)),
+                Span::styled(
+                    pending.transaction.description.clone().unwrap_or_default(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                state,
+            ])];
+            if selected == Some(self.transactions.len() + n) {
+                for posting in &pending.postings {
+                    lines.push(Spans::from(self.format_posting(
+                        &posting.account_name,
+                        posting.amount,
+                        &posting.currency,
+                        posting.budget,
+                    )));
+                }
+            }
+            ListItem::new(lines)
+        });
+        let items: Vec<ListItem> = items.into_iter().chain(queued).collect();
+        let list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut self.state);
+
+        if let Some(editor) = &self.editor {
+            editor.draw(f, area);
+        }
+        if let Some(form) = &self.confirm_delete {
+            form.draw(f, area);
+        }
     }
-}
 
-pub struct PostingsView {}
+    fn activate(&mut self, context: &mut Context) {
+        if self.book != context.book {
+            self.state.select(None);
+            self.load(context);
+        }
+    }
 
-impl PostingsView {
-    pub fn new() -> Self {
-        Self {}
+    fn handle_key(&mut self, key: KeyEvent, context: &mut Context) -> bool {
+        if let Some(mut editor) = self.editor.take() {
+            match editor.handle_key(key) {
+                DialogResult::Open => self.editor = Some(editor),
+                DialogResult::Submitted => match editor.draft() {
+                    Some(draft) => self.save(draft, context),
+                    None => self.editor = Some(editor),
+                },
+                DialogResult::Cancelled => {}
+            }
+            return true;
+        }
+        if let Some(mut form) = self.confirm_delete.take() {
+            match form.handle_key(key) {
+                DialogResult::Open => self.confirm_delete = Some(form),
+                DialogResult::Submitted => self.delete(context),
+                DialogResult::Cancelled => {}
+            }
+            return true;
+        }
+        false
     }
-}
 
-impl View for PostingsView {
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        let b = Block::default().title("Postings").borders(Borders::ALL);
-        f.render_widget(b, area);
+    fn handle_action(&mut self, action: Action, context: &mut Context) -> bool {
+        if self.book.is_none() {
+            return false;
+        }
+        if let Some(by) = movement(action) {
+            self.move_selection(by, context);
+            return true;
+        }
+        match action {
+            Action::New => {
+                self.editor = Some(TransactionEditor::new(
+                    self.accounts.clone(),
+                    self.currencies.clone(),
+                ));
+            }
+            Action::Open | Action::Edit | Action::Post
+                if self.selected_queued(context).is_some() =>
+            {
+                context.status = Some(Status::Error(
+                    "Queued transactions are sent as they are, discard it to enter it anew."
+                        .to_owned(),
+                ));
+            }
+            Action::Open | Action::Edit | Action::Post if context.refuse_offline() => {}
+            Action::Delete if self.selected_queued(context).is_some() => {
+                self.confirm_delete = Some(Form::new("Discard the queued transaction?"));
+            }
+            Action::Delete if context.refuse_offline() => {}
+            Action::Open | Action::Edit => {
+                if let Some(transaction) = self.selected() {
+                    let transaction_id = transaction.id;
+                    let (book_name, transaction) = match &self.book {
+                        Some(book_name) => (book_name.clone(), transaction_id),
+                        None => return true,
+                    };
+                    // Edits start from the stored state, so their tags are current.
+                    let loaded = context.connection.run(|client| async move {
+                        let stored = client.get_transaction(&book_name, transaction).await?;
+                        let mut postings = vec![];
+                        for posting_id in client.get_postings(&book_name, transaction).await? {
+                            postings.push(
+                                client
+                                    .get_posting(&book_name, transaction, posting_id)
+                                    .await?,
+                            );
+                        }
+                        Ok((stored, postings))
+                    });
+                    if let Some((stored, postings)) = context.report(loaded) {
+                        self.editor = Some(TransactionEditor::edit(
+                            stored,
+                            postings,
+                            self.accounts.clone(),
+                            self.currencies.clone(),
+                        ));
+                    }
+                }
+            }
+            Action::Post => self.post_or_delete(false, context),
+            Action::Delete => {
+                if let Some(transaction) = self.selected() {
+                    self.confirm_delete =
+                        Some(Form::new(format!("Delete transaction {}?", transaction.id)));
+                }
+            }
+            Action::Reload => self.load(context),
+            _ => return false,
+        }
+        true
+    }
+
+    fn help(&self) -> Vec<(Action, &'static str)> {
+        if self.book.is_none() {
+            return vec![];
+        }
+        let mut help = MOVEMENT_HELP.to_vec();
+        help.extend([
+            (Action::New, "create a transaction"),
+            (Action::Open, "edit the transaction"),
+            (Action::Edit, "edit the transaction"),
+            (Action::Post, "post the draft transaction"),
+            (
+                Action::Delete,
+                "delete the transaction, or discard a queued one",
+            ),
+            (Action::Reload, "reload the journal"),
+        ]);
+        help
     }
 }
@@ -0,0 +1,41 @@
+//! Amounts are stored in the smallest unit of their currency, like cents, and shown with the
+//! `decimal_points` of the currency.
+
+pub fn format(amount: i64, decimal_points: i32) -> String {
+    let decimal_points = decimal_points.max(0) as u32;
+    if decimal_points == 0 {
+        return amount.to_string();
+    }
+    let unit = 10i64.pow(decimal_points);
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        amount / unit as u64,
+        amount % unit as u64,
+        width = decimal_points as usize
+    )
+}
+
+/// Reads amounts like `-12.5` or `3`, `None` if the text has more decimals than the currency.
+pub fn parse(text: &str, decimal_points: i32) -> Option<i64> {
+    let decimal_points = decimal_points.max(0) as usize;
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty())
+        || !is_digits(whole)
+        || !is_digits(fraction)
+        || fraction.len() > decimal_points
+    {
+        return None;
+    }
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimal_points);
+    let amount: i64 = digits.parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
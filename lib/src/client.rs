@@ -0,0 +1,853 @@
+//! Typed client for the routes of the server.
+
+use crate::*;
+use reqwest::header::{ETAG, IF_MATCH};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::ops::Deref;
+
+/// Header making a create request idempotent, see [`FinanceClient::create_transaction_once`].
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Failure of a request, either refused by the server or lost on the way there.
+#[derive(Debug)]
+pub enum ClientError {
+    Api(ApiError),
+    Http(reqwest::Error),
+}
+
+impl ClientError {
+    /// Code of the error the server answered with.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Api(error) => Some(error.code),
+            ClientError::Http(_) => None,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Api(error) => error.fmt(f),
+            ClientError::Http(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(error: reqwest::Error) -> Self {
+        ClientError::Http(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// An entity along with its tag. Passing the tag to a change of the entity makes the change fail
+/// with [`ErrorCode::PreconditionFailed`] if someone else changed it in the meantime.
+pub struct Tagged<T> {
+    pub value: T,
+    pub etag: Option<String>,
+}
+
+impl<T> Deref for Tagged<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+/// Client for one server, authenticated with a bearer token.
+#[derive(Clone)]
+pub struct FinanceClient {
+    http: reqwest::Client,
+    base_url: Url,
+    token: Option<String>,
+    owner: Option<String>,
+}
+
+impl FinanceClient {
+    pub fn new(base_url: Url) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url,
+            token: None,
+            owner: None,
+        }
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Reaches the books of `owner`, for users who have access to several books of the same name.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Url of the route made of `segments`, which are escaped. An empty last segment gives the
+    /// trailing slash some routes have.
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut request = self.http.request(method, self.url(segments));
+        if let (Some(owner), Some(&"book")) = (&self.owner, segments.first()) {
+            request = request.query(&[("owner", owner)]);
+        }
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn get(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::GET, segments)
+    }
+
+    fn post(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::POST, segments)
+    }
+
+    fn patch(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::PATCH, segments)
+    }
+
+    fn delete(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::DELETE, segments)
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.bytes().await?;
+        let error = serde_json::from_slice::<ApiError>(&body).unwrap_or_else(|_| ApiError {
+            code: code_of_status(status),
+            message: String::from_utf8_lossy(&body).into_owned(),
+            details: vec![],
+        });
+        Err(ClientError::Api(error))
+    }
+
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    async fn text(request: RequestBuilder) -> Result<String> {
+        Ok(Self::send(request).await?.text().await?)
+    }
+
+    async fn empty(request: RequestBuilder) -> Result<()> {
+        Self::send(request).await?;
+        Ok(())
+    }
+
+    async fn tagged<T: DeserializeOwned>(request: RequestBuilder) -> Result<Tagged<T>> {
+        let response = Self::send(request).await?;
+        let etag = etag_of(&response);
+        Ok(Tagged {
+            value: response.json().await?,
+            etag,
+        })
+    }
+
+    /// Sends a change, expecting the entity to still have the tag `if_match`. Returns the tag of
+    /// the changed entity.
+    async fn change(request: RequestBuilder, if_match: Option<&str>) -> Result<Option<String>> {
+        let request = match if_match {
+            Some(tag) => request.header(IF_MATCH, tag),
+            None => request,
+        };
+        let response = Self::send(request).await?;
+        Ok(etag_of(&response))
+    }
+
+    /// Fetches the pages of a list one after another until one comes back short.
+    async fn all_pages<T, F, Fut>(page_size: i64, mut fetch: F) -> Result<Vec<T>>
+    where
+        F: FnMut(Page) -> Fut,
+        Fut: Future<Output = Result<Vec<T>>>,
+    {
+        let mut all = vec![];
+        loop {
+            let page = fetch(Page {
+                offset: Some(all.len() as i64),
+                limit: Some(page_size),
+            })
+            .await?;
+            let complete = (page.len() as i64) < page_size;
+            all.extend(page);
+            if complete {
+                return Ok(all);
+            }
+        }
+    }
+
+    pub async fn hello(&self) -> Result<String> {
+        Self::text(self.get(&[""])).await
+    }
+
+    pub async fn login(&self, login: &Login) -> Result<CreatedToken> {
+        Self::json(self.post(&["login"]).json(login)).await
+    }
+
+    pub async fn change_password(&self, change: &PasswordChange) -> Result<()> {
+        Self::empty(self.post(&["password"]).json(change)).await
+    }
+
+    pub async fn change_user_password(
+        &self,
+        user_name: &str,
+        new_password: &NewPassword,
+    ) -> Result<()> {
+        Self::empty(
+            self.post(&["user", user_name, "password"])
+                .json(new_password),
+        )
+        .await
+    }
+
+    pub async fn get_tokens(&self) -> Result<Vec<Token>> {
+        Self::json(self.get(&["tokens", ""])).await
+    }
+
+    pub async fn create_token(&self, new_token: &NewToken) -> Result<CreatedToken> {
+        Self::json(self.post(&["token", ""]).json(new_token)).await
+    }
+
+    pub async fn revoke_token(&self, token_id: i64) -> Result<()> {
+        Self::empty(self.delete(&["token", &token_id.to_string()])).await
+    }
+
+    pub async fn get_users(&self) -> Result<Vec<User>> {
+        Self::json(self.get(&["users", ""])).await
+    }
+
+    pub async fn get_user(&self, user_name: &str) -> Result<User> {
+        Self::json(self.get(&["user", user_name])).await
+    }
+
+    /// Creates the user along with a first token for it.
+    pub async fn create_user(&self, new_user: &NewUser) -> Result<CreatedToken> {
+        Self::json(self.post(&["user", ""]).json(new_user)).await
+    }
+
+    pub async fn update_user(&self, user_name: &str, user: &User) -> Result<()> {
+        Self::empty(self.post(&["user", user_name, "update"]).json(user)).await
+    }
+
+    pub async fn create_user_token(
+        &self,
+        user_name: &str,
+        new_token: &NewToken,
+    ) -> Result<CreatedToken> {
+        Self::json(self.post(&["user", user_name, "token"]).json(new_token)).await
+    }
+
+    pub async fn delete_user(&self, user_name: &str) -> Result<()> {
+        Self::empty(self.delete(&["user", user_name])).await
+    }
+
+    pub async fn get_books(&self) -> Result<Vec<String>> {
+        Self::json(self.get(&["books", ""])).await
+    }
+
+    pub async fn get_book(&self, book_name: &str) -> Result<Tagged<Book>> {
+        Self::tagged(self.get(&["book", book_name])).await
+    }
+
+    pub async fn create_book(&self, book: &Book) -> Result<()> {
+        Self::empty(self.post(&["book", ""]).json(book)).await
+    }
+
+    pub async fn update_book(
+        &self,
+        book_name: &str,
+        book: &Book,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(
+            self.post(&["book", book_name, "update"]).json(book),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn patch_book(
+        &self,
+        book_name: &str,
+        patch: &BookPatch,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(self.patch(&["book", book_name]).json(patch), if_match).await
+    }
+
+    pub async fn delete_book(&self, book_name: &str) -> Result<()> {
+        Self::empty(self.delete(&["book", book_name])).await
+    }
+
+    pub async fn get_book_trash(&self) -> Result<Vec<TrashEntry>> {
+        Self::json(self.get(&["books", "trash"])).await
+    }
+
+    pub async fn restore_book(&self, book_name: &str) -> Result<()> {
+        Self::empty(self.post(&["books", "trash", book_name, "restore"])).await
+    }
+
+    /// Returns the closing transaction, if one was booked.
+    pub async fn close_period(
+        &self,
+        book_name: &str,
+        period_close: &PeriodClose,
+    ) -> Result<Option<i64>> {
+        Self::json(self.post(&["book", book_name, "close"]).json(period_close)).await
+    }
+
+    pub async fn get_members(&self, book_name: &str) -> Result<Vec<BookMember>> {
+        Self::json(self.get(&["book", book_name, "members"])).await
+    }
+
+    pub async fn add_member(&self, book_name: &str, member: &BookMember) -> Result<()> {
+        Self::empty(self.post(&["book", book_name, "member", ""]).json(member)).await
+    }
+
+    pub async fn update_member(
+        &self,
+        book_name: &str,
+        user_name: &str,
+        member: &BookMember,
+    ) -> Result<()> {
+        Self::empty(
+            self.post(&["book", book_name, "member", user_name, "update"])
+                .json(member),
+        )
+        .await
+    }
+
+    pub async fn remove_member(&self, book_name: &str, user_name: &str) -> Result<()> {
+        Self::empty(self.delete(&["book", book_name, "member", user_name])).await
+    }
+
+    pub async fn leave_book(&self, book_name: &str) -> Result<()> {
+        Self::empty(self.delete(&["book", book_name, "membership"])).await
+    }
+
+    pub async fn get_audit_log(&self, book_name: &str, page: Page) -> Result<Vec<AuditEntry>> {
+        Self::json(self.get(&["book", book_name, "audit"]).query(&page)).await
+    }
+
+    /// The whole audit log, fetched `page_size` entries at a time.
+    pub async fn all_audit_entries(
+        &self,
+        book_name: &str,
+        page_size: i64,
+    ) -> Result<Vec<AuditEntry>> {
+        Self::all_pages(page_size, |page| self.get_audit_log(book_name, page)).await
+    }
+
+    pub async fn get_entity_audit_log(
+        &self,
+        book_name: &str,
+        entity: AuditEntity,
+        entity_id: &str,
+    ) -> Result<Vec<AuditEntry>> {
+        Self::json(self.get(&["book", book_name, "audit", entity.as_str(), entity_id])).await
+    }
+
+    pub async fn verify_audit_log(&self, book_name: &str) -> Result<AuditVerification> {
+        Self::json(self.get(&["book", book_name, "audit", "verify"])).await
+    }
+
+    pub async fn get_trash(&self, book_name: &str) -> Result<Vec<TrashEntry>> {
+        Self::json(self.get(&["book", book_name, "trash"])).await
+    }
+
+    pub async fn restore(
+        &self,
+        book_name: &str,
+        entity: AuditEntity,
+        entity_id: &str,
+    ) -> Result<()> {
+        Self::empty(self.post(&[
+            "book",
+            book_name,
+            "trash",
+            entity.as_str(),
+            entity_id,
+            "restore",
+        ]))
+        .await
+    }
+
+    pub async fn get_currencies(&self, book_name: &str) -> Result<Vec<String>> {
+        Self::json(self.get(&["book", book_name, "currencies"])).await
+    }
+
+    pub async fn get_currency(&self, book_name: &str, symbol: &str) -> Result<Tagged<Currency>> {
+        Self::tagged(self.get(&["book", book_name, "currency", symbol])).await
+    }
+
+    pub async fn create_currency(&self, book_name: &str, currency: &Currency) -> Result<()> {
+        Self::empty(
+            self.post(&["book", book_name, "currency", ""])
+                .json(currency),
+        )
+        .await
+    }
+
+    pub async fn update_currency(
+        &self,
+        book_name: &str,
+        symbol: &str,
+        currency: &Currency,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(
+            self.post(&["book", book_name, "currency", symbol, "update"])
+                .json(currency),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn patch_currency(
+        &self,
+        book_name: &str,
+        symbol: &str,
+        patch: &CurrencyPatch,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(
+            self.patch(&["book", book_name, "currency", symbol])
+                .json(patch),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn delete_currency(
+        &self,
+        book_name: &str,
+        symbol: &str,
+        options: &DeleteOptions,
+    ) -> Result<()> {
+        Self::empty(
+            self.delete(&["book", book_name, "currency", symbol])
+                .query(options),
+        )
+        .await
+    }
+
+    pub async fn merge_currency(&self, book_name: &str, symbol: &str, merge: &Merge) -> Result<()> {
+        Self::empty(
+            self.post(&["book", book_name, "currency", symbol, "merge"])
+                .json(merge),
+        )
+        .await
+    }
+
+    pub async fn get_accounts(&self, book_name: &str) -> Result<Vec<String>> {
+        Self::json(self.get(&["book", book_name, "accounts"])).await
+    }
+
+    pub async fn get_account(
+        &self,
+        book_name: &str,
+        account_name: &str,
+    ) -> Result<Tagged<Account>> {
+        Self::tagged(self.get(&["book", book_name, "account", account_name])).await
+    }
+
+    pub async fn create_account(&self, book_name: &str, account: &Account) -> Result<()> {
+        Self::empty(self.post(&["book", book_name, "account", ""]).json(account)).await
+    }
+
+    pub async fn update_account(
+        &self,
+        book_name: &str,
+        account_name: &str,
+        account: &Account,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(
+            self.post(&["book", book_name, "account", account_name, "update"])
+                .json(account),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn patch_account(
+        &self,
+        book_name: &str,
+        account_name: &str,
+        patch: &AccountPatch,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        Self::change(
+            self.patch(&["book", book_name, "account", account_name])
+                .json(patch),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn delete_account(
+        &self,
+        book_name: &str,
+        account_name: &str,
+        options: &DeleteOptions,
+    ) -> Result<()> {
+        Self::empty(
+            self.delete(&["book", book_name, "account", account_name])
+                .query(options),
+        )
+        .await
+    }
+
+    pub async fn merge_account(
+        &self,
+        book_name: &str,
+        account_name: &str,
+        merge: &Merge,
+    ) -> Result<()> {
+        Self::empty(
+            self.post(&["book", book_name, "account", account_name, "merge"])
+                .json(merge),
+        )
+        .await
+    }
+
+    pub async fn account_value(
+        &self,
+        book_name: &str,
+        account_name: &str,
+    ) -> Result<Vec<CurrencyAmount>> {
+        Self::json(self.get(&["book", book_name, "account", account_name, "value"])).await
+    }
+
+    /// Value of the account without budget postings.
+    pub async fn real_account_value(
+        &self,
+        book_name: &str,
+        account_name: &str,
+    ) -> Result<Vec<CurrencyAmount>> {
+        Self::json(self.get(&["book", book_name, "account", account_name, "real_value"])).await
+    }
+
+    /// Postings to the account along with their transactions, in the order of the journal.
+    pub async fn account_register(
+        &self,
+        book_name: &str,
+        account_name: &str,
+    ) -> Result<Vec<RegisterEntry>> {
+        Self::json(self.get(&["book", book_name, "account", account_name, "register"])).await
+    }
+
+    /// Balance of the accounts of the report at the end of each of its periods.
+    pub async fn balance_report(
+        &self,
+        book_name: &str,
+        query: &ReportQuery,
+    ) -> Result<Vec<BalancePoint>> {
+        Self::json(
+            self.get(&["book", book_name, "report", "balances"])
+                .query(query),
+        )
+        .await
+    }
+
+    /// Sums of the postings in each period of the report.
+    pub async fn flow_report(
+        &self,
+        book_name: &str,
+        query: &ReportQuery,
+    ) -> Result<Vec<FlowAmount>> {
+        Self::json(
+            self.get(&["book", book_name, "report", "flows"])
+                .query(query),
+        )
+        .await
+    }
+
+    pub async fn get_transactions(&self, book_name: &str, page: Page) -> Result<Vec<i64>> {
+        Self::json(self.get(&["book", book_name, "transactions"]).query(&page)).await
+    }
+
+    /// Ids of all transactions of the book, fetched `page_size` at a time.
+    pub async fn all_transactions(&self, book_name: &str, page_size: i64) -> Result<Vec<i64>> {
+        Self::all_pages(page_size, |page| self.get_transactions(book_name, page)).await
+    }
+
+    pub async fn get_transaction(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+    ) -> Result<Tagged<Transaction>> {
+        Self::tagged(self.get(&[
+            "book",
+            book_name,
+            "transaction",
+            &transaction_id.to_string(),
+        ]))
+        .await
+    }
+
+    /// Returns the id of the new transaction.
+    pub async fn create_transaction(
+        &self,
+        book_name: &str,
+        transaction: &NewTransaction,
+    ) -> Result<i64> {
+        Self::json(
+            self.post(&["book", book_name, "transaction"])
+                .json(transaction),
+        )
+        .await
+    }
+
+    /// Like [`Self::create_transaction`], but retrying with the same `key` returns the id of the
+    /// transaction created the first time instead of creating another one.
+    pub async fn create_transaction_once(
+        &self,
+        book_name: &str,
+        transaction: &NewTransaction,
+        key: &str,
+    ) -> Result<i64> {
+        Self::json(
+            self.post(&["book", book_name, "transaction"])
+                .header(IDEMPOTENCY_KEY, key)
+                .json(transaction),
+        )
+        .await
+    }
+
+    pub async fn update_transaction(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        transaction: &Transaction,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        let transaction_id = transaction_id.to_string();
+        Self::change(
+            self.post(&["book", book_name, "transaction", &transaction_id, "update"])
+                .json(transaction),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn patch_transaction(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        patch: &TransactionPatch,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        let transaction_id = transaction_id.to_string();
+        Self::change(
+            self.patch(&["book", book_name, "transaction", &transaction_id])
+                .json(patch),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn delete_transaction(&self, book_name: &str, transaction_id: i64) -> Result<()> {
+        Self::empty(self.delete(&[
+            "book",
+            book_name,
+            "transaction",
+            &transaction_id.to_string(),
+        ]))
+        .await
+    }
+
+    pub async fn post_transaction(&self, book_name: &str, transaction_id: i64) -> Result<()> {
+        let transaction_id = transaction_id.to_string();
+        Self::empty(self.post(&["book", book_name, "transaction", &transaction_id, "post"])).await
+    }
+
+    /// Returns the id of the reversing transaction.
+    pub async fn reverse_transaction(&self, book_name: &str, transaction_id: i64) -> Result<i64> {
+        let transaction_id = transaction_id.to_string();
+        Self::json(self.post(&["book", book_name, "transaction", &transaction_id, "reverse"])).await
+    }
+
+    pub async fn get_postings(&self, book_name: &str, transaction_id: i64) -> Result<Vec<i64>> {
+        let transaction_id = transaction_id.to_string();
+        Self::json(self.get(&[
+            "book",
+            book_name,
+            "transaction",
+            &transaction_id,
+            "postings",
+        ]))
+        .await
+    }
+
+    pub async fn get_posting(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting_id: i64,
+    ) -> Result<Tagged<Posting>> {
+        let (transaction_id, posting_id) = (transaction_id.to_string(), posting_id.to_string());
+        Self::tagged(self.get(&[
+            "book",
+            book_name,
+            "transaction",
+            &transaction_id,
+            "posting",
+            &posting_id,
+        ]))
+        .await
+    }
+
+    /// Returns the id of the new posting.
+    pub async fn create_posting(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting: &NewPosting,
+    ) -> Result<i64> {
+        let transaction_id = transaction_id.to_string();
+        Self::json(
+            self.post(&["book", book_name, "transaction", &transaction_id, "posting"])
+                .json(posting),
+        )
+        .await
+    }
+
+    /// Like [`Self::create_posting`], but retrying with the same `key` returns the id of the
+    /// posting created the first time.
+    pub async fn create_posting_once(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting: &NewPosting,
+        key: &str,
+    ) -> Result<i64> {
+        let transaction_id = transaction_id.to_string();
+        Self::json(
+            self.post(&["book", book_name, "transaction", &transaction_id, "posting"])
+                .header(IDEMPOTENCY_KEY, key)
+                .json(posting),
+        )
+        .await
+    }
+
+    pub async fn update_posting(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting: &Posting,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        let (transaction_id, posting_id) = (transaction_id.to_string(), posting.id.to_string());
+        Self::change(
+            self.post(&[
+                "book",
+                book_name,
+                "transaction",
+                &transaction_id,
+                "posting",
+                &posting_id,
+                "update",
+            ])
+            .json(posting),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn patch_posting(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting_id: i64,
+        patch: &PostingPatch,
+        if_match: Option<&str>,
+    ) -> Result<Option<String>> {
+        let (transaction_id, posting_id) = (transaction_id.to_string(), posting_id.to_string());
+        Self::change(
+            self.patch(&[
+                "book",
+                book_name,
+                "transaction",
+                &transaction_id,
+                "posting",
+                &posting_id,
+            ])
+            .json(patch),
+            if_match,
+        )
+        .await
+    }
+
+    pub async fn delete_posting(
+        &self,
+        book_name: &str,
+        transaction_id: i64,
+        posting_id: i64,
+    ) -> Result<()> {
+        let (transaction_id, posting_id) = (transaction_id.to_string(), posting_id.to_string());
+        Self::empty(self.delete(&[
+            "book",
+            book_name,
+            "transaction",
+            &transaction_id,
+            "posting",
+            &posting_id,
+        ]))
+        .await
+    }
+}
+
+fn etag_of(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Code for errors that did not come with a body, such as from a proxy in front of the server.
+fn code_of_status(status: StatusCode) -> ErrorCode {
+    match status {
+        StatusCode::UNAUTHORIZED => ErrorCode::NotAuthenticated,
+        StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+        StatusCode::NOT_FOUND => ErrorCode::NotFound,
+        StatusCode::CONFLICT => ErrorCode::Conflict,
+        StatusCode::PRECONDITION_FAILED => ErrorCode::PreconditionFailed,
+        status if status.is_client_error() => ErrorCode::InvalidInput,
+        _ => ErrorCode::Internal,
+    }
+}
@@ -1,10 +1,34 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize)]
+pub mod amount;
+pub mod schema;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Book {
     pub name: String,
     pub description: Option<String>,
+    /// Posted transactions of immutable books can only be corrected by reversing them. Once
+    /// enabled, this cannot be turned off again.
+    pub immutable: Option<bool>,
+    /// Read-only, transactions and postings dated before it can no longer be changed.
+    pub lock_date: Option<NaiveDateTime>,
+}
+
+/// Closes all periods of a book before `lock_date`.
+#[derive(Serialize, Deserialize)]
+pub struct PeriodClose {
+    pub lock_date: NaiveDateTime,
+    /// Equity account receiving the balances of `closing_accounts`, which usually are the income
+    /// and expense accounts. Without it no closing transaction is booked.
+    pub retained_earnings: Option<String>,
+    #[serde(default)]
+    pub closing_accounts: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -20,20 +44,26 @@ pub struct Account {
     pub description: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Transaction {
     pub id: i64,
     pub time: Option<NaiveDateTime>,
     pub description: Option<String>,
+    /// Read-only, see the post route of transactions.
+    pub posted: Option<bool>,
+    /// Read-only, the transaction this one reverses.
+    pub reverses: Option<i64>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct NewTransaction {
     pub description: Option<String>,
     pub time: Option<NaiveDateTime>,
+    /// Defaults to a draft, which stays editable in immutable books until it is posted.
+    pub posted: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Posting {
     pub id: i64,
     pub valuta: Option<NaiveDateTime>,
@@ -43,7 +73,7 @@ pub struct Posting {
     pub budget: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct NewPosting {
     pub valuta: Option<NaiveDateTime>,
     pub account_name: String,
@@ -52,8 +82,547 @@ pub struct NewPosting {
     pub budget: bool,
 }
 
+/// Query parameters for deleting an account or currency. Deleting one that postings still use
+/// fails, unless they are reassigned to another account or currency of the book.
+#[derive(Serialize, Deserialize, Default)]
+pub struct DeleteOptions {
+    pub reassign_to: Option<String>,
+}
+
+/// Moves all postings of an account or currency to `into` and deletes it.
+#[derive(Serialize, Deserialize)]
+pub struct Merge {
+    pub into: String,
+}
+
+/// Query parameters of lists that are fetched in pages. Without a limit the rest of the list
+/// after `offset` is returned.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub struct Page {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Tells a field set to null (`Some(None)`) apart from a field that is left out (`None`).
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Partial update of a book, fields that are left out keep their value.
+#[derive(Serialize, Deserialize, Default)]
+pub struct BookPatch {
+    pub name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+    pub immutable: Option<bool>,
+}
+
+impl BookPatch {
+    pub fn apply(self, book: &mut Book) {
+        if let Some(name) = self.name {
+            book.name = name;
+        }
+        if let Some(description) = self.description {
+            book.description = description;
+        }
+        if let Some(immutable) = self.immutable {
+            book.immutable = Some(immutable);
+        }
+    }
+}
+
+/// Partial update of a currency, fields that are left out keep their value.
+#[derive(Serialize, Deserialize, Default)]
+pub struct CurrencyPatch {
+    pub symbol: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+    pub decimal_points: Option<i32>,
+}
+
+impl CurrencyPatch {
+    pub fn apply(self, currency: &mut Currency) {
+        if let Some(symbol) = self.symbol {
+            currency.symbol = symbol;
+        }
+        if let Some(description) = self.description {
+            currency.description = description;
+        }
+        if let Some(decimal_points) = self.decimal_points {
+            currency.decimal_points = decimal_points;
+        }
+    }
+}
+
+/// Partial update of an account, fields that are left out keep their value.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AccountPatch {
+    pub name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+}
+
+impl AccountPatch {
+    pub fn apply(self, account: &mut Account) {
+        if let Some(name) = self.name {
+            account.name = name;
+        }
+        if let Some(description) = self.description {
+            account.description = description;
+        }
+    }
+}
+
+/// Partial update of a transaction, fields that are left out keep their value.
+#[derive(Serialize, Deserialize, Default)]
+pub struct TransactionPatch {
+    pub time: Option<NaiveDateTime>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+}
+
+impl TransactionPatch {
+    pub fn apply(self, transaction: &mut Transaction) {
+        if let Some(time) = self.time {
+            transaction.time = Some(time);
+        }
+        if let Some(description) = self.description {
+            transaction.description = description;
+        }
+    }
+}
+
+/// Partial update of a posting, fields that are left out keep their value.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PostingPatch {
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub valuta: Option<Option<NaiveDateTime>>,
+    pub account_name: Option<String>,
+    pub currency: Option<String>,
+    pub amount: Option<i32>,
+    pub budget: Option<bool>,
+}
+
+impl PostingPatch {
+    pub fn apply(self, posting: &mut Posting) {
+        if let Some(valuta) = self.valuta {
+            posting.valuta = valuta;
+        }
+        if let Some(account_name) = self.account_name {
+            posting.account_name = account_name;
+        }
+        if let Some(currency) = self.currency {
+            posting.currency = currency;
+        }
+        if let Some(amount) = self.amount {
+            posting.amount = amount;
+        }
+        if let Some(budget) = self.budget {
+            posting.budget = Some(budget);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CurrencyAmount {
     pub currency: String,
     pub amount: Option<i64>,
 }
+
+/// A posting to an account along with its transaction, see the register route of accounts.
+#[derive(Serialize, Deserialize)]
+pub struct RegisterEntry {
+    pub transaction: Transaction,
+    pub posting: Posting,
+}
+
+/// Length of the periods of a report.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    Day,
+    /// Starting on Mondays.
+    Week,
+    #[default]
+    Month,
+    Year,
+}
+
+/// Query parameters of reports. Postings count at their valuta, or else at the time of their
+/// transaction, and postings at neither are left out.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ReportQuery {
+    /// Start of the first period, by default eleven periods before the current one.
+    pub from: Option<NaiveDateTime>,
+    /// End of the last period, by default the end of the current one.
+    pub to: Option<NaiveDateTime>,
+    pub interval: Option<Interval>,
+    /// Only this account and the accounts below it, like `Expenses` for `Expenses:Food`.
+    pub account: Option<String>,
+    /// Levels of account names to sum up by, 1 by default, which sums `Expenses:Food` up
+    /// with the rest of `Expenses`.
+    pub depth: Option<usize>,
+    /// Whether to count budget postings, which reports leave out by default.
+    pub budget: Option<bool>,
+}
+
+/// Balance in a currency at the end of a period, see the balances report.
+#[derive(Serialize, Deserialize)]
+pub struct BalancePoint {
+    /// Start of the period.
+    pub period: NaiveDateTime,
+    pub currency: String,
+    pub balance: i64,
+}
+
+/// Sum of the postings to accounts in a period, see the flows report.
+#[derive(Serialize, Deserialize)]
+pub struct FlowAmount {
+    /// Start of the period.
+    pub period: NaiveDateTime,
+    /// Account name cut off after the levels of the depth of the report.
+    pub account_name: String,
+    pub currency: String,
+    pub amount: i64,
+}
+
+/// Role of a member in a shared book. Each role includes the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BookRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl BookRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+impl Display for BookRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BookRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            _ => Err(format!("Unknown book role '{}'.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BookMember {
+    pub user_name: String,
+    pub role: BookRole,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub admin: bool,
+    pub disabled: bool,
+    /// Subject of the OpenID Connect identity the user logs in with. An update with `None`
+    /// unlinks the identity.
+    pub oidc_subject: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewUser {
+    pub name: String,
+    pub admin: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Login {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewPassword {
+    pub password: String,
+}
+
+/// Changes the password of the user of the token. It takes the current password, unless the user
+/// has none yet, so that a leaked session token cannot lock its user out.
+#[derive(Serialize, Deserialize)]
+pub struct PasswordChange {
+    pub current_password: Option<String>,
+    pub password: String,
+}
+
+/// What a token is allowed to do. Each capability includes the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Read,
+    WriteTransactions,
+    Admin,
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::WriteTransactions => "write_transactions",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write_transactions" => Ok(Self::WriteTransactions),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("Unknown capability '{}'.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Token {
+    pub id: i64,
+    pub name: String,
+    pub created: NaiveDateTime,
+    pub expires: Option<NaiveDateTime>,
+    pub last_used: Option<NaiveDateTime>,
+    pub revoked: bool,
+    pub capability: Capability,
+    /// Books the token is limited to. `None` grants access to all books of the user.
+    pub books: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewToken {
+    pub name: String,
+    pub expires: Option<NaiveDateTime>,
+    /// Defaults to [`Capability::Admin`].
+    pub capability: Option<Capability>,
+    /// Defaults to all books.
+    pub books: Option<Vec<String>>,
+}
+
+/// Returned once when a token is created. The secret is not stored by the server and cannot be
+/// queried again.
+#[derive(Serialize, Deserialize)]
+pub struct CreatedToken {
+    pub token: Token,
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntity {
+    Book,
+    Member,
+    Currency,
+    Account,
+    Transaction,
+    Posting,
+}
+
+impl AuditEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Book => "book",
+            Self::Member => "member",
+            Self::Currency => "currency",
+            Self::Account => "account",
+            Self::Transaction => "transaction",
+            Self::Posting => "posting",
+        }
+    }
+}
+
+impl Display for AuditEntity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditEntity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "book" => Ok(Self::Book),
+            "member" => Ok(Self::Member),
+            "currency" => Ok(Self::Currency),
+            "account" => Ok(Self::Account),
+            "transaction" => Ok(Self::Transaction),
+            "posting" => Ok(Self::Posting),
+            _ => Err(format!("Unknown audit entity '{}'.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+        }
+    }
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            "restore" => Ok(Self::Restore),
+            _ => Err(format!("Unknown audit action '{}'.", s)),
+        }
+    }
+}
+
+/// One change to a book. `before` and `after` hold the entity as returned by the API.
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub time: NaiveDateTime,
+    pub actor: String,
+    pub token_id: Option<i64>,
+    pub entity: AuditEntity,
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    /// Hash over this entry and the hash of the previous entry of the book.
+    pub hash: String,
+}
+
+/// An entity of a book, or a book itself, that was deleted and can be restored until it is purged.
+/// Until then it keeps its name: creating or renaming another one to that name is refused with
+/// [`ErrorCode::Conflict`].
+#[derive(Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub entity: AuditEntity,
+    pub entity_id: String,
+    pub deleted_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditVerification {
+    pub entries: usize,
+    /// The first entry whose hash does not match its content, if the log was tampered with.
+    pub first_invalid_entry: Option<i64>,
+}
+
+/// Stable, machine-readable kind of an [`ApiError`]. Clients match on these rather than on the
+/// message, which may change.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed or breaks a rule, see the details for the offending fields.
+    InvalidInput,
+    /// No valid credentials were sent.
+    NotAuthenticated,
+    /// The credentials do not allow the request.
+    Forbidden,
+    NotFound,
+    AlreadyExists,
+    /// An account or currency is still used by postings.
+    InUse,
+    /// A posted transaction of an immutable book, which can only be reversed.
+    TransactionFinal,
+    /// A change dated before the lock date of the book.
+    PeriodClosed,
+    /// The request conflicts with the current state in another way.
+    Conflict,
+    /// The entity changed since the `If-Match` tag was read.
+    PreconditionFailed,
+    Internal,
+}
+
+/// A field of the request that is invalid.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of every error response of the server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
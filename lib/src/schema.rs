@@ -0,0 +1,381 @@
+//! JSON schemas of the types of the API, from which the server builds its OpenAPI document.
+
+use crate::*;
+use chrono::NaiveDateTime;
+pub use serde_json::{json, Map, Value};
+
+/// Named schemas, which other schemas point to with `$ref`.
+pub type Components = Map<String, Value>;
+
+pub trait Schema {
+    /// Name of the schema in the components, `None` to inline it wherever it is used.
+    const NAME: Option<&'static str> = None;
+    /// Whether a field of this type may be left out.
+    const OPTIONAL: bool = false;
+
+    /// The schema itself, adding the named schemas it uses to `components`.
+    fn schema(components: &mut Components) -> Value;
+
+    /// Points to the schema if it is named, otherwise returns it.
+    fn reference(components: &mut Components) -> Value {
+        let name = match Self::NAME {
+            Some(name) => name,
+            None => return Self::schema(components),
+        };
+        if !components.contains_key(name) {
+            // Reserves the name first, so types containing themselves end up pointing to it.
+            components.insert(name.to_owned(), Value::Null);
+            let schema = Self::schema(components);
+            components.insert(name.to_owned(), schema);
+        }
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+}
+
+/// Implements [`Schema`] for a struct from its fields. The fields have to match the struct, or
+/// the invocation fails to compile. Serde attributes cannot be checked the same way: fields serde
+/// may leave out, with `#[serde(default)]` or `skip_serializing_if`, are marked with `= default`,
+/// and the tests of the server check serialized values against the schemas.
+#[macro_export]
+macro_rules! object_schema {
+    ($type:ident { $($field:ident: $field_type:ty $(= $default:ident)?),* $(,)? }) => {
+        impl $crate::schema::Schema for $type {
+            const NAME: Option<&'static str> = Some(stringify!($type));
+
+            fn schema(components: &mut $crate::schema::Components) -> $crate::schema::Value {
+                let _ = |value: $type| {
+                    let $type { $($field),* } = value;
+                    $(let _: $field_type = $field;)*
+                };
+                let mut properties = $crate::schema::Map::new();
+                let mut required = vec![];
+                $(
+                    properties.insert(
+                        stringify!($field).to_owned(),
+                        <$field_type as $crate::schema::Schema>::reference(components),
+                    );
+                    let has_default = false $(|| stringify!($default) == "default")?;
+                    if !<$field_type as $crate::schema::Schema>::OPTIONAL && !has_default {
+                        required.push(stringify!($field));
+                    }
+                )*
+                $crate::schema::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                })
+            }
+        }
+    };
+}
+
+/// Implements [`Schema`] for an enum of unit variants, which serde writes as strings. All variants
+/// have to be listed, or the invocation fails to compile.
+#[macro_export]
+macro_rules! string_schema {
+    ($type:ident { $($variant:ident),* $(,)? }) => {
+        impl $crate::schema::Schema for $type {
+            const NAME: Option<&'static str> = Some(stringify!($type));
+
+            fn schema(_: &mut $crate::schema::Components) -> $crate::schema::Value {
+                let _ = |value: $type| match value {
+                    $($type::$variant => ()),*
+                };
+                let values: Vec<$crate::schema::Value> = vec![
+                    $($crate::schema::json!($type::$variant)),*
+                ];
+                $crate::schema::json!({ "type": "string", "enum": values })
+            }
+        }
+    };
+}
+
+impl Schema for () {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "null" })
+    }
+}
+
+impl Schema for String {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for bool {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl Schema for i32 {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "integer", "format": "int32" })
+    }
+}
+
+impl Schema for i64 {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "integer", "format": "int64" })
+    }
+}
+
+impl Schema for usize {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl Schema for NaiveDateTime {
+    fn schema(_: &mut Components) -> Value {
+        json!({
+            "type": "string",
+            "description": "Date and time in UTC without an offset, like 2023-06-01T12:00:00.",
+            "example": "2023-06-01T12:00:00",
+        })
+    }
+}
+
+impl Schema for Value {
+    fn schema(_: &mut Components) -> Value {
+        json!({})
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema(components: &mut Components) -> Value {
+        json!({ "type": "array", "items": T::reference(components) })
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    const OPTIONAL: bool = true;
+
+    fn schema(components: &mut Components) -> Value {
+        json!({ "anyOf": [T::reference(components), { "type": "null" }] })
+    }
+}
+
+object_schema!(Book {
+    name: String,
+    description: Option<String>,
+    immutable: Option<bool>,
+    lock_date: Option<NaiveDateTime>,
+});
+object_schema!(PeriodClose {
+    lock_date: NaiveDateTime,
+    retained_earnings: Option<String>,
+    closing_accounts: Vec<String> = default,
+});
+object_schema!(Currency {
+    symbol: String,
+    description: Option<String>,
+    decimal_points: i32,
+});
+object_schema!(Account {
+    name: String,
+    description: Option<String>,
+});
+object_schema!(Transaction {
+    id: i64,
+    time: Option<NaiveDateTime>,
+    description: Option<String>,
+    posted: Option<bool>,
+    reverses: Option<i64>,
+});
+object_schema!(NewTransaction {
+    description: Option<String>,
+    time: Option<NaiveDateTime>,
+    posted: Option<bool>,
+});
+object_schema!(Posting {
+    id: i64,
+    valuta: Option<NaiveDateTime>,
+    account_name: String,
+    currency: String,
+    amount: i32,
+    budget: Option<bool>,
+});
+object_schema!(NewPosting {
+    valuta: Option<NaiveDateTime>,
+    account_name: String,
+    currency: String,
+    amount: i32,
+    budget: bool,
+});
+object_schema!(DeleteOptions {
+    reassign_to: Option<String>,
+});
+object_schema!(Merge { into: String });
+object_schema!(Page {
+    offset: Option<i64>,
+    limit: Option<i64>,
+});
+object_schema!(BookPatch {
+    name: Option<String>,
+    description: Option<Option<String>>,
+    immutable: Option<bool>,
+});
+object_schema!(CurrencyPatch {
+    symbol: Option<String>,
+    description: Option<Option<String>>,
+    decimal_points: Option<i32>,
+});
+object_schema!(AccountPatch {
+    name: Option<String>,
+    description: Option<Option<String>>,
+});
+object_schema!(TransactionPatch {
+    time: Option<NaiveDateTime>,
+    description: Option<Option<String>>,
+});
+object_schema!(PostingPatch {
+    valuta: Option<Option<NaiveDateTime>>,
+    account_name: Option<String>,
+    currency: Option<String>,
+    amount: Option<i32>,
+    budget: Option<bool>,
+});
+object_schema!(CurrencyAmount {
+    currency: String,
+    amount: Option<i64>,
+});
+object_schema!(RegisterEntry {
+    transaction: Transaction,
+    posting: Posting,
+});
+string_schema!(Interval {
+    Day,
+    Week,
+    Month,
+    Year
+});
+object_schema!(ReportQuery {
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    interval: Option<Interval>,
+    account: Option<String>,
+    depth: Option<usize>,
+    budget: Option<bool>,
+});
+object_schema!(BalancePoint {
+    period: NaiveDateTime,
+    currency: String,
+    balance: i64,
+});
+object_schema!(FlowAmount {
+    period: NaiveDateTime,
+    account_name: String,
+    currency: String,
+    amount: i64,
+});
+string_schema!(BookRole {
+    Viewer,
+    Editor,
+    Owner
+});
+object_schema!(BookMember {
+    user_name: String,
+    role: BookRole,
+});
+object_schema!(User {
+    name: String,
+    admin: bool,
+    disabled: bool,
+    oidc_subject: Option<String>,
+});
+object_schema!(NewUser {
+    name: String,
+    admin: bool,
+});
+object_schema!(Login {
+    name: String,
+    password: String,
+});
+object_schema!(NewPassword { password: String });
+object_schema!(PasswordChange {
+    current_password: Option<String>,
+    password: String,
+});
+string_schema!(Capability {
+    Read,
+    WriteTransactions,
+    Admin
+});
+object_schema!(Token {
+    id: i64,
+    name: String,
+    created: NaiveDateTime,
+    expires: Option<NaiveDateTime>,
+    last_used: Option<NaiveDateTime>,
+    revoked: bool,
+    capability: Capability,
+    books: Option<Vec<String>>,
+});
+object_schema!(NewToken {
+    name: String,
+    expires: Option<NaiveDateTime>,
+    capability: Option<Capability>,
+    books: Option<Vec<String>>,
+});
+object_schema!(CreatedToken {
+    token: Token,
+    secret: String,
+});
+string_schema!(AuditEntity {
+    Book,
+    Member,
+    Currency,
+    Account,
+    Transaction,
+    Posting
+});
+string_schema!(AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore
+});
+object_schema!(AuditEntry {
+    id: i64,
+    time: NaiveDateTime,
+    actor: String,
+    token_id: Option<i64>,
+    entity: AuditEntity,
+    entity_id: String,
+    action: AuditAction,
+    before: Option<Value>,
+    after: Option<Value>,
+    hash: String,
+});
+object_schema!(TrashEntry {
+    entity: AuditEntity,
+    entity_id: String,
+    deleted_at: NaiveDateTime,
+});
+object_schema!(AuditVerification {
+    entries: usize,
+    first_invalid_entry: Option<i64>,
+});
+string_schema!(ErrorCode {
+    InvalidInput,
+    NotAuthenticated,
+    Forbidden,
+    NotFound,
+    AlreadyExists,
+    InUse,
+    TransactionFinal,
+    PeriodClosed,
+    Conflict,
+    PreconditionFailed,
+    Internal
+});
+object_schema!(FieldError {
+    field: String,
+    message: String,
+});
+object_schema!(ApiError {
+    code: ErrorCode,
+    message: String,
+    details: Vec<FieldError> = default,
+});
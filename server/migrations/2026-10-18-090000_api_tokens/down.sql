@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+-- Plaintext bearer tokens cannot be recovered from their hashes.
+ALTER TABLE users ADD COLUMN bearer VARCHAR(100) UNIQUE;
+DROP TABLE tokens;
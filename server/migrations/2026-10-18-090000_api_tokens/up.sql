@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+CREATE TABLE tokens
+(
+    id         BIGINT       NOT NULL,
+    user_name  VARCHAR(100) NOT NULL,
+    name       VARCHAR(100) NOT NULL,
+    token_hash VARCHAR(64)  NOT NULL UNIQUE,
+    created    TIMESTAMP    NOT NULL,
+    expires    TIMESTAMP    NULL,
+    last_used  TIMESTAMP    NULL,
+    revoked    BOOLEAN      NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id),
+    UNIQUE (user_name, name),
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO tokens (id, user_name, name, token_hash, created)
+SELECT ROW_NUMBER() OVER (ORDER BY name), name, 'default', SHA2(bearer, 256), CURRENT_TIMESTAMP
+FROM users
+WHERE bearer IS NOT NULL;
+
+ALTER TABLE users DROP COLUMN bearer;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE token_books;
+ALTER TABLE tokens DROP COLUMN capability;
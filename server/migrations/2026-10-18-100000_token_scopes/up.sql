@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+ALTER TABLE tokens ADD COLUMN capability VARCHAR(20) NOT NULL DEFAULT 'admin';
+
+CREATE TABLE token_books
+(
+    token_id  BIGINT       NOT NULL,
+    book_name VARCHAR(100) NOT NULL,
+    PRIMARY KEY (token_id, book_name),
+    FOREIGN KEY (token_id) REFERENCES tokens (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE users ADD COLUMN password_hash VARCHAR(255);
+ALTER TABLE users ADD COLUMN oidc_subject VARCHAR(255) UNIQUE;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+CREATE TABLE book_members
+(
+    book_name   VARCHAR(100) NOT NULL,
+    owner_name  VARCHAR(100) NOT NULL,
+    member_name VARCHAR(100) NOT NULL,
+    role        VARCHAR(20)  NOT NULL,
+    PRIMARY KEY (book_name, member_name),
+    FOREIGN KEY (book_name, owner_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (member_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO book_members (book_name, owner_name, member_name, role)
+SELECT name, user_name, user_name, 'owner'
+FROM books;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER audit_log_no_delete;
+DROP TABLE audit_log;
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+-- No foreign keys: entries must outlive the books, users and tokens they refer to.
+CREATE TABLE audit_log
+(
+    id            BIGINT       NOT NULL,
+    book_name     VARCHAR(100) NOT NULL,
+    owner_name    VARCHAR(100) NOT NULL,
+    time          TIMESTAMP    NOT NULL,
+    actor         VARCHAR(100) NOT NULL,
+    token_id      BIGINT,
+    entity        VARCHAR(20)  NOT NULL,
+    entity_id     VARCHAR(100) NOT NULL,
+    action        VARCHAR(20)  NOT NULL,
+    before_value  MEDIUMTEXT,
+    after_value   MEDIUMTEXT,
+    previous_hash VARCHAR(64),
+    hash          VARCHAR(64)  NOT NULL,
+    PRIMARY KEY (id),
+    INDEX (owner_name, book_name, entity, entity_id)
+);
+
+CREATE TRIGGER audit_log_no_delete
+    BEFORE DELETE
+    ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions DROP COLUMN reverses;
+ALTER TABLE transactions DROP COLUMN posted;
+ALTER TABLE books DROP COLUMN immutable;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE books ADD COLUMN immutable BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE transactions ADD COLUMN posted BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE transactions ADD COLUMN reverses BIGINT UNIQUE;
+UPDATE transactions SET posted = TRUE;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE books ADD COLUMN lock_date TIMESTAMP NULL;
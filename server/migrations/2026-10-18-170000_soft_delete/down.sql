@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM transactions WHERE deleted_at IS NOT NULL;
+DELETE FROM accounts WHERE deleted_at IS NOT NULL;
+DELETE FROM currencies WHERE deleted_at IS NOT NULL;
+DELETE FROM books WHERE deleted_at IS NOT NULL;
+ALTER TABLE transactions DROP COLUMN deleted_at;
+ALTER TABLE accounts DROP COLUMN deleted_at;
+ALTER TABLE currencies DROP COLUMN deleted_at;
+ALTER TABLE books DROP COLUMN deleted_at;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE books ADD COLUMN deleted_at TIMESTAMP NULL;
+ALTER TABLE currencies ADD COLUMN deleted_at TIMESTAMP NULL;
+ALTER TABLE accounts ADD COLUMN deleted_at TIMESTAMP NULL;
+ALTER TABLE transactions ADD COLUMN deleted_at TIMESTAMP NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE postings
+    ADD CONSTRAINT postings_ibfk_4 FOREIGN KEY (currency) REFERENCES currencies (symbol) ON DELETE CASCADE ON UPDATE CASCADE;
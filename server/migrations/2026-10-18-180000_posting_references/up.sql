@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+-- The key only covered the symbol, so deleting a currency removed the postings of every book
+-- using that symbol. The server checks the currency and account of postings within their book.
+ALTER TABLE postings DROP FOREIGN KEY postings_ibfk_4;
+
+-- Accounts used to be deleted without their postings.
+INSERT IGNORE INTO accounts (name, user_name, book_name)
+SELECT DISTINCT account_name, user_name, book_name
+FROM postings;
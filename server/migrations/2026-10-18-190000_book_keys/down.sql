@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE postings DROP FOREIGN KEY postings_book;
+ALTER TABLE postings
+    ADD CONSTRAINT postings_ibfk_2 FOREIGN KEY (book_name) REFERENCES books (name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE transactions DROP FOREIGN KEY transactions_book;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_ibfk_1 FOREIGN KEY (book_name) REFERENCES books (name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE currencies DROP FOREIGN KEY currencies_book;
+ALTER TABLE currencies
+    ADD CONSTRAINT currencies_ibfk_2 FOREIGN KEY (book_name) REFERENCES books (name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE accounts DROP FOREIGN KEY accounts_book;
+ALTER TABLE accounts
+    ADD CONSTRAINT accounts_ibfk_1 FOREIGN KEY (book_name) REFERENCES books (name) ON DELETE CASCADE ON UPDATE CASCADE;
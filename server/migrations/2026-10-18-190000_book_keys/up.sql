@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+-- Book names are only unique per owner, so renaming or removing a book must not touch the rows of
+-- books of other owners with the same name.
+ALTER TABLE accounts DROP FOREIGN KEY accounts_ibfk_1;
+ALTER TABLE accounts
+    ADD CONSTRAINT accounts_book FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE currencies DROP FOREIGN KEY currencies_ibfk_2;
+ALTER TABLE currencies
+    ADD CONSTRAINT currencies_book FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE transactions DROP FOREIGN KEY transactions_ibfk_1;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_book FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE postings DROP FOREIGN KEY postings_ibfk_2;
+ALTER TABLE postings
+    ADD CONSTRAINT postings_book FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE;
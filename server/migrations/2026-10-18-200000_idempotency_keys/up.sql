@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- Ids created by requests carrying an Idempotency-Key header. Retries with the same key get the
+-- id back instead of creating the entity again.
+CREATE TABLE idempotency_keys
+(
+    user_name       VARCHAR(100) NOT NULL,
+    idempotency_key VARCHAR(100) NOT NULL,
+    request_hash    VARCHAR(64)  NOT NULL,
+    created_id      BIGINT       NOT NULL,
+    created         TIMESTAMP    NOT NULL,
+    PRIMARY KEY (user_name, idempotency_key),
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Transaction ids are snowflakes and unique across books, as the schema of PostgreSQL requires.
+ALTER TABLE transactions ADD CONSTRAINT transactions_id UNIQUE (id);
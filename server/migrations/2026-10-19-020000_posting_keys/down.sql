@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE postings DROP FOREIGN KEY postings_account;
+ALTER TABLE postings DROP FOREIGN KEY postings_currency;
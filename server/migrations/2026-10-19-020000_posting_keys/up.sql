@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- Postings refer to an account and a currency of their own book. Renames move the postings along,
+-- deleting an account or currency that postings still use is refused.
+ALTER TABLE postings
+    ADD CONSTRAINT postings_account FOREIGN KEY (account_name, book_name, user_name) REFERENCES accounts (name, book_name, user_name) ON DELETE RESTRICT ON UPDATE CASCADE,
+    ADD CONSTRAINT postings_currency FOREIGN KEY (currency, book_name, user_name) REFERENCES currencies (symbol, book_name, user_name) ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER audit_log_no_update;
+
+ALTER TABLE audit_log
+    DROP INDEX audit_log_book,
+    DROP COLUMN book_id;
+
+ALTER TABLE books
+    DROP INDEX books_id,
+    DROP COLUMN id;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+-- Books get an id that stays the same when they are renamed, the audit log is kept under it. A
+-- book created under the name of a purged one starts a log of its own.
+ALTER TABLE books ADD COLUMN id BIGINT NULL;
+
+UPDATE books
+    JOIN (SELECT name, user_name, ROW_NUMBER() OVER (ORDER BY user_name, name) AS id FROM books) numbered
+    ON books.name = numbered.name AND books.user_name = numbered.user_name
+SET books.id = numbered.id;
+
+ALTER TABLE books
+    MODIFY id BIGINT NOT NULL,
+    ADD CONSTRAINT books_id UNIQUE (id);
+
+-- Entries of books purged before have no id.
+ALTER TABLE audit_log
+    ADD COLUMN book_id BIGINT NULL,
+    ADD INDEX audit_log_book (book_id, entity, entity_id);
+
+UPDATE audit_log
+    JOIN books ON audit_log.book_name = books.name AND audit_log.owner_name = books.user_name
+SET audit_log.book_id = books.id;
+
+CREATE TRIGGER audit_log_no_update
+    BEFORE UPDATE
+    ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE book_members
+    DROP PRIMARY KEY,
+    ADD PRIMARY KEY (book_name, member_name);
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE idempotency_keys;
+DROP TABLE audit_log;
+DROP FUNCTION audit_log_no_delete;
+DROP TABLE book_members;
+DROP TABLE token_books;
+DROP TABLE tokens;
+DROP TABLE postings;
+DROP TABLE transactions;
+DROP TABLE currencies;
+DROP TABLE accounts;
+DROP TABLE books;
+DROP TABLE users;
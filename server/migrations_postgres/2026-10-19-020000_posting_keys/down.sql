@@ -0,0 +1,2 @@
+ALTER TABLE postings DROP CONSTRAINT postings_account;
+ALTER TABLE postings DROP CONSTRAINT postings_currency;
@@ -0,0 +1,9 @@
+DROP TRIGGER audit_log_no_update ON audit_log;
+
+ALTER FUNCTION audit_log_append_only() RENAME TO audit_log_no_delete;
+
+DROP INDEX audit_log_book;
+
+ALTER TABLE audit_log DROP COLUMN book_id;
+
+ALTER TABLE books DROP COLUMN id;
@@ -0,0 +1,32 @@
+-- Books get an id that stays the same when they are renamed, the audit log is kept under it. A
+-- book created under the name of a purged one starts a log of its own.
+ALTER TABLE books ADD COLUMN id BIGINT;
+
+UPDATE books
+SET id = numbered.id
+FROM (SELECT name, user_name, ROW_NUMBER() OVER (ORDER BY user_name, name) AS id FROM books) numbered
+WHERE books.name = numbered.name
+  AND books.user_name = numbered.user_name;
+
+ALTER TABLE books
+    ALTER COLUMN id SET NOT NULL,
+    ADD CONSTRAINT books_id UNIQUE (id);
+
+-- Entries of books purged before have no id.
+ALTER TABLE audit_log ADD COLUMN book_id BIGINT;
+
+UPDATE audit_log
+SET book_id = books.id
+FROM books
+WHERE books.name = audit_log.book_name
+  AND books.user_name = audit_log.owner_name;
+
+CREATE INDEX audit_log_book ON audit_log (book_id, entity, entity_id);
+
+ALTER FUNCTION audit_log_no_delete() RENAME TO audit_log_append_only;
+
+CREATE TRIGGER audit_log_no_update
+    BEFORE UPDATE
+    ON audit_log
+    FOR EACH ROW
+EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,3 @@
+ALTER TABLE book_members
+    DROP CONSTRAINT book_members_pkey,
+    ADD PRIMARY KEY (book_name, member_name);
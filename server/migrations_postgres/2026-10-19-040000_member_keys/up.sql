@@ -0,0 +1,5 @@
+-- Book names are only unique per owner, so a user can be a member of several books with the same
+-- name. Memberships are keyed by the owner of the book along with its name.
+ALTER TABLE book_members
+    DROP CONSTRAINT book_members_pkey,
+    ADD PRIMARY KEY (book_name, owner_name, member_name);
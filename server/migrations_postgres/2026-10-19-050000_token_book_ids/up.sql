@@ -0,0 +1,31 @@
+-- Tokens are limited to books by their id, so a limit neither carries over to another book of the
+-- same name nor needs to follow renames. The ids of purged books are kept, which leaves the token
+-- limited to nothing rather than to all books, so there is no foreign key to the books.
+CREATE TABLE token_book_ids
+(
+    token_id BIGINT NOT NULL,
+    book_id  BIGINT NOT NULL,
+    PRIMARY KEY (token_id, book_id),
+    FOREIGN KEY (token_id) REFERENCES tokens (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- Until now a user could only reach one book of a name.
+INSERT INTO token_book_ids (token_id, book_id)
+SELECT DISTINCT token_books.token_id, books.id
+FROM token_books
+         JOIN tokens ON tokens.id = token_books.token_id
+         JOIN book_members
+              ON book_members.book_name = token_books.book_name AND book_members.member_name = tokens.user_name
+         JOIN books ON books.name = book_members.book_name AND books.user_name = book_members.owner_name;
+
+-- Tokens limited to books that are gone are revoked rather than losing their limit.
+UPDATE tokens
+SET revoked = TRUE
+WHERE id IN (SELECT token_id FROM token_books)
+  AND id NOT IN (SELECT token_id FROM token_book_ids);
+
+DROP TABLE token_books;
+
+ALTER TABLE token_book_ids RENAME TO token_books;
+
+ALTER TABLE token_books RENAME CONSTRAINT token_book_ids_pkey TO token_books_pkey;
@@ -0,0 +1,156 @@
+-- The schema reached by the MySQL migrations in ../../migrations, in one step. Changes to the
+-- schema need a migration in migrations_postgres as well.
+
+CREATE TABLE users
+(
+    name          VARCHAR(100) PRIMARY KEY NOT NULL,
+    admin         BOOLEAN      NOT NULL DEFAULT FALSE,
+    disabled      BOOLEAN      NOT NULL DEFAULT FALSE,
+    password_hash VARCHAR(255),
+    oidc_subject  VARCHAR(255) UNIQUE
+);
+
+CREATE TABLE books
+(
+    name        VARCHAR(100) NOT NULL,
+    user_name   VARCHAR(100) NOT NULL,
+    description VARCHAR(1000),
+    immutable   BOOLEAN      NOT NULL DEFAULT FALSE,
+    lock_date   TIMESTAMP,
+    deleted_at  TIMESTAMP,
+    PRIMARY KEY (name, user_name),
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE accounts
+(
+    name        VARCHAR(100) NOT NULL,
+    description VARCHAR(1000),
+    user_name   VARCHAR(100) NOT NULL,
+    book_name   VARCHAR(100) NOT NULL,
+    deleted_at  TIMESTAMP,
+    PRIMARY KEY (name, book_name, user_name),
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE currencies
+(
+    symbol         VARCHAR(10)  NOT NULL,
+    decimal_points INTEGER      NOT NULL,
+    description    VARCHAR(1000),
+    user_name      VARCHAR(100) NOT NULL,
+    book_name      VARCHAR(100) NOT NULL,
+    deleted_at     TIMESTAMP,
+    PRIMARY KEY (symbol, book_name, user_name),
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE transactions
+(
+    id          BIGINT       NOT NULL,
+    time        TIMESTAMP    NOT NULL,
+    description VARCHAR(1000),
+    book_name   VARCHAR(100) NOT NULL,
+    user_name   VARCHAR(100) NOT NULL,
+    posted      BOOLEAN      NOT NULL DEFAULT FALSE,
+    reverses    BIGINT UNIQUE,
+    deleted_at  TIMESTAMP,
+    PRIMARY KEY (id, book_name, user_name),
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- SQLite only accepts a foreign key to a unique set of columns, so postings refer to the whole key
+-- of their transaction.
+CREATE TABLE postings
+(
+    id             BIGINT       NOT NULL,
+    transaction_id BIGINT       NOT NULL,
+    valuta         TIMESTAMP,
+    book_name      VARCHAR(100) NOT NULL,
+    user_name      VARCHAR(100) NOT NULL,
+    account_name   VARCHAR(100) NOT NULL,
+    currency       VARCHAR(10)  NOT NULL,
+    amount         INTEGER      NOT NULL,
+    budget         BOOLEAN      NOT NULL,
+    PRIMARY KEY (id, transaction_id, book_name, user_name),
+    FOREIGN KEY (transaction_id, book_name, user_name) REFERENCES transactions (id, book_name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE tokens
+(
+    id         BIGINT       NOT NULL,
+    user_name  VARCHAR(100) NOT NULL,
+    name       VARCHAR(100) NOT NULL,
+    token_hash VARCHAR(64)  NOT NULL UNIQUE,
+    created    TIMESTAMP    NOT NULL,
+    expires    TIMESTAMP,
+    last_used  TIMESTAMP,
+    revoked    BOOLEAN      NOT NULL DEFAULT FALSE,
+    capability VARCHAR(20)  NOT NULL DEFAULT 'admin',
+    PRIMARY KEY (id),
+    UNIQUE (user_name, name),
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE token_books
+(
+    token_id  BIGINT       NOT NULL,
+    book_name VARCHAR(100) NOT NULL,
+    PRIMARY KEY (token_id, book_name),
+    FOREIGN KEY (token_id) REFERENCES tokens (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE book_members
+(
+    book_name   VARCHAR(100) NOT NULL,
+    owner_name  VARCHAR(100) NOT NULL,
+    member_name VARCHAR(100) NOT NULL,
+    role        VARCHAR(20)  NOT NULL,
+    PRIMARY KEY (book_name, member_name),
+    FOREIGN KEY (book_name, owner_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (member_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- No foreign keys: entries must outlive the books, users and tokens they refer to.
+CREATE TABLE audit_log
+(
+    id            BIGINT       NOT NULL,
+    book_name     VARCHAR(100) NOT NULL,
+    owner_name    VARCHAR(100) NOT NULL,
+    time          TIMESTAMP    NOT NULL,
+    actor         VARCHAR(100) NOT NULL,
+    token_id      BIGINT,
+    entity        VARCHAR(20)  NOT NULL,
+    entity_id     VARCHAR(100) NOT NULL,
+    action        VARCHAR(20)  NOT NULL,
+    before_value  TEXT,
+    after_value   TEXT,
+    previous_hash VARCHAR(64),
+    hash          VARCHAR(64)  NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX audit_log_entity ON audit_log (owner_name, book_name, entity, entity_id);
+
+CREATE TRIGGER audit_log_no_delete
+    BEFORE DELETE
+    ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only.');
+END;
+
+CREATE TABLE idempotency_keys
+(
+    user_name       VARCHAR(100) NOT NULL,
+    idempotency_key VARCHAR(100) NOT NULL,
+    request_hash    VARCHAR(64)  NOT NULL,
+    created_id      BIGINT       NOT NULL,
+    created         TIMESTAMP    NOT NULL,
+    PRIMARY KEY (user_name, idempotency_key),
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
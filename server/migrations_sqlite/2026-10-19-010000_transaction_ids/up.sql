@@ -0,0 +1,2 @@
+-- Transaction ids are snowflakes and unique across books, as the schema of PostgreSQL requires.
+CREATE UNIQUE INDEX transactions_id ON transactions (id);
@@ -0,0 +1,24 @@
+CREATE TABLE postings_without_keys
+(
+    id             BIGINT       NOT NULL,
+    transaction_id BIGINT       NOT NULL,
+    valuta         TIMESTAMP,
+    book_name      VARCHAR(100) NOT NULL,
+    user_name      VARCHAR(100) NOT NULL,
+    account_name   VARCHAR(100) NOT NULL,
+    currency       VARCHAR(10)  NOT NULL,
+    amount         INTEGER      NOT NULL,
+    budget         BOOLEAN      NOT NULL,
+    PRIMARY KEY (id, transaction_id, book_name, user_name),
+    FOREIGN KEY (transaction_id, book_name, user_name) REFERENCES transactions (id, book_name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO postings_without_keys
+SELECT id, transaction_id, valuta, book_name, user_name, account_name, currency, amount, budget
+FROM postings;
+
+DROP TABLE postings;
+
+ALTER TABLE postings_without_keys RENAME TO postings;
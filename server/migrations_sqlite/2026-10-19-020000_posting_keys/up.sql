@@ -0,0 +1,29 @@
+-- Postings refer to an account and a currency of their own book. Renames move the postings along,
+-- deleting an account or currency that postings still use is refused. SQLite cannot add foreign
+-- keys to a table, so the table is built again.
+CREATE TABLE postings_with_keys
+(
+    id             BIGINT       NOT NULL,
+    transaction_id BIGINT       NOT NULL,
+    valuta         TIMESTAMP,
+    book_name      VARCHAR(100) NOT NULL,
+    user_name      VARCHAR(100) NOT NULL,
+    account_name   VARCHAR(100) NOT NULL,
+    currency       VARCHAR(10)  NOT NULL,
+    amount         INTEGER      NOT NULL,
+    budget         BOOLEAN      NOT NULL,
+    PRIMARY KEY (id, transaction_id, book_name, user_name),
+    FOREIGN KEY (transaction_id, book_name, user_name) REFERENCES transactions (id, book_name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (book_name, user_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (user_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (account_name, book_name, user_name) REFERENCES accounts (name, book_name, user_name) ON DELETE RESTRICT ON UPDATE CASCADE,
+    FOREIGN KEY (currency, book_name, user_name) REFERENCES currencies (symbol, book_name, user_name) ON DELETE RESTRICT ON UPDATE CASCADE
+);
+
+INSERT INTO postings_with_keys
+SELECT id, transaction_id, valuta, book_name, user_name, account_name, currency, amount, budget
+FROM postings;
+
+DROP TABLE postings;
+
+ALTER TABLE postings_with_keys RENAME TO postings;
@@ -0,0 +1,9 @@
+DROP TRIGGER audit_log_no_update;
+
+DROP INDEX audit_log_book;
+
+ALTER TABLE audit_log DROP COLUMN book_id;
+
+DROP INDEX books_id;
+
+ALTER TABLE books DROP COLUMN id;
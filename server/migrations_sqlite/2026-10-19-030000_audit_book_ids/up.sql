@@ -0,0 +1,25 @@
+-- Books get an id that stays the same when they are renamed, the audit log is kept under it. A
+-- book created under the name of a purged one starts a log of its own.
+ALTER TABLE books ADD COLUMN id BIGINT NOT NULL DEFAULT 0;
+
+UPDATE books SET id = rowid;
+
+CREATE UNIQUE INDEX books_id ON books (id);
+
+-- Entries of books purged before have no id.
+ALTER TABLE audit_log ADD COLUMN book_id BIGINT;
+
+UPDATE audit_log
+SET book_id = (SELECT id
+               FROM books
+               WHERE books.name = audit_log.book_name
+                 AND books.user_name = audit_log.owner_name);
+
+CREATE INDEX audit_log_book ON audit_log (book_id, entity, entity_id);
+
+CREATE TRIGGER audit_log_no_update
+    BEFORE UPDATE
+    ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only.');
+END;
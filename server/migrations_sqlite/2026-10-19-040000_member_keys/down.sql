@@ -0,0 +1,18 @@
+CREATE TABLE book_members_by_name
+(
+    book_name   VARCHAR(100) NOT NULL,
+    owner_name  VARCHAR(100) NOT NULL,
+    member_name VARCHAR(100) NOT NULL,
+    role        VARCHAR(20)  NOT NULL,
+    PRIMARY KEY (book_name, member_name),
+    FOREIGN KEY (book_name, owner_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (member_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO book_members_by_name
+SELECT book_name, owner_name, member_name, role
+FROM book_members;
+
+DROP TABLE book_members;
+
+ALTER TABLE book_members_by_name RENAME TO book_members;
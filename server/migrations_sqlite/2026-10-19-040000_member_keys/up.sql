@@ -0,0 +1,21 @@
+-- Book names are only unique per owner, so a user can be a member of several books with the same
+-- name. Memberships are keyed by the owner of the book along with its name. SQLite cannot change
+-- the primary key of a table, so the table is built again.
+CREATE TABLE book_members_by_owner
+(
+    book_name   VARCHAR(100) NOT NULL,
+    owner_name  VARCHAR(100) NOT NULL,
+    member_name VARCHAR(100) NOT NULL,
+    role        VARCHAR(20)  NOT NULL,
+    PRIMARY KEY (book_name, owner_name, member_name),
+    FOREIGN KEY (book_name, owner_name) REFERENCES books (name, user_name) ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (member_name) REFERENCES users (name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO book_members_by_owner
+SELECT book_name, owner_name, member_name, role
+FROM book_members;
+
+DROP TABLE book_members;
+
+ALTER TABLE book_members_by_owner RENAME TO book_members;
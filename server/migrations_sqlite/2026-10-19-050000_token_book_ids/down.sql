@@ -0,0 +1,16 @@
+CREATE TABLE token_book_names
+(
+    token_id  BIGINT       NOT NULL,
+    book_name VARCHAR(100) NOT NULL,
+    PRIMARY KEY (token_id, book_name),
+    FOREIGN KEY (token_id) REFERENCES tokens (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO token_book_names (token_id, book_name)
+SELECT DISTINCT token_books.token_id, books.name
+FROM token_books
+         JOIN books ON books.id = token_books.book_id;
+
+DROP TABLE token_books;
+
+ALTER TABLE token_book_names RENAME TO token_books;
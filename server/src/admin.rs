@@ -0,0 +1,204 @@
+use crate::auth::{issue_token, Claim, IsServerAdmin};
+use crate::error::Error;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use finance_lib::{Capability, NewToken};
+
+/// Creates the user `name` with the administrator role, or promotes and enables it if it already
+/// exists, and issues a new token for it. Used to bootstrap a fresh installation.
+pub fn create_admin(
+    pool: &ConnectionPool,
+    name: &String,
+) -> Result<finance_lib::CreatedToken, Box<dyn std::error::Error>> {
+    let conn = &mut pool.get()?;
+    let user = User {
+        name: name.clone(),
+        admin: true,
+        disabled: false,
+        password_hash: None,
+        oidc_subject: None,
+    };
+    let insert = diesel::insert_into(users::table).values(&user);
+    #[cfg(feature = "mysql")]
+    let upsert = insert.on_conflict(diesel::dsl::DuplicatedKeys);
+    #[cfg(not(feature = "mysql"))]
+    let upsert = insert.on_conflict(users::name);
+    upsert.do_update().set(&user).execute(conn)?;
+    let new_token = NewToken {
+        name: format!("bootstrap-{}", Utc::now().format("%Y%m%d%H%M%S")),
+        expires: None,
+        capability: Some(Capability::Admin),
+        books: None,
+    };
+    issue_token(conn, name, &new_token)
+        .map_err(|response| format!("Could not issue token: {}", response.status()).into())
+}
+
+pub async fn get_users(
+    _claim: Claim<IsServerAdmin>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = users::table.load::<User>(conn);
+    match result {
+        Ok(list) => {
+            let user_structs: Vec<_> = list.iter().map(|u| u.to_user_struct()).collect();
+            Ok(Json(user_structs).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn get_user(
+    _claim: Claim<IsServerAdmin>,
+    Path(user_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = users::table
+        .filter(users::dsl::name.eq(&user_name))
+        .load::<User>(conn);
+    match result {
+        Ok(users) => {
+            if !users.is_empty() {
+                Ok(Json(users[0].to_user_struct()).into_response())
+            } else {
+                Err(
+                    Error::NotFound(format!("User '{}' does not exist.", user_name))
+                        .into_response(),
+                )
+            }
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Creates a user together with its first token, which is returned in the response.
+pub async fn create_user(
+    _claim: Claim<IsServerAdmin>,
+    State(pool): State<ConnectionPool>,
+    Json(new_user): Json<finance_lib::NewUser>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let user = User {
+        name: new_user.name.clone(),
+        admin: new_user.admin,
+        disabled: false,
+        password_hash: None,
+        oidc_subject: None,
+    };
+    let new_token = NewToken {
+        name: "default".to_owned(),
+        expires: None,
+        capability: Some(Capability::Admin),
+        books: None,
+    };
+    let mut token_error = None;
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(users::table)
+            .values(&user)
+            .execute(conn)?;
+        issue_token(conn, &user.name, &new_token).map_err(|response| {
+            token_error = Some(response);
+            diesel::result::Error::RollbackTransaction
+        })
+    });
+    match result {
+        Ok(created) => Ok(Json(created).into_response()),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(
+            Error::AlreadyExists(format!("User '{}' already exists.", user.name)).into_response(),
+        ),
+        Err(e) => Err(token_error.unwrap_or_else(|| Error::from(e).into_response())),
+    }
+}
+
+/// Updates the roles of a user. Disabled users keep their tokens but cannot authenticate. The
+/// name cannot change, the audit log of the user's books is kept under it.
+pub async fn update_user(
+    claim: Claim<IsServerAdmin>,
+    Path(user_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+    Json(user_struct): Json<finance_lib::User>,
+) -> Result<Response, Response> {
+    if user_struct.name != user_name {
+        return Err(Error::InvalidField(
+            "name",
+            "Users cannot be renamed, their audit log is kept under their name.".to_owned(),
+        )
+        .into_response());
+    }
+    if user_name == claim.user.name && (!user_struct.admin || user_struct.disabled) {
+        return Err(Error::Invalid(
+            "Administrators cannot demote or disable themselves.".to_owned(),
+        )
+        .into_response());
+    }
+    let conn = &mut get_connection(&pool)?;
+    let user = User::from_user_struct(&user_struct, ());
+    let result = diesel::update(users::table)
+        .set(user)
+        .filter(users::dsl::name.eq(&user_name))
+        .execute(conn);
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Ok(_) => {
+            Err(Error::NotFound(format!("User '{}' does not exist.", user_name)).into_response())
+        }
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(Error::AlreadyExists(
+                "The OpenID Connect identity is linked to another user already.".to_owned(),
+            )
+            .into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn delete_user(
+    claim: Claim<IsServerAdmin>,
+    Path(user_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    if user_name == claim.user.name {
+        return Err(
+            Error::Invalid("Administrators cannot delete themselves.".to_owned()).into_response(),
+        );
+    }
+    let conn = &mut get_connection(&pool)?;
+    // Postings go first, the accounts and currencies of the user's books cannot be deleted under
+    // them.
+    let result = conn.transaction(|conn| {
+        diesel::delete(postings::table)
+            .filter(postings::dsl::user_name.eq(&user_name))
+            .execute(conn)?;
+        diesel::delete(users::table)
+            .filter(users::dsl::name.eq(&user_name))
+            .execute(conn)
+    });
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Ok(_) => {
+            Err(Error::NotFound(format!("User '{}' does not exist.", user_name)).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Issues a token for another user, e.g. after all of their tokens were revoked.
+pub async fn create_user_token(
+    _claim: Claim<IsServerAdmin>,
+    Path(user_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+    Json(new_token): Json<finance_lib::NewToken>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let created = issue_token(conn, &user_name, &new_token)?;
+    Ok(Json(created).into_response())
+}
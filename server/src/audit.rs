@@ -0,0 +1,288 @@
+use crate::auth::{CanRead, Claim};
+use crate::db::{self, DbConnection};
+use crate::error::Error;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{SubsecRound, Utc};
+use diesel::prelude::*;
+use finance_lib::{AuditAction, AuditEntity, Page};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::PoisonError;
+
+/// Records the creation of an entity in the audit log of `book_name`. Must be called in the same
+/// database transaction as the change itself.
+pub fn created<C, T: Serialize>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &str,
+    entity: AuditEntity,
+    entity_id: &str,
+    after: &T,
+) -> QueryResult<()> {
+    let after = to_json(after)?;
+    record(
+        conn,
+        claim,
+        book_name,
+        entity,
+        entity_id,
+        AuditAction::Create,
+        None,
+        Some(after),
+    )
+}
+
+pub fn updated<C, B: Serialize, A: Serialize>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &str,
+    entity: AuditEntity,
+    entity_id: &str,
+    before: &B,
+    after: &A,
+) -> QueryResult<()> {
+    let before = to_json(before)?;
+    let after = to_json(after)?;
+    record(
+        conn,
+        claim,
+        book_name,
+        entity,
+        entity_id,
+        AuditAction::Update,
+        Some(before),
+        Some(after),
+    )
+}
+
+pub fn deleted<C, T: Serialize>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &str,
+    entity: AuditEntity,
+    entity_id: &str,
+    before: &T,
+) -> QueryResult<()> {
+    let before = to_json(before)?;
+    record(
+        conn,
+        claim,
+        book_name,
+        entity,
+        entity_id,
+        AuditAction::Delete,
+        Some(before),
+        None,
+    )
+}
+
+/// Records that an entity was taken out of the trash. The restored entity is the same as before
+/// its deletion, so only the new state is kept.
+pub fn restored<C, T: Serialize>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &str,
+    entity: AuditEntity,
+    entity_id: &str,
+    after: &T,
+) -> QueryResult<()> {
+    let after = to_json(after)?;
+    record(
+        conn,
+        claim,
+        book_name,
+        entity,
+        entity_id,
+        AuditAction::Restore,
+        None,
+        Some(after),
+    )
+}
+
+fn to_json<T: Serialize>(value: &T) -> QueryResult<String> {
+    serde_json::to_string(value).map_err(|e| diesel::result::Error::SerializationError(e.into()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &str,
+    entity: AuditEntity,
+    entity_id: &str,
+    action: AuditAction,
+    before_value: Option<String>,
+    after_value: Option<String>,
+) -> QueryResult<()> {
+    // Locking the book keeps concurrent changes from forking the hash chain, also before its
+    // first entry.
+    let query = books::table.select(books::dsl::id).filter(
+        books::dsl::name
+            .eq(book_name)
+            .and(books::dsl::user_name.eq(claim.owner())),
+    );
+    let book_id = db::for_update!(query).first::<i64>(conn)?;
+    let previous_hash = audit_log::table
+        .select(audit_log::dsl::hash)
+        .filter(audit_log::dsl::book_id.eq(book_id))
+        .order(audit_log::dsl::id.desc())
+        .first::<String>(conn)
+        .optional()?;
+    let id = crate::SNOWFLAKE_GENERATOR
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .real_time_generate();
+    let mut entry = AuditLogEntry {
+        id,
+        book_name: book_name.to_owned(),
+        owner_name: claim.owner().clone(),
+        // The database does not store fractions of seconds, which would break the hash.
+        time: Utc::now().naive_utc().trunc_subsecs(0),
+        actor: claim.user.name.clone(),
+        token_id: Some(claim.token.id),
+        entity: entity.to_string(),
+        entity_id: entity_id.to_owned(),
+        action: action.to_string(),
+        before_value,
+        after_value,
+        previous_hash,
+        hash: String::new(),
+        book_id: Some(book_id),
+    };
+    entry.hash = entry_hash(&entry);
+    diesel::insert_into(audit_log::table)
+        .values(&entry)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Hashes everything but the location of the entry. The location is kept by the database, which
+/// refuses to change or delete entries.
+fn entry_hash(entry: &AuditLogEntry) -> String {
+    let mut hasher = Sha256::new();
+    let token_id = entry.token_id.map(|id| id.to_string());
+    let time = entry.time.format("%Y-%m-%d %H:%M:%S").to_string();
+    for part in [
+        entry.previous_hash.as_deref(),
+        Some(entry.id.to_string().as_str()),
+        Some(time.as_str()),
+        Some(entry.actor.as_str()),
+        token_id.as_deref(),
+        Some(entry.entity.as_str()),
+        Some(entry.entity_id.as_str()),
+        Some(entry.action.as_str()),
+        entry.before_value.as_deref(),
+        entry.after_value.as_deref(),
+    ] {
+        match part {
+            Some(part) => {
+                hasher.update([1]);
+                hasher.update((part.len() as u64).to_le_bytes());
+                hasher.update(part.as_bytes());
+            }
+            None => hasher.update([0]),
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Id of the book the log is kept under.
+fn book_id(conn: &mut DbConnection, owner: &String, book_name: &String) -> QueryResult<i64> {
+    books::table
+        .select(books::dsl::id)
+        .filter(
+            books::dsl::name
+                .eq(book_name)
+                .and(books::dsl::user_name.eq(owner)),
+        )
+        .first::<i64>(conn)
+}
+
+pub async fn get_audit_log(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    Query(page): Query<Page>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = book_id(conn, claim.owner(), &book_name).and_then(|book_id| {
+        audit_log::table
+            .filter(audit_log::dsl::book_id.eq(book_id))
+            .order(audit_log::dsl::id.asc())
+            .offset(page.offset.unwrap_or(0))
+            .limit(page.limit.unwrap_or(i64::MAX))
+            .load::<AuditLogEntry>(conn)
+    });
+    match result {
+        Ok(list) => {
+            let user_structs: Vec<_> = list.iter().map(|e| e.to_user_struct()).collect();
+            Ok(Json(user_structs).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn get_entity_audit_log(
+    claim: Claim<CanRead>,
+    Path((book_name, entity, entity_id)): Path<(String, String, String)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let entity = match entity.parse::<AuditEntity>() {
+        Ok(entity) => entity,
+        Err(e) => return Err(Error::Invalid(e).into_response()),
+    };
+    let conn = &mut get_connection(&pool)?;
+    let result = book_id(conn, claim.owner(), &book_name).and_then(|book_id| {
+        audit_log::table
+            .filter(
+                audit_log::dsl::book_id
+                    .eq(book_id)
+                    .and(audit_log::dsl::entity.eq(entity.as_str()))
+                    .and(audit_log::dsl::entity_id.eq(entity_id)),
+            )
+            .order(audit_log::dsl::id.asc())
+            .load::<AuditLogEntry>(conn)
+    });
+    match result {
+        Ok(list) => {
+            let user_structs: Vec<_> = list.iter().map(|e| e.to_user_struct()).collect();
+            Ok(Json(user_structs).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Recomputes the hash chain of the book to detect entries that were modified or removed.
+pub async fn verify_audit_log(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = book_id(conn, claim.owner(), &book_name).and_then(|book_id| {
+        audit_log::table
+            .filter(audit_log::dsl::book_id.eq(book_id))
+            .order(audit_log::dsl::id.asc())
+            .load::<AuditLogEntry>(conn)
+    });
+    let entries = match result {
+        Ok(entries) => entries,
+        Err(e) => return Err(Error::from(e).into_response()),
+    };
+    let mut previous_hash = None;
+    let first_invalid_entry = entries.iter().find_map(|entry| {
+        let valid = entry.previous_hash == previous_hash && entry.hash == entry_hash(entry);
+        previous_hash = Some(entry.hash.clone());
+        (!valid).then_some(entry.id)
+    });
+    Ok(Json(finance_lib::AuditVerification {
+        entries: entries.len(),
+        first_invalid_entry,
+    })
+    .into_response())
+}
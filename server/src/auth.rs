@@ -0,0 +1,503 @@
+use crate::db::DbConnection;
+use crate::error::Error;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{FromRequestParts, Path, Query, RawPathParams, State};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Json};
+use axum_auth::AuthBearer;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use finance_lib::{BookRole, Capability};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::marker::PhantomData;
+
+/// Marks the capability a route requires from the token of its [`Claim`].
+pub trait RequiredCapability {
+    const CAPABILITY: Capability;
+    /// Role the user needs in the book of the route, if it has a `book_name` parameter.
+    const ROLE: BookRole = BookRole::Viewer;
+    /// Whether the user of the token also needs the administrator role of the server.
+    const SERVER_ADMIN: bool = false;
+}
+
+pub struct CanRead;
+pub struct CanWriteTransactions;
+pub struct CanAdmin;
+pub struct IsBookMember;
+pub struct IsBookOwner;
+pub struct IsServerAdmin;
+
+impl RequiredCapability for CanRead {
+    const CAPABILITY: Capability = Capability::Read;
+}
+
+impl RequiredCapability for CanWriteTransactions {
+    const CAPABILITY: Capability = Capability::WriteTransactions;
+    const ROLE: BookRole = BookRole::Editor;
+}
+
+impl RequiredCapability for CanAdmin {
+    const CAPABILITY: Capability = Capability::Admin;
+    const ROLE: BookRole = BookRole::Editor;
+}
+
+impl RequiredCapability for IsBookMember {
+    const CAPABILITY: Capability = Capability::Admin;
+}
+
+impl RequiredCapability for IsBookOwner {
+    const CAPABILITY: Capability = Capability::Admin;
+    const ROLE: BookRole = BookRole::Owner;
+}
+
+impl RequiredCapability for IsServerAdmin {
+    const CAPABILITY: Capability = Capability::Admin;
+    const SERVER_ADMIN: bool = true;
+}
+
+/// Picks the book of a route among the books of the same name shared with the user.
+#[derive(Deserialize)]
+struct BookOwner {
+    owner: Option<String>,
+}
+
+/// An authenticated request whose token has at least the capability `C` and, if the route has a
+/// `book_name` parameter, whose user is a member of that book with at least the role of `C`.
+pub struct Claim<C> {
+    pub user: User,
+    pub token: Token,
+    /// Ids of the books the token is limited to. `None` grants access to all books.
+    pub books: Option<Vec<i64>>,
+    /// Membership in the book of the route.
+    pub membership: Option<BookMember>,
+    capability: PhantomData<fn() -> C>,
+}
+
+impl<C> Claim<C> {
+    /// Name of the user owning the book of the route, which keys all rows of the book. Outside of
+    /// a book this is the authenticated user.
+    pub fn owner(&self) -> &String {
+        match &self.membership {
+            Some(membership) => &membership.owner_name,
+            None => &self.user.name,
+        }
+    }
+
+    pub fn allows_book(&self, book_id: i64) -> bool {
+        match &self.books {
+            Some(books) => books.contains(&book_id),
+            None => true,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: RequiredCapability> FromRequestParts<ConnectionPool> for Claim<C> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        pool: &ConnectionPool,
+    ) -> Result<Self, Self::Rejection> {
+        let book_name = RawPathParams::from_request_parts(parts, pool)
+            .await
+            .ok()
+            .and_then(|params| {
+                params
+                    .iter()
+                    .find(|(key, _)| *key == "book_name")
+                    .map(|(_, value)| value.to_owned())
+            });
+        let owner = Query::<BookOwner>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|query| query.0.owner);
+        let bearer = match AuthBearer::from_request_parts(parts, pool).await {
+            Ok(bearer) => bearer,
+            Err(_) => {
+                return Err(Error::NotAuthenticated("Not authenticated.".to_owned()).into_response())
+            }
+        };
+        let conn = &mut get_connection(pool)?;
+        let result = tokens::table
+            .inner_join(users::table)
+            .filter(
+                tokens::dsl::token_hash
+                    .eq(hash_token(&bearer.0))
+                    .and(tokens::dsl::revoked.eq(false)),
+            )
+            .load::<(Token, User)>(conn);
+        let mut result = match result {
+            Ok(list) => list,
+            Err(e) => return Err(Error::from(e).into_response()),
+        };
+        if result.len() != 1 {
+            return Err(
+                Error::NotAuthenticated("Authentication failed.".to_owned()).into_response()
+            );
+        }
+        let (token, user) = result.remove(0);
+        if user.disabled {
+            return Err(Error::Forbidden("User is disabled.".to_owned()).into_response());
+        }
+        let now = Utc::now().naive_utc();
+        if token.expires.is_some_and(|expires| expires <= now) {
+            return Err(Error::NotAuthenticated("Token expired.".to_owned()).into_response());
+        }
+        if token.capability() < C::CAPABILITY {
+            return Err(Error::Forbidden(format!(
+                "Token lacks the '{}' capability.",
+                C::CAPABILITY
+            ))
+            .into_response());
+        }
+        if C::SERVER_ADMIN && !user.admin {
+            return Err(Error::Forbidden("Administrator role required.".to_owned()).into_response());
+        }
+        let books = match load_token_books(conn, token.id) {
+            Ok(books) => books,
+            Err(e) => return Err(Error::from(e).into_response()),
+        };
+        let update = diesel::update(tokens::table)
+            .set(tokens::dsl::last_used.eq(now))
+            .filter(tokens::dsl::id.eq(token.id))
+            .execute(conn);
+        if let Err(e) = update {
+            return Err(Error::from(e).into_response());
+        }
+        // The limit of the token comes before the membership, so books out of its reach are
+        // refused alike whether they exist or not.
+        if let (Some(book_name), Some(scope)) = (&book_name, &books) {
+            let mut query = books::table
+                .select(books::dsl::id)
+                .filter(
+                    books::dsl::name
+                        .eq(book_name)
+                        .and(books::dsl::id.eq_any(scope)),
+                )
+                .into_boxed();
+            if let Some(owner) = &owner {
+                query = query.filter(books::dsl::user_name.eq(owner));
+            }
+            match query.first::<i64>(conn).optional() {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    return Err(Error::Forbidden(format!(
+                        "Token has no access to book '{}'.",
+                        book_name
+                    ))
+                    .into_response())
+                }
+                Err(e) => return Err(Error::from(e).into_response()),
+            }
+        }
+        let membership = match &book_name {
+            Some(book_name) => match find_membership(
+                conn,
+                &user.name,
+                book_name,
+                owner.as_ref(),
+                books.as_deref(),
+            ) {
+                Ok(Ok(membership)) => Some(membership),
+                Ok(Err(error)) => return Err(error.into_response()),
+                Err(e) => return Err(Error::from(e).into_response()),
+            },
+            None => None,
+        };
+        let claim = Claim {
+            user,
+            token,
+            books,
+            membership,
+            capability: PhantomData,
+        };
+
+        match book_name {
+            Some(_)
+                if claim
+                    .membership
+                    .as_ref()
+                    .is_some_and(|m| m.role() < C::ROLE) =>
+            {
+                Err(
+                    Error::Forbidden(format!("Requires the '{}' role in this book.", C::ROLE))
+                        .into_response(),
+                )
+            }
+            // Admin routes outside of a book manage the user itself and must not be reachable
+            // with a token limited to some books.
+            None if C::CAPABILITY == Capability::Admin && claim.books.is_some() => Err(
+                Error::Forbidden("Token is limited to specific books.".to_owned()).into_response(),
+            ),
+            _ => Ok(claim),
+        }
+    }
+}
+
+/// Membership of `user_name` in the book of a route, among the books in `scope` if the token is
+/// limited. Book names are only unique per owner: the `owner` query parameter picks the book,
+/// without it the own book of the user comes first, then the only book of that name shared with
+/// them.
+fn find_membership(
+    conn: &mut DbConnection,
+    user_name: &String,
+    book_name: &String,
+    owner: Option<&String>,
+    scope: Option<&[i64]>,
+) -> QueryResult<Result<BookMember, Error>> {
+    // Books in the trash are hidden until their owner restores them.
+    let mut query = book_members::table
+        .inner_join(
+            books::table.on(books::dsl::name
+                .eq(book_members::dsl::book_name)
+                .and(books::dsl::user_name.eq(book_members::dsl::owner_name))),
+        )
+        .select(book_members::all_columns)
+        .filter(
+            book_members::dsl::book_name
+                .eq(book_name)
+                .and(book_members::dsl::member_name.eq(user_name))
+                .and(books::dsl::deleted_at.is_null()),
+        )
+        .into_boxed();
+    if let Some(owner) = owner {
+        query = query.filter(book_members::dsl::owner_name.eq(owner));
+    }
+    if let Some(scope) = scope {
+        query = query.filter(books::dsl::id.eq_any(scope));
+    }
+    let mut memberships = query.load::<BookMember>(conn)?;
+    let ambiguous = memberships.len() > 1;
+    if ambiguous {
+        memberships.retain(|membership| &membership.owner_name == user_name);
+    }
+    Ok(match memberships.pop() {
+        Some(membership) => Ok(membership),
+        None if ambiguous => Err(Error::InvalidField(
+            "owner",
+            format!(
+                "Several users share a book named '{}' with you, pick one by its owner.",
+                book_name
+            ),
+        )),
+        None => Err(Error::NotFound(format!(
+            "Book '{}' does not exist.",
+            book_name
+        ))),
+    })
+}
+
+fn load_token_books(conn: &mut DbConnection, token_id: i64) -> QueryResult<Option<Vec<i64>>> {
+    let books = token_books::table
+        .select(token_books::dsl::book_id)
+        .filter(token_books::dsl::token_id.eq(token_id))
+        .load::<i64>(conn)?;
+    Ok(if books.is_empty() { None } else { Some(books) })
+}
+
+/// Names of the books a token is limited to, as they are called now. Books purged since are left
+/// out, the token stays limited even if none is left.
+fn token_book_names(conn: &mut DbConnection, token_id: i64) -> QueryResult<Option<Vec<String>>> {
+    let books = token_books::table
+        .left_join(books::table.on(books::dsl::id.eq(token_books::dsl::book_id)))
+        .select(books::dsl::name.nullable())
+        .filter(token_books::dsl::token_id.eq(token_id))
+        .load::<Option<String>>(conn)?;
+    Ok(if books.is_empty() {
+        None
+    } else {
+        Some(books.into_iter().flatten().collect())
+    })
+}
+
+/// Ids of the books named in a new token, picked like the book of a route without an owner.
+fn resolve_token_books(
+    conn: &mut DbConnection,
+    user_name: &String,
+    book_names: &[String],
+) -> QueryResult<Result<Vec<i64>, Error>> {
+    let mut ids = vec![];
+    for book_name in book_names {
+        let mut books = book_members::table
+            .inner_join(
+                books::table.on(books::dsl::name
+                    .eq(book_members::dsl::book_name)
+                    .and(books::dsl::user_name.eq(book_members::dsl::owner_name))),
+            )
+            .select((books::dsl::id, books::dsl::user_name))
+            .filter(
+                book_members::dsl::book_name
+                    .eq(book_name)
+                    .and(book_members::dsl::member_name.eq(user_name))
+                    .and(books::dsl::deleted_at.is_null()),
+            )
+            .load::<(i64, String)>(conn)?;
+        let ambiguous = books.len() > 1;
+        if ambiguous {
+            books.retain(|(_, owner)| owner == user_name);
+        }
+        match books.pop() {
+            Some((id, _)) => ids.push(id),
+            None if ambiguous => {
+                return Ok(Err(Error::InvalidField(
+                    "books",
+                    format!("Several users share a book named '{}' with you.", book_name),
+                )))
+            }
+            None => {
+                return Ok(Err(Error::InvalidField(
+                    "books",
+                    format!("Book '{}' does not exist.", book_name),
+                )))
+            }
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(Ok(ids))
+}
+
+/// Deletes the tokens that expired, along with their books.
+pub fn purge_expired(conn: &mut DbConnection) -> QueryResult<usize> {
+    diesel::delete(tokens::table)
+        .filter(tokens::dsl::expires.lt(Utc::now().naive_utc()))
+        .execute(conn)
+}
+
+/// Creates a new random token secret. Only its hash is persisted.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a token for `user_name` and returns it together with its secret.
+#[allow(clippy::result_large_err)]
+pub fn issue_token(
+    conn: &mut DbConnection,
+    user_name: &String,
+    new_token: &finance_lib::NewToken,
+) -> Result<finance_lib::CreatedToken, Response> {
+    let secret = generate_token();
+    let token = match Token::from_new_user_struct(
+        new_token,
+        AddedInformationForToken {
+            user_name,
+            token_hash: &hash_token(&secret),
+        },
+    ) {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("could not create the token: {}", e);
+            return Err(Error::Internal.into_response());
+        }
+    };
+    let books = new_token.books.clone().filter(|books| !books.is_empty());
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let book_ids = match &books {
+            Some(books) => match resolve_token_books(conn, user_name, books)? {
+                Ok(book_ids) => book_ids,
+                Err(error) => return Ok(Err(error)),
+            },
+            None => vec![],
+        };
+        diesel::insert_into(tokens::table)
+            .values(&token)
+            .execute(conn)?;
+        let token_books: Vec<_> = book_ids
+            .into_iter()
+            .map(|book_id| TokenBook {
+                token_id: token.id,
+                book_id,
+            })
+            .collect();
+        diesel::insert_into(token_books::table)
+            .values(&token_books)
+            .execute(conn)?;
+        Ok(Ok(()))
+    });
+    match result {
+        Ok(Err(error)) => Err(error.into_response()),
+        Ok(Ok(())) => {
+            let mut user_token = token.to_user_struct();
+            user_token.books = books;
+            Ok(finance_lib::CreatedToken {
+                token: user_token,
+                secret,
+            })
+        }
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(
+            Error::AlreadyExists(format!("Token '{}' already exists.", token.name)).into_response(),
+        ),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            Err(Error::NotFound(format!("User '{}' does not exist.", user_name)).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn create_token(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+    Json(new_token): Json<finance_lib::NewToken>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let created = issue_token(conn, &claim.user.name, &new_token)?;
+    Ok(Json(created).into_response())
+}
+
+pub async fn get_tokens(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = tokens::table
+        .filter(tokens::dsl::user_name.eq(claim.user.name))
+        .load::<Token>(conn)
+        .and_then(|list| {
+            list.iter()
+                .map(|token| {
+                    let mut user_token = token.to_user_struct();
+                    user_token.books = token_book_names(conn, token.id)?;
+                    Ok(user_token)
+                })
+                .collect::<QueryResult<Vec<_>>>()
+        });
+    match result {
+        Ok(user_structs) => Ok(Json(user_structs).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn revoke_token(
+    claim: Claim<CanAdmin>,
+    Path(token_id): Path<i64>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = diesel::update(tokens::table)
+        .set(tokens::dsl::revoked.eq(true))
+        .filter(
+            tokens::dsl::user_name
+                .eq(claim.user.name)
+                .and(tokens::dsl::id.eq(token_id)),
+        )
+        .execute(conn);
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Ok(_) => {
+            Err(Error::NotFound(format!("Token {} does not exist.", token_id)).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
@@ -1,17 +1,94 @@
-pub mod diesel_extension;
-
 use diesel::r2d2::{ConnectionManager, Pool};
 
-use diesel::MysqlConnection;
 use dotenvy::dotenv;
 use std::env;
 
-pub fn get_connection_pool() -> Pool<ConnectionManager<MysqlConnection>> {
+#[cfg(any(
+    all(feature = "mysql", feature = "postgres"),
+    all(feature = "mysql", feature = "sqlite"),
+    all(feature = "postgres", feature = "sqlite"),
+))]
+compile_error!("The features mysql, postgres and sqlite choose the database backend, enable one.");
+#[cfg(not(any(feature = "mysql", feature = "postgres", feature = "sqlite")))]
+compile_error!("Enable the feature mysql, postgres or sqlite to choose the database backend.");
+
+#[cfg(feature = "mysql")]
+pub type DbConnection = diesel::MysqlConnection;
+#[cfg(feature = "mysql")]
+pub type Backend = diesel::mysql::Mysql;
+
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::PgConnection;
+#[cfg(feature = "postgres")]
+pub type Backend = diesel::pg::Pg;
+
+#[cfg(feature = "sqlite")]
+pub type DbConnection = diesel::SqliteConnection;
+#[cfg(feature = "sqlite")]
+pub type Backend = diesel::sqlite::Sqlite;
+
+/// Locks the rows selected by a query until the end of the transaction. SQLite has no row locks
+/// and needs none, its pool has a single connection.
+#[cfg(not(feature = "sqlite"))]
+macro_rules! for_update {
+    ($query:expr) => {
+        $query.for_update()
+    };
+}
+#[cfg(feature = "sqlite")]
+macro_rules! for_update {
+    ($query:expr) => {
+        $query
+    };
+}
+pub(crate) use for_update;
+
+#[cfg(feature = "sqlite")]
+const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("migrations_sqlite");
+
+/// Turns on the foreign keys, which SQLite leaves off by default, on every new connection.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqliteSettings;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<DbConnection, diesel::r2d2::Error> for SqliteSettings {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub fn get_connection_pool() -> Pool<ConnectionManager<DbConnection>> {
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<MysqlConnection>::new(database_url);
-    Pool::builder()
-        .test_on_check_out(true)
+    connection_pool(&database_url)
+}
+
+/// Connects to the database at `database_url`, the path of the file for SQLite. An SQLite
+/// database is created and migrated as needed.
+pub fn connection_pool(database_url: &str) -> Pool<ConnectionManager<DbConnection>> {
+    let manager = ConnectionManager::<DbConnection>::new(database_url);
+    let builder = Pool::builder().test_on_check_out(true);
+    // SQLite writes one transaction at a time. A single connection makes requests wait for each
+    // other rather than fail as the database is busy, see `get_connection` for what this asks of
+    // the handlers.
+    #[cfg(feature = "sqlite")]
+    let builder = builder
+        .max_size(1)
+        .connection_customizer(Box::new(SqliteSettings));
+    let pool = builder
         .build(manager)
-        .expect("Could not build connection pool")
+        .expect("Could not build connection pool");
+    #[cfg(feature = "sqlite")]
+    {
+        use diesel_migrations::MigrationHarness;
+        pool.get()
+            .expect("Could not connect to the database")
+            .run_pending_migrations(MIGRATIONS)
+            .expect("Could not migrate the database");
+    }
+    pool
 }
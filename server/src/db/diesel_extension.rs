@@ -1,5 +0,0 @@
-use diesel::prelude::*;
-
-sql_function!(
-    fn last_insert_id() -> Unsigned<Integer>
-);
@@ -0,0 +1,131 @@
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::NaiveDateTime;
+use finance_lib::{ApiError, ErrorCode, FieldError};
+
+/// Everything a request can fail with, answered with an [`ApiError`] body.
+pub enum Error {
+    Invalid(String),
+    /// Like `Invalid`, naming the field of the request at fault.
+    InvalidField(&'static str, String),
+    NotAuthenticated(String),
+    Forbidden(String),
+    NotFound(String),
+    AlreadyExists(String),
+    InUse(String),
+    TransactionFinal(i64),
+    PeriodClosed(NaiveDateTime),
+    Conflict(String),
+    PreconditionFailed,
+    /// Details are logged, never sent to the client.
+    Internal,
+}
+
+impl Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Invalid(_) | Error::InvalidField(..) => ErrorCode::InvalidInput,
+            Error::NotAuthenticated(_) => ErrorCode::NotAuthenticated,
+            Error::Forbidden(_) => ErrorCode::Forbidden,
+            Error::NotFound(_) => ErrorCode::NotFound,
+            Error::AlreadyExists(_) => ErrorCode::AlreadyExists,
+            Error::InUse(_) => ErrorCode::InUse,
+            Error::TransactionFinal(_) => ErrorCode::TransactionFinal,
+            Error::PeriodClosed(_) => ErrorCode::PeriodClosed,
+            Error::Conflict(_) => ErrorCode::Conflict,
+            Error::PreconditionFailed => ErrorCode::PreconditionFailed,
+            Error::Internal => ErrorCode::Internal,
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::InvalidInput => StatusCode::BAD_REQUEST,
+            ErrorCode::NotAuthenticated => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AlreadyExists
+            | ErrorCode::InUse
+            | ErrorCode::TransactionFinal
+            | ErrorCode::PeriodClosed
+            | ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn to_api_error(&self) -> ApiError {
+        let message = match self {
+            Error::Invalid(message)
+            | Error::InvalidField(_, message)
+            | Error::NotAuthenticated(message)
+            | Error::Forbidden(message)
+            | Error::NotFound(message)
+            | Error::AlreadyExists(message)
+            | Error::InUse(message)
+            | Error::Conflict(message) => message.clone(),
+            Error::TransactionFinal(transaction_id) => format!(
+                "Transaction {} is posted in an immutable book, reverse it instead.",
+                transaction_id
+            ),
+            Error::PeriodClosed(lock_date) => format!(
+                "The book is closed for everything dated before {}.",
+                lock_date
+            ),
+            Error::PreconditionFailed => "The entity was changed in the meantime.".to_owned(),
+            Error::Internal => "Internal server error.".to_owned(),
+        };
+        let details = match self {
+            Error::InvalidField(field, message) => vec![FieldError {
+                field: field.to_string(),
+                message: message.clone(),
+            }],
+            _ => vec![],
+        };
+        ApiError {
+            code: self.code(),
+            message,
+            details,
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.to_api_error())).into_response()
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(error: diesel::result::Error) -> Self {
+        tracing::error!("database error: {}", error);
+        Error::Internal
+    }
+}
+
+/// Turns the plain text rejections of axum, such as for a malformed body or an unknown route,
+/// into the same JSON body as every other error.
+pub async fn json_rejections(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if is_json || !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+    let message = match hyper::body::to_bytes(response.into_body()).await {
+        Ok(body) if !body.is_empty() => String::from_utf8_lossy(&body).into_owned(),
+        _ => status.canonical_reason().unwrap_or_default().to_owned(),
+    };
+    match status {
+        StatusCode::NOT_FOUND => Error::NotFound(message),
+        StatusCode::UNAUTHORIZED => Error::NotAuthenticated(message),
+        StatusCode::FORBIDDEN => Error::Forbidden(message),
+        status if status.is_server_error() => Error::Internal,
+        _ => Error::Invalid(message),
+    }
+    .into_response()
+}
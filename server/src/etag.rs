@@ -0,0 +1,66 @@
+use crate::error::Error;
+use axum::extract::FromRequestParts;
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Json};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Strong entity tag of the JSON representation of an entity, it changes whenever the entity does.
+pub fn of<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    format!("\"{}\"", hex::encode(Sha256::digest(json)))
+}
+
+/// The entity along with its tag, which clients send back in `If-Match` when they change it.
+pub fn tagged<T: Serialize>(value: T) -> Response {
+    ([(ETAG, of(&value))], Json(value)).into_response()
+}
+
+/// Empty response to a change, carrying the tag of the changed entity.
+pub fn updated<T: Serialize>(after: &T) -> Response {
+    [(ETAG, of(after))].into_response()
+}
+
+/// Tags of the `If-Match` header. Without the header, or with `*`, any version of the entity
+/// matches and the last writer wins.
+pub struct IfMatch(Option<Vec<String>>);
+
+impl IfMatch {
+    /// Falls back to expecting the entity as it was read with `tag`.
+    pub fn or(self, tag: String) -> Self {
+        Self(self.0.or(Some(vec![tag])))
+    }
+
+    /// Refuses the change if someone else changed the entity since the client read it.
+    #[allow(clippy::result_large_err)]
+    pub fn check<T: Serialize>(&self, current: &T) -> Result<(), Response> {
+        match &self.0 {
+            Some(tags) if !tags.contains(&of(current)) => {
+                Err(Error::PreconditionFailed.into_response())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = match parts.headers.get(IF_MATCH) {
+            Some(header) => header,
+            None => return Ok(Self(None)),
+        };
+        let header = header
+            .to_str()
+            .map_err(|_| Error::Invalid("Invalid If-Match header.".to_owned()).into_response())?;
+        let tags: Vec<String> = header.split(',').map(|tag| tag.trim().to_owned()).collect();
+        if tags.iter().any(|tag| tag == "*") {
+            return Ok(Self(None));
+        }
+        Ok(Self(Some(tags)))
+    }
+}
@@ -0,0 +1,131 @@
+//! Idempotent creation of entities with server-assigned ids. A client that does not know whether
+//! its request got through, like one replaying changes made offline, sends an `Idempotency-Key`
+//! header and retries with the same key. Retries get the id created the first time.
+
+use crate::db::DbConnection;
+use crate::error::Error;
+use crate::schema::*;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+pub const HEADER: &str = "Idempotency-Key";
+pub const MAX_KEY_LENGTH: usize = 100;
+/// How long keys are kept, much longer than clients keep retrying.
+const RETENTION_DAYS: i64 = 30;
+
+/// Key of the `Idempotency-Key` header. Without the header, every request creates an entity.
+pub struct IdempotencyKey(Option<String>);
+
+fn request_hash<T: Serialize>(request: &T) -> String {
+    let json = serde_json::to_vec(request).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+impl IdempotencyKey {
+    /// The id created by an earlier request of `user_name` with the key. Reusing the key for a
+    /// different `request` is refused.
+    #[allow(clippy::result_large_err)]
+    pub fn replay<T: Serialize>(
+        &self,
+        conn: &mut DbConnection,
+        user_name: &str,
+        request: &T,
+    ) -> Result<Option<i64>, Response> {
+        let key = match &self.0 {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let recorded = idempotency_keys::table
+            .select((
+                idempotency_keys::dsl::request_hash,
+                idempotency_keys::dsl::created_id,
+            ))
+            .filter(
+                idempotency_keys::dsl::user_name
+                    .eq(user_name)
+                    .and(idempotency_keys::dsl::idempotency_key.eq(key)),
+            )
+            .first::<(String, i64)>(conn)
+            .optional()
+            .map_err(|error| Error::from(error).into_response())?;
+        match recorded {
+            Some((hash, created_id)) if hash == request_hash(request) => Ok(Some(created_id)),
+            Some(_) => Err(Error::Conflict(format!(
+                "The idempotency key '{}' was used for a different request.",
+                key
+            ))
+            .into_response()),
+            None => Ok(None),
+        }
+    }
+
+    /// Remembers the id created for the key. Has to be called in the database transaction
+    /// creating the entity, so the key is only taken if the entity is created. A concurrent
+    /// request with the same key fails with a unique violation.
+    pub fn record<T: Serialize>(
+        &self,
+        conn: &mut DbConnection,
+        user_name: &str,
+        request: &T,
+        created_id: i64,
+    ) -> QueryResult<()> {
+        let key = match &self.0 {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        diesel::insert_into(idempotency_keys::table)
+            .values((
+                idempotency_keys::dsl::user_name.eq(user_name),
+                idempotency_keys::dsl::idempotency_key.eq(key),
+                idempotency_keys::dsl::request_hash.eq(request_hash(request)),
+                idempotency_keys::dsl::created_id.eq(created_id),
+                idempotency_keys::dsl::created.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/// Answer to a request racing another one with the same key.
+pub fn in_progress() -> Response {
+    Error::Conflict("A request with the same idempotency key is in progress.".to_owned())
+        .into_response()
+}
+
+/// Forgets keys older than the retention.
+pub fn purge(conn: &mut DbConnection) -> QueryResult<usize> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(RETENTION_DAYS);
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::dsl::created.lt(cutoff))
+        .execute(conn)
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IdempotencyKey {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = match parts.headers.get(HEADER) {
+            Some(header) => header,
+            None => return Ok(Self(None)),
+        };
+        let invalid = || {
+            Error::Invalid(format!(
+                "The {} header has to be 1 to {} visible characters.",
+                HEADER, MAX_KEY_LENGTH
+            ))
+            .into_response()
+        };
+        let key = header.to_str().map_err(|_| invalid())?;
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(invalid());
+        }
+        Ok(Self(Some(key.to_owned())))
+    }
+}
@@ -0,0 +1,281 @@
+use crate::audit;
+use crate::auth::Claim;
+use crate::db::DbConnection;
+use crate::error::Error;
+use crate::ledger;
+use crate::model::*;
+use crate::schema::*;
+use axum::response::{IntoResponse, Response};
+use diesel::prelude::*;
+use finance_lib::AuditEntity;
+
+/// An entity of a book that postings refer to by name.
+#[derive(Clone, Copy)]
+pub enum Reference {
+    Account,
+    Currency,
+}
+
+/// Decimal points of a currency of the book that is not in the trash.
+fn currency_decimal_points(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    symbol: &String,
+) -> QueryResult<Option<i32>> {
+    currencies::table
+        .select(currencies::dsl::decimal_points)
+        .filter(
+            currencies::dsl::user_name
+                .eq(owner)
+                .and(currencies::dsl::book_name.eq(book_name))
+                .and(currencies::dsl::symbol.eq(symbol))
+                .and(currencies::dsl::deleted_at.is_null()),
+        )
+        .first::<i32>(conn)
+        .optional()
+}
+
+/// Whether the account or currency exists in the book and is not in the trash.
+pub fn exists(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    reference: Reference,
+    name: &String,
+) -> QueryResult<bool> {
+    match reference {
+        Reference::Account => {
+            let account = accounts::table
+                .select(accounts::dsl::name)
+                .filter(
+                    accounts::dsl::user_name
+                        .eq(owner)
+                        .and(accounts::dsl::book_name.eq(book_name))
+                        .and(accounts::dsl::name.eq(name))
+                        .and(accounts::dsl::deleted_at.is_null()),
+                )
+                .first::<String>(conn)
+                .optional()?;
+            Ok(account.is_some())
+        }
+        Reference::Currency => Ok(currency_decimal_points(conn, owner, book_name, name)?.is_some()),
+    }
+}
+
+/// Refuses postings whose account or currency is not part of the book.
+pub fn check_posting(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    posting: &Posting,
+) -> QueryResult<Result<(), Response>> {
+    for (reference, field, name) in [
+        (Reference::Account, "account_name", &posting.account_name),
+        (Reference::Currency, "currency", &posting.currency),
+    ] {
+        if !exists(conn, owner, book_name, reference, name)? {
+            return Ok(Err(
+                Error::InvalidField(field, unknown(reference, name)).into_response()
+            ));
+        }
+    }
+    Ok(Ok(()))
+}
+
+fn unknown(reference: Reference, name: &String) -> String {
+    match reference {
+        Reference::Account => format!("Account '{}' does not exist.", name),
+        Reference::Currency => format!("Currency '{}' does not exist.", name),
+    }
+}
+
+/// All postings of the book, including those of transactions in the trash, that use the account
+/// or currency.
+pub fn referencing_postings(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    reference: Reference,
+    name: &String,
+) -> QueryResult<Vec<Posting>> {
+    let query = postings::table
+        .filter(
+            postings::dsl::user_name
+                .eq(owner)
+                .and(postings::dsl::book_name.eq(book_name)),
+        )
+        .into_boxed();
+    let query = match reference {
+        Reference::Account => query.filter(postings::dsl::account_name.eq(name)),
+        Reference::Currency => query.filter(postings::dsl::currency.eq(name)),
+    };
+    query.load::<Posting>(conn)
+}
+
+/// Makes sure no posting uses the account or currency `name` any more, so it can be deleted. With
+/// `reassign_to` the postings are moved there, otherwise any posting using it is a conflict.
+#[allow(clippy::result_large_err)]
+pub fn release<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    reference: Reference,
+    name: &String,
+    reassign_to: Option<&String>,
+) -> QueryResult<Result<(), Response>> {
+    let postings = referencing_postings(conn, claim.owner(), book_name, reference, name)?;
+    if postings.is_empty() {
+        return Ok(Ok(()));
+    }
+    let target = match reassign_to {
+        Some(target) => target,
+        None => {
+            return Ok(Err(Error::InUse(format!(
+                "{} '{}' is used by {} postings, reassign them to delete it.",
+                match reference {
+                    Reference::Account => "Account",
+                    Reference::Currency => "Currency",
+                },
+                name,
+                postings.len()
+            ))
+            .into_response()))
+        }
+    };
+    if let Err(response) = check_target(conn, claim.owner(), book_name, reference, name, target)? {
+        return Ok(Err(response));
+    }
+    reassign(conn, claim, book_name, reference, postings, target)
+}
+
+/// Checks that postings can be moved from `name` to `target`. Amounts are stored in the smallest
+/// unit, so currencies need the same decimal points.
+#[allow(clippy::result_large_err)]
+pub fn check_target(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    reference: Reference,
+    name: &String,
+    target: &String,
+) -> QueryResult<Result<(), Response>> {
+    if name == target {
+        return Ok(Err(Error::Invalid(
+            "Postings cannot be reassigned to where they already are.".to_owned(),
+        )
+        .into_response()));
+    }
+    match reference {
+        Reference::Account => {
+            if !exists(conn, owner, book_name, reference, target)? {
+                return Ok(Err(
+                    Error::Invalid(unknown(reference, target)).into_response()
+                ));
+            }
+        }
+        Reference::Currency => {
+            let target_decimal_points = currency_decimal_points(conn, owner, book_name, target)?;
+            let target_decimal_points = match target_decimal_points {
+                Some(decimal_points) => decimal_points,
+                None => {
+                    return Ok(Err(
+                        Error::Invalid(unknown(reference, target)).into_response()
+                    ))
+                }
+            };
+            let decimal_points = currencies::table
+                .select(currencies::dsl::decimal_points)
+                .filter(
+                    currencies::dsl::user_name
+                        .eq(owner)
+                        .and(currencies::dsl::book_name.eq(book_name))
+                        .and(currencies::dsl::symbol.eq(name)),
+                )
+                .first::<i32>(conn)
+                .optional()?;
+            if decimal_points.is_some_and(|decimal_points| decimal_points != target_decimal_points)
+            {
+                return Ok(Err(Error::Invalid(format!(
+                    "Currencies '{}' and '{}' have different decimal points.",
+                    name, target
+                ))
+                .into_response()));
+            }
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Moves the postings to `target`. Posted transactions of immutable books and closed periods are
+/// refused like any other change of their postings.
+#[allow(clippy::result_large_err)]
+pub fn reassign<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    reference: Reference,
+    postings: Vec<Posting>,
+    target: &str,
+) -> QueryResult<Result<(), Response>> {
+    for posting in &postings {
+        if let Err(refusal) = ledger::check_change(
+            conn,
+            claim.owner(),
+            book_name,
+            posting.transaction_id,
+            posting.valuta.as_slice(),
+        )? {
+            return Ok(Err(refusal.into_response()));
+        }
+    }
+    rewrite(conn, claim, book_name, reference, postings, target)?;
+    Ok(Ok(()))
+}
+
+/// Points the postings at `target` and records each one. Without any checks, as renaming an
+/// account or currency does not change what was booked. A rename has moved the postings already,
+/// the keys of the postings follow their account and currency.
+pub fn rewrite<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    reference: Reference,
+    postings: Vec<Posting>,
+    target: &str,
+) -> QueryResult<()> {
+    for before in postings {
+        let after = match reference {
+            Reference::Account => Posting {
+                account_name: target.to_owned(),
+                ..before.clone()
+            },
+            Reference::Currency => Posting {
+                currency: target.to_owned(),
+                ..before.clone()
+            },
+        };
+        diesel::update(postings::table)
+            .set((
+                postings::dsl::account_name.eq(&after.account_name),
+                postings::dsl::currency.eq(&after.currency),
+            ))
+            .filter(
+                postings::dsl::user_name
+                    .eq(claim.owner())
+                    .and(postings::dsl::book_name.eq(book_name))
+                    .and(postings::dsl::id.eq(before.id)),
+            )
+            .execute(conn)?;
+        audit::updated(
+            conn,
+            claim,
+            book_name,
+            AuditEntity::Posting,
+            &before.id.to_string(),
+            &before.to_user_struct(),
+            &after.to_user_struct(),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,480 @@
+use crate::audit;
+use crate::auth::{CanWriteTransactions, Claim, IsBookOwner};
+use crate::db::{self, DbConnection};
+use crate::error::Error;
+use crate::integrity::{self, Reference};
+use crate::model::*;
+use crate::reports;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{Duration, NaiveDateTime};
+use diesel::dsl::sum;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use finance_lib::{AuditEntity, NewTransaction};
+use std::sync::PoisonError;
+
+/// Reason why a transaction or its postings cannot be changed.
+pub enum Refusal {
+    /// The transaction is posted in an immutable book and may only be reversed.
+    Final(i64),
+    /// The change touches a date before the lock date of the book.
+    Closed(NaiveDateTime),
+}
+
+impl IntoResponse for Refusal {
+    fn into_response(self) -> Response {
+        match self {
+            Refusal::Final(transaction_id) => Error::TransactionFinal(transaction_id),
+            Refusal::Closed(lock_date) => Error::PeriodClosed(lock_date),
+        }
+        .into_response()
+    }
+}
+
+/// Checks whether the transaction may be changed and whether `dates` the change introduces are
+/// still open. A missing transaction is not refused. Locks the transaction, so it has to be called
+/// in the database transaction changing it.
+pub fn check_change(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &str,
+    transaction_id: i64,
+    dates: &[NaiveDateTime],
+) -> QueryResult<Result<(), Refusal>> {
+    let book = books::table
+        .select((books::dsl::immutable, books::dsl::lock_date))
+        .filter(
+            books::dsl::name
+                .eq(book_name)
+                .and(books::dsl::user_name.eq(owner)),
+        )
+        .first::<(bool, Option<NaiveDateTime>)>(conn)
+        .optional()?;
+    let (immutable, lock_date) = match book {
+        Some(book) => book,
+        None => return Ok(Ok(())),
+    };
+    let query = transactions::table
+        .select((transactions::dsl::posted, transactions::dsl::time))
+        .filter(
+            transactions::dsl::user_name
+                .eq(owner)
+                .and(transactions::dsl::book_name.eq(book_name))
+                .and(transactions::dsl::id.eq(transaction_id)),
+        );
+    let transaction = db::for_update!(query)
+        .first::<(bool, NaiveDateTime)>(conn)
+        .optional()?;
+    if immutable && transaction.is_some_and(|(posted, _)| posted) {
+        return Ok(Err(Refusal::Final(transaction_id)));
+    }
+    if let Some(lock_date) = lock_date {
+        let mut dates = dates.iter().chain(transaction.iter().map(|(_, time)| time));
+        if dates.any(|date| *date < lock_date) {
+            return Ok(Err(Refusal::Closed(lock_date)));
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Marks a draft transaction as posted. In immutable books it can no longer be changed afterwards.
+pub async fn post_transaction(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, transaction_id)): Path<(String, i64)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let query = transactions::table.filter(
+            transactions::dsl::user_name
+                .eq(claim.owner())
+                .and(transactions::dsl::book_name.eq(&book_name))
+                .and(transactions::dsl::id.eq(transaction_id))
+                .and(transactions::dsl::deleted_at.is_null()),
+        );
+        let before = db::for_update!(query)
+            .first::<Transaction>(conn)
+            .optional()?;
+        let before = match before {
+            Some(before) if !before.posted => before,
+            before => return Ok(Ok(before.is_some())),
+        };
+        if let Err(refusal) = check_change(conn, claim.owner(), &book_name, transaction_id, &[])? {
+            return Ok(Err(refusal));
+        }
+        diesel::update(transactions::table)
+            .set(transactions::dsl::posted.eq(true))
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id)),
+            )
+            .execute(conn)?;
+        let mut after = before.to_user_struct();
+        after.posted = Some(true);
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Transaction,
+            &transaction_id.to_string(),
+            &before.to_user_struct(),
+            &after,
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(true))
+    });
+    match result {
+        Ok(Ok(true)) => Ok(().into_response()),
+        Ok(Ok(false)) => Err(Error::NotFound(format!(
+            "Transaction {} does not exist.",
+            transaction_id
+        ))
+        .into_response()),
+        Ok(Err(refusal)) => Err(refusal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Books a posted transaction that cancels the given one by negating all of its postings and
+/// links it to the original. Returns the id of the reversal. Drafts are changed or deleted instead.
+pub async fn reverse_transaction(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, transaction_id)): Path<(String, i64)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let original = transactions::table
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .first::<Transaction>(conn)
+            .optional()?;
+        let original = match original {
+            Some(original) => original,
+            None => return Ok(Ok(None)),
+        };
+        if !original.posted {
+            return Ok(Err(Error::Invalid(format!(
+                "Transaction {} is a draft, only posted transactions are reversed.",
+                transaction_id
+            ))
+            .into_response()));
+        }
+        let description = match &original.description {
+            Some(description) => format!("Reversal of {}: {}", transaction_id, description),
+            None => format!("Reversal of {}", transaction_id),
+        };
+        let mut reversal = Transaction::from_new_user_struct(
+            &NewTransaction {
+                description: Some(description),
+                time: None,
+                posted: Some(true),
+            },
+            UserAndBookInfo {
+                book_name: &book_name,
+                user_name: claim.owner(),
+            },
+        )
+        .map_err(|_| diesel::result::Error::RollbackTransaction)?;
+        reversal.reverses = Some(transaction_id);
+        if let Err(refusal) = check_change(
+            conn,
+            claim.owner(),
+            &book_name,
+            reversal.id,
+            &[reversal.time()],
+        )? {
+            return Ok(Err(refusal.into_response()));
+        }
+        let lock_date = books::table
+            .select(books::dsl::lock_date)
+            .filter(
+                books::dsl::name
+                    .eq(&book_name)
+                    .and(books::dsl::user_name.eq(claim.owner())),
+            )
+            .first::<Option<NaiveDateTime>>(conn)?;
+        diesel::insert_into(transactions::table)
+            .values(&reversal)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Transaction,
+            &reversal.id.to_string(),
+            &reversal.to_user_struct(),
+        )?;
+
+        let postings = postings::table
+            .filter(
+                postings::dsl::user_name
+                    .eq(claim.owner())
+                    .and(postings::dsl::book_name.eq(&book_name))
+                    .and(postings::dsl::transaction_id.eq(original.id)),
+            )
+            .load::<Posting>(conn)?;
+        for posting in postings {
+            let mirrored = Posting {
+                id: crate::SNOWFLAKE_GENERATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .real_time_generate(),
+                transaction_id: reversal.id,
+                amount: posting
+                    .amount
+                    .checked_neg()
+                    .ok_or(diesel::result::Error::RollbackTransaction)?,
+                // Value dates in closed periods move to the date of the reversal.
+                valuta: posting
+                    .valuta
+                    .filter(|valuta| lock_date.is_none_or(|lock_date| *valuta >= lock_date)),
+                ..posting
+            };
+            diesel::insert_into(postings::table)
+                .values(&mirrored)
+                .execute(conn)?;
+            audit::created(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Posting,
+                &mirrored.id.to_string(),
+                &mirrored.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(Some(reversal.id)))
+    });
+    match result {
+        Ok(Ok(Some(id))) => Ok(Json(id).into_response()),
+        Ok(Ok(None)) => Err(Error::NotFound(format!(
+            "Transaction {} does not exist.",
+            transaction_id
+        ))
+        .into_response()),
+        Ok(Err(response)) => Err(response),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(Error::Conflict(format!(
+                "Transaction {} is already reversed.",
+                transaction_id
+            ))
+            .into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Closes the book for everything dated before the lock date, which can only move forward. With a
+/// retained earnings account, the balances of the closing accounts up to the lock date are moved
+/// there by a posted transaction at the end of the closed period, whose id is returned.
+pub async fn close_period(
+    claim: Claim<IsBookOwner>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+    Json(period_close): Json<finance_lib::PeriodClose>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let query = books::table.filter(
+            books::dsl::name
+                .eq(&book_name)
+                .and(books::dsl::user_name.eq(claim.owner())),
+        );
+        let before = db::for_update!(query).first::<Book>(conn)?;
+        if let Some(lock_date) = before.lock_date {
+            if period_close.lock_date <= lock_date {
+                return Ok(Err(Error::Conflict(format!(
+                    "The book is already closed before {}, periods cannot be reopened.",
+                    lock_date
+                ))
+                .into_response()));
+            }
+        }
+        if let Some(retained_earnings) = &period_close.retained_earnings {
+            if !integrity::exists(
+                conn,
+                claim.owner(),
+                &book_name,
+                Reference::Account,
+                retained_earnings,
+            )? {
+                return Ok(Err(Error::InvalidField(
+                    "retained_earnings",
+                    format!("Account '{}' does not exist.", retained_earnings),
+                )
+                .into_response()));
+            }
+        }
+        let closing_transaction = match &period_close.retained_earnings {
+            Some(retained_earnings) => match book_closing_transaction(
+                conn,
+                &claim,
+                &book_name,
+                retained_earnings,
+                &period_close,
+            )? {
+                Ok(closing_transaction) => closing_transaction,
+                Err(response) => return Ok(Err(response)),
+            },
+            None => None,
+        };
+        diesel::update(books::table)
+            .set(books::dsl::lock_date.eq(period_close.lock_date))
+            .filter(
+                books::dsl::name
+                    .eq(&book_name)
+                    .and(books::dsl::user_name.eq(claim.owner())),
+            )
+            .execute(conn)?;
+        let mut after = before.to_user_struct();
+        after.lock_date = Some(period_close.lock_date);
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Book,
+            &book_name,
+            &before.to_user_struct(),
+            &after,
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(closing_transaction))
+    });
+    match result {
+        Ok(Ok(closing_transaction)) => Ok(Json(closing_transaction).into_response()),
+        Ok(Err(response)) => Err(response),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Books the real balances of the closing accounts and the accounts below them up to the lock
+/// date into `retained_earnings`. Returns `None` if all balances are zero.
+#[allow(clippy::result_large_err)]
+fn book_closing_transaction<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    retained_earnings: &str,
+    period_close: &finance_lib::PeriodClose,
+) -> QueryResult<Result<Option<i64>, Response>> {
+    let balances = postings::table
+        .inner_join(
+            transactions::table.on(transactions::dsl::id
+                .eq(postings::dsl::transaction_id)
+                .and(transactions::dsl::user_name.eq(postings::dsl::user_name))
+                .and(transactions::dsl::book_name.eq(postings::dsl::book_name))),
+        )
+        .group_by((postings::dsl::account_name, postings::dsl::currency))
+        .filter(
+            postings::dsl::user_name
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(book_name))
+                .and(postings::dsl::budget.eq(false))
+                .and(transactions::dsl::deleted_at.is_null())
+                .and(transactions::dsl::time.lt(period_close.lock_date)),
+        )
+        .select((
+            postings::dsl::account_name,
+            postings::dsl::currency,
+            sum(postings::dsl::amount),
+        ))
+        .load::<(String, String, Option<i64>)>(conn)?;
+    let mut closings = Vec::new();
+    for (account_name, currency, amount) in balances {
+        let closed = period_close
+            .closing_accounts
+            .iter()
+            .any(|account| reports::is_below(&account_name, account));
+        let amount = match amount {
+            Some(amount) if closed && amount != 0 => amount,
+            _ => continue,
+        };
+        // The closing posting takes the negated balance, which has to fit a posting amount.
+        let amounts = i32::try_from(amount)
+            .ok()
+            .and_then(|amount| Some((amount, amount.checked_neg()?)));
+        match amounts {
+            Some((amount, negated)) => closings.push((account_name, currency, amount, negated)),
+            None => {
+                return Ok(Err(Error::Invalid(format!(
+                    "The balance of account '{}' in {} is too large to close.",
+                    account_name, currency
+                ))
+                .into_response()))
+            }
+        }
+    }
+    if closings.is_empty() {
+        return Ok(Ok(None));
+    }
+
+    let transaction = Transaction::from_new_user_struct(
+        &NewTransaction {
+            description: Some(format!(
+                "Closing of the period before {}",
+                period_close.lock_date
+            )),
+            time: Some(period_close.lock_date - Duration::seconds(1)),
+            posted: Some(true),
+        },
+        UserAndBookInfo {
+            book_name,
+            user_name: claim.owner(),
+        },
+    )
+    .map_err(|_| diesel::result::Error::RollbackTransaction)?;
+    diesel::insert_into(transactions::table)
+        .values(&transaction)
+        .execute(conn)?;
+    audit::created(
+        conn,
+        claim,
+        book_name,
+        AuditEntity::Transaction,
+        &transaction.id.to_string(),
+        &transaction.to_user_struct(),
+    )?;
+
+    for (account_name, currency, amount, negated) in closings {
+        for (account_name, amount) in [
+            (account_name, negated),
+            (retained_earnings.to_owned(), amount),
+        ] {
+            let posting = Posting {
+                id: crate::SNOWFLAKE_GENERATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .real_time_generate(),
+                transaction_id: transaction.id,
+                valuta: None,
+                book_name: book_name.clone(),
+                user_name: claim.owner().clone(),
+                account_name,
+                currency: currency.clone(),
+                amount,
+                budget: false,
+            };
+            diesel::insert_into(postings::table)
+                .values(&posting)
+                .execute(conn)?;
+            audit::created(
+                conn,
+                claim,
+                book_name,
+                AuditEntity::Posting,
+                &posting.id.to_string(),
+                &posting.to_user_struct(),
+            )?;
+        }
+    }
+    Ok(Ok(Some(transaction.id)))
+}
@@ -0,0 +1,375 @@
+use crate::auth::{generate_token, issue_token, CanAdmin, Claim, IsServerAdmin};
+use crate::db::DbConnection;
+use crate::error::Error;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use finance_lib::{Capability, NewToken};
+use lazy_static::lazy_static;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const DEFAULT_SESSION_HOURS: i64 = 12;
+/// How long a user may take at the OpenID Connect provider before the login is discarded.
+const OIDC_LOGIN_TIMEOUT_MINUTES: i64 = 10;
+
+lazy_static! {
+    static ref DUMMY_PASSWORD_HASH: String =
+        hash_password(&generate_token()).expect("Could not hash the dummy password");
+}
+
+struct PendingOidcLogin {
+    nonce: Nonce,
+    pkce_verifier: PkceCodeVerifier,
+    started: NaiveDateTime,
+}
+
+/// How the server is registered at the OpenID Connect provider users log in with.
+#[derive(Clone)]
+pub struct OidcConfig {
+    pub issuer_url: IssuerUrl,
+    pub client_id: ClientId,
+    pub client_secret: Option<ClientSecret>,
+    pub redirect_url: RedirectUrl,
+}
+
+impl OidcConfig {
+    /// Reads `OIDC_ISSUER_URL`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET` and `OIDC_REDIRECT_URL`.
+    /// Without an issuer URL the login is not configured.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let issuer_url = match env::var("OIDC_ISSUER_URL") {
+            Ok(url) => IssuerUrl::new(url)?,
+            Err(_) => return Ok(None),
+        };
+        Ok(Some(Self {
+            issuer_url,
+            client_id: ClientId::new(env::var("OIDC_CLIENT_ID")?),
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok().map(ClientSecret::new),
+            redirect_url: RedirectUrl::new(env::var("OIDC_REDIRECT_URL")?)?,
+        }))
+    }
+}
+
+/// The OpenID Connect login, handed to the routes as an extension. The provider is discovered
+/// on first use, logins started at it wait here for their callback.
+pub struct Oidc {
+    config: Option<OidcConfig>,
+    client: OnceCell<CoreClient>,
+    pending: Mutex<HashMap<String, PendingOidcLogin>>,
+}
+
+impl Oidc {
+    pub fn new(config: Option<OidcConfig>) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            client: OnceCell::new(),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn client(&self) -> Result<&CoreClient, Box<dyn std::error::Error>> {
+        let config = self.config.as_ref().ok_or("OIDC_ISSUER_URL is not set")?;
+        self.client
+            .get_or_try_init(|| async {
+                let provider_metadata = CoreProviderMetadata::discover_async(
+                    config.issuer_url.clone(),
+                    async_http_client,
+                )
+                .await?;
+                let client = CoreClient::from_provider_metadata(
+                    provider_metadata,
+                    config.client_id.clone(),
+                    config.client_secret.clone(),
+                )
+                .set_redirect_uri(config.redirect_url.clone());
+                Ok::<_, Box<dyn std::error::Error>>(client)
+            })
+            .await
+    }
+}
+
+fn session_duration() -> Duration {
+    let hours = env::var("SESSION_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(DEFAULT_SESSION_HOURS);
+    Duration::hours(hours)
+}
+
+/// Issues a short-lived token for a user who logged in interactively.
+#[allow(clippy::result_large_err)]
+fn issue_session(
+    conn: &mut DbConnection,
+    user_name: &String,
+) -> Result<finance_lib::CreatedToken, Response> {
+    let new_token = NewToken {
+        name: format!("session-{}", &generate_token()[..16]),
+        expires: Some(Utc::now().naive_utc() + session_duration()),
+        capability: Some(Capability::Admin),
+        books: None,
+    };
+    issue_token(conn, user_name, &new_token)
+}
+
+fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[allow(clippy::result_large_err)]
+fn set_password(
+    conn: &mut DbConnection,
+    user_name: &String,
+    password: &str,
+) -> Result<Response, Response> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(Error::InvalidField(
+            "password",
+            format!(
+                "Passwords must have at least {} characters.",
+                MIN_PASSWORD_LENGTH
+            ),
+        )
+        .into_response());
+    }
+    let password_hash = match hash_password(password) {
+        Ok(hash) => hash,
+        Err(e) => {
+            tracing::error!("could not hash the password: {}", e);
+            return Err(Error::Internal.into_response());
+        }
+    };
+    let result = diesel::update(users::table)
+        .set(users::dsl::password_hash.eq(password_hash))
+        .filter(users::dsl::name.eq(user_name))
+        .execute(conn);
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Ok(_) => {
+            Err(Error::NotFound(format!("User '{}' does not exist.", user_name)).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn login(
+    State(pool): State<ConnectionPool>,
+    Json(login): Json<finance_lib::Login>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = users::table
+        .filter(users::dsl::name.eq(&login.name))
+        .load::<User>(conn);
+    let users = match result {
+        Ok(users) => users,
+        Err(e) => return Err(Error::from(e).into_response()),
+    };
+    // Unknown users and users without a password are checked against a dummy hash, so that the
+    // time of the answer does not tell which user names exist.
+    let user = users.first();
+    let password_hash = user.and_then(|user| user.password_hash.as_deref());
+    let verified = verify_password(
+        &login.password,
+        password_hash.unwrap_or(&DUMMY_PASSWORD_HASH),
+    );
+    let authenticated =
+        verified && password_hash.is_some() && user.is_some_and(|user| !user.disabled);
+    if !authenticated {
+        return Err(Error::NotAuthenticated("Authentication failed.".to_owned()).into_response());
+    }
+    let created = issue_session(conn, &login.name)?;
+    Ok(Json(created).into_response())
+}
+
+pub async fn change_password(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+    Json(change): Json<finance_lib::PasswordChange>,
+) -> Result<Response, Response> {
+    let confirmed = match (&claim.user.password_hash, &change.current_password) {
+        (None, _) => true,
+        (Some(hash), Some(current_password)) => verify_password(current_password, hash),
+        (Some(_), None) => false,
+    };
+    if !confirmed {
+        return Err(Error::InvalidField(
+            "current_password",
+            "The current password is wrong.".to_owned(),
+        )
+        .into_response());
+    }
+    let conn = &mut get_connection(&pool)?;
+    set_password(conn, &claim.user.name, &change.password)
+}
+
+pub async fn change_user_password(
+    _claim: Claim<IsServerAdmin>,
+    Path(user_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+    Json(new_password): Json<finance_lib::NewPassword>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    set_password(conn, &user_name, &new_password.password)
+}
+
+/// Redirects to the OpenID Connect provider. It sends the user back to
+/// [`oidc_login_callback`].
+pub async fn oidc_login(Extension(oidc): Extension<Arc<Oidc>>) -> Result<Response, Response> {
+    let client = match oidc.client().await {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("OpenID Connect is not available: {}", e);
+            return Err(
+                Error::NotFound("OpenID Connect login is not configured.".to_owned())
+                    .into_response(),
+            );
+        }
+    };
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (auth_url, csrf_token, nonce) = client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scope(Scope::new("openid".to_owned()))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let now = Utc::now().naive_utc();
+    let mut pending = match oidc.pending.lock() {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!("the pending OpenID Connect logins are poisoned: {}", e);
+            return Err(Error::Internal.into_response());
+        }
+    };
+    pending.retain(|_, login| now - login.started < Duration::minutes(OIDC_LOGIN_TIMEOUT_MINUTES));
+    pending.insert(
+        csrf_token.secret().clone(),
+        PendingOidcLogin {
+            nonce,
+            pkce_verifier,
+            started: now,
+        },
+    );
+    Ok(Redirect::to(auth_url.as_str()).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    code: String,
+    state: String,
+}
+
+finance_lib::object_schema!(OidcCallback {
+    code: String,
+    state: String,
+});
+
+/// Completes an OpenID Connect login and issues a session token for the user whose
+/// `oidc_subject` matches the subject of the ID token.
+pub async fn oidc_login_callback(
+    Extension(oidc): Extension<Arc<Oidc>>,
+    State(pool): State<ConnectionPool>,
+    Query(callback): Query<OidcCallback>,
+) -> Result<Response, Response> {
+    let client = match oidc.client().await {
+        Ok(client) => client,
+        Err(_) => {
+            return Err(
+                Error::NotFound("OpenID Connect login is not configured.".to_owned())
+                    .into_response(),
+            )
+        }
+    };
+    let pending = match oidc.pending.lock() {
+        Ok(mut pending) => pending.remove(&callback.state),
+        Err(e) => {
+            tracing::error!("the pending OpenID Connect logins are poisoned: {}", e);
+            return Err(Error::Internal.into_response());
+        }
+    };
+    let pending = match pending {
+        Some(login)
+            if Utc::now().naive_utc() - login.started
+                < Duration::minutes(OIDC_LOGIN_TIMEOUT_MINUTES) =>
+        {
+            login
+        }
+        _ => {
+            return Err(
+                Error::NotAuthenticated("Unknown or expired login.".to_owned()).into_response(),
+            )
+        }
+    };
+
+    let token_response = client
+        .exchange_code(AuthorizationCode::new(callback.code))
+        .set_pkce_verifier(pending.pkce_verifier)
+        .request_async(async_http_client)
+        .await;
+    let subject = token_response.ok().and_then(|response| {
+        let id_token = response.id_token()?.clone();
+        let claims = id_token
+            .claims(&client.id_token_verifier(), &pending.nonce)
+            .ok()?;
+        Some(claims.subject().to_string())
+    });
+    let subject = match subject {
+        Some(subject) => subject,
+        None => {
+            return Err(Error::NotAuthenticated("Authentication failed.".to_owned()).into_response())
+        }
+    };
+
+    let conn = &mut get_connection(&pool)?;
+    let result = users::table
+        .filter(
+            users::dsl::oidc_subject
+                .eq(&subject)
+                .and(users::dsl::disabled.eq(false)),
+        )
+        .load::<User>(conn);
+    match result {
+        Ok(users) if users.len() == 1 => {
+            let created = issue_session(conn, &users[0].name)?;
+            Ok(Json(created).into_response())
+        }
+        Ok(_) => Err(
+            Error::NotAuthenticated("No user is linked to this identity.".to_owned())
+                .into_response(),
+        ),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
@@ -1,24 +1,48 @@
+mod admin;
+mod audit;
+mod auth;
 mod db;
+mod error;
+mod etag;
+mod idempotency;
+mod integrity;
+mod ledger;
+mod login;
+mod members;
 mod model;
+mod openapi;
+mod reports;
 mod schema;
+#[cfg(test)]
+mod tests;
+mod trash;
 
-use axum::extract::{FromRequestParts, Path, State};
-use axum::http::request::Parts;
-use axum::http::StatusCode;
+use auth::{CanAdmin, CanRead, CanWriteTransactions, Claim, IsBookOwner};
+use axum::extract::{Path, Query, State};
+use axum::middleware;
 use axum::response::{IntoResponse, Response};
-use axum::routing::{delete, get, post};
-use axum::{async_trait, Json, Router};
-use axum_auth::AuthBearer;
+use axum::{Extension, Json, Router};
+use chrono::Utc;
+use db::DbConnection;
 use diesel::dsl::sum;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::result::DatabaseErrorKind;
+use error::Error;
+use etag::IfMatch;
+use finance_lib::{
+    AccountPatch, AuditEntity, BookPatch, BookRole, CurrencyPatch, DeleteOptions, Merge, Page,
+    PostingPatch, TransactionPatch,
+};
+use idempotency::IdempotencyKey;
+use integrity::Reference;
 use model::*;
+use openapi::{operation, Api};
 use schema::*;
 use snowflake::SnowflakeIdGenerator;
-use std::error::Error;
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use lazy_static::lazy_static;
 
@@ -27,117 +51,441 @@ lazy_static! {
         Mutex::new(SnowflakeIdGenerator::new(1, 1));
 }
 
-type ConnectionPool = Pool<ConnectionManager<MysqlConnection>>;
+type ConnectionPool = Pool<ConnectionManager<DbConnection>>;
 
+/// Takes a connection from the pool. A request must not hold one connection while it takes
+/// another: with SQLite the pool has a single connection, so the second one would wait until the
+/// pool gives up after its connection timeout. Extractors give theirs back before the handler runs.
+#[allow(clippy::result_large_err)]
 fn get_connection(
     pool: &ConnectionPool,
-) -> Result<PooledConnection<ConnectionManager<MysqlConnection>>, Response> {
-    pool.get()
-        .or(Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()))
+) -> Result<PooledConnection<ConnectionManager<DbConnection>>, Response> {
+    pool.get().map_err(|e| {
+        tracing::error!("could not get a database connection: {}", e);
+        Error::Internal.into_response()
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn found<T>(result: QueryResult<T>, message: String) -> Result<T, Response> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(diesel::result::Error::NotFound) => Err(Error::NotFound(message).into_response()),
+        Err(error) => Err(Error::from(error).into_response()),
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    if command.as_deref() == Some("openapi") {
+        println!("{:#}", api().document());
+        return Ok(());
+    }
+
     let pool = db::get_connection_pool();
+    match command.as_deref() {
+        Some("create-admin") => {
+            let name = args
+                .next()
+                .ok_or("Usage: finance_server create-admin <user name>")?;
+            let created = admin::create_admin(&pool, &name)?;
+            println!("Token '{}': {}", created.token.name, created.secret);
+            return Ok(());
+        }
+        Some(command) => return Err(format!("Unknown command '{}'.", command).into()),
+        None => {}
+    }
+
+    tokio::spawn(trash::purge_periodically(pool.clone()));
+
+    let router = router(pool, login::Oidc::new(login::OidcConfig::from_env()?));
 
-    let router = Router::new()
-        .route("/", get(root))
-        .route("/books/", get(get_books))
-        .route("/book/", post(create_book))
-        .route("/book/:book_name/update", post(update_book))
-        .route("/book/:book_name", delete(delete_book).get(get_book))
-        .route("/book/:book_name/currency/", post(create_currency))
-        .route("/book/:book_name/currency/update", post(update_currency))
-        .route(
+    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
+    tracing::debug!("listening on {}", addr);
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service())
+        .await
+        .unwrap();
+    Ok(())
+}
+
+/// All routes of the server, documented as they are registered.
+fn api() -> Api {
+    Api::default()
+        .get("/", root, operation("Greets the user of the token.").returns_text())
+        .post(
+            "/login",
+            login::login,
+            operation("Logs in with a password and issues a session token.")
+                .returns::<finance_lib::CreatedToken>(),
+        )
+        .get(
+            "/login/oidc",
+            login::oidc_login,
+            operation("Starts a login at the OpenID Connect provider.").redirects(),
+        )
+        .get(
+            "/login/oidc/callback",
+            login::oidc_login_callback,
+            operation("Completes an OpenID Connect login and issues a session token.")
+                .returns::<finance_lib::CreatedToken>(),
+        )
+        .post(
+            "/password",
+            login::change_password,
+            operation("Changes the password of the user."),
+        )
+        .get(
+            "/tokens/",
+            auth::get_tokens,
+            operation("Lists the tokens of the user.").returns::<Vec<finance_lib::Token>>(),
+        )
+        .post(
+            "/token/",
+            auth::create_token,
+            operation("Creates a token for the user.").returns::<finance_lib::CreatedToken>(),
+        )
+        .delete(
+            "/token/:token_id",
+            auth::revoke_token,
+            operation("Revokes a token of the user."),
+        )
+        .get(
+            "/users/",
+            admin::get_users,
+            operation("Lists all users.").returns::<Vec<finance_lib::User>>(),
+        )
+        .post(
+            "/user/",
+            admin::create_user,
+            operation("Creates a user along with a first token.")
+                .returns::<finance_lib::CreatedToken>(),
+        )
+        .post(
+            "/user/:user_name/update",
+            admin::update_user,
+            operation("Updates a user."),
+        )
+        .post(
+            "/user/:user_name/token",
+            admin::create_user_token,
+            operation("Creates a token for a user.").returns::<finance_lib::CreatedToken>(),
+        )
+        .post(
+            "/user/:user_name/password",
+            login::change_user_password,
+            operation("Sets the password of a user."),
+        )
+        .get(
+            "/user/:user_name",
+            admin::get_user,
+            operation("A user.").returns::<finance_lib::User>(),
+        )
+        .delete(
+            "/user/:user_name",
+            admin::delete_user,
+            operation("Deletes a user."),
+        )
+        .get(
+            "/books/",
+            get_books,
+            operation("Lists the names of the books of the user.").returns::<Vec<String>>(),
+        )
+        .get(
+            "/books/trash",
+            trash::get_book_trash,
+            operation("Lists the deleted books of the user.")
+                .returns::<Vec<finance_lib::TrashEntry>>(),
+        )
+        .post(
+            "/books/trash/:deleted_book_name/restore",
+            trash::restore_book,
+            operation("Restores a deleted book."),
+        )
+        .post(
+            "/book/",
+            create_book,
+            operation("Creates a book.").returns_text(),
+        )
+        .post(
+            "/book/:book_name/update",
+            update_book,
+            operation("Updates a book.").tagged(),
+        )
+        .delete(
+            "/book/:book_name",
+            delete_book,
+            operation("Moves a book to the trash.").returns_text(),
+        )
+        .get(
+            "/book/:book_name",
+            get_book,
+            operation("A book.").returns::<finance_lib::Book>().tagged(),
+        )
+        .patch(
+            "/book/:book_name",
+            patch_book,
+            operation("Updates some fields of a book.").tagged(),
+        )
+        .get(
+            "/book/:book_name/members",
+            members::get_members,
+            operation("Lists the members of a book.").returns::<Vec<finance_lib::BookMember>>(),
+        )
+        .post(
+            "/book/:book_name/member/",
+            members::add_member,
+            operation("Shares a book with a user."),
+        )
+        .post(
+            "/book/:book_name/member/:user_name/update",
+            members::update_member,
+            operation("Changes the role of a member."),
+        )
+        .delete(
+            "/book/:book_name/member/:user_name",
+            members::remove_member,
+            operation("Stops sharing a book with a user."),
+        )
+        .delete(
+            "/book/:book_name/membership",
+            members::leave_book,
+            operation("Leaves a book shared with the user."),
+        )
+        .get(
+            "/book/:book_name/audit",
+            audit::get_audit_log,
+            operation("A page of the audit log of a book.")
+                .returns::<Vec<finance_lib::AuditEntry>>(),
+        )
+        .get(
+            "/book/:book_name/audit/verify",
+            audit::verify_audit_log,
+            operation("Checks the hash chain of the audit log of a book.")
+                .returns::<finance_lib::AuditVerification>(),
+        )
+        .get(
+            "/book/:book_name/audit/:entity/:entity_id",
+            audit::get_entity_audit_log,
+            operation("The audit log of one entity of a book.")
+                .returns::<Vec<finance_lib::AuditEntry>>(),
+        )
+        .get(
+            "/book/:book_name/trash",
+            trash::get_trash,
+            operation("Lists the deleted entities of a book.")
+                .returns::<Vec<finance_lib::TrashEntry>>(),
+        )
+        .post(
+            "/book/:book_name/trash/:entity/:entity_id/restore",
+            trash::restore,
+            operation("Restores a deleted entity of a book."),
+        )
+        .post(
+            "/book/:book_name/currency/",
+            create_currency,
+            operation("Creates a currency."),
+        )
+        .post(
+            "/book/:book_name/currency/:currency_symbol/update",
+            update_currency,
+            operation("Updates a currency.").tagged(),
+        )
+        .post(
+            "/book/:book_name/currency/update",
+            update_currency_by_body,
+            operation("Updates the currency with the symbol in the body.")
+                .tagged()
+                .deprecated(),
+        )
+        .post(
+            "/book/:book_name/currency/:currency_symbol/merge",
+            merge_currency,
+            operation("Moves all postings of a currency to another one and deletes it."),
+        )
+        .get(
+            "/book/:book_name/currency/:currency_symbol",
+            get_currency,
+            operation("A currency.").returns::<finance_lib::Currency>().tagged(),
+        )
+        .delete(
             "/book/:book_name/currency/:currency_symbol",
-            get(get_currency).delete(delete_currency),
+            delete_currency,
+            operation("Moves a currency to the trash."),
+        )
+        .patch(
+            "/book/:book_name/currency/:currency_symbol",
+            patch_currency,
+            operation("Updates some fields of a currency.").tagged(),
+        )
+        .get(
+            "/book/:book_name/currencies",
+            get_currencies,
+            operation("Lists the symbols of the currencies of a book.").returns::<Vec<String>>(),
+        )
+        .post(
+            "/book/:book_name/account/",
+            create_account,
+            operation("Creates an account."),
+        )
+        .get(
+            "/book/:book_name/accounts",
+            get_accounts,
+            operation("Lists the names of the accounts of a book.").returns::<Vec<String>>(),
+        )
+        .post(
+            "/book/:book_name/account/:account_name/update",
+            update_account,
+            operation("Updates an account.").tagged(),
+        )
+        .post(
+            "/book/:book_name/account/:account_name/merge",
+            merge_account,
+            operation("Moves all postings of an account to another one and deletes it."),
+        )
+        .delete(
+            "/book/:book_name/account/:account_name",
+            delete_account,
+            operation("Moves an account to the trash."),
+        )
+        .get(
+            "/book/:book_name/account/:account_name",
+            get_account,
+            operation("An account.").returns::<finance_lib::Account>().tagged(),
         )
-        .route("/book/:book_name/currencies", get(get_currencies))
-        .route("/book/:book_name/account/", post(create_account))
-        .route("/book/:book_name/accounts", get(get_accounts))
-        .route(
+        .patch(
             "/book/:book_name/account/:account_name",
-            delete(delete_account).get(get_account),
+            patch_account,
+            operation("Updates some fields of an account.").tagged(),
         )
-        .route(
+        .post(
             "/book/:book_name/transaction/:transaction_id/update",
-            post(update_transaction),
+            update_transaction,
+            operation("Updates a transaction.").tagged(),
         )
-        .route("/book/:book_name/transaction", post(create_transaction))
-        .route("/book/:book_name/transactions", get(get_transactions))
-        .route(
+        .post(
+            "/book/:book_name/transaction",
+            create_transaction,
+            operation("Creates a transaction and returns its id.").returns::<i64>(),
+        )
+        .get(
+            "/book/:book_name/transactions",
+            get_transactions,
+            operation("A page of the ids of the transactions of a book, oldest first.")
+                .returns::<Vec<i64>>(),
+        )
+        .delete(
             "/book/:book_name/transaction/:transaction_id",
-            delete(delete_transaction).get(get_transaction),
+            delete_transaction,
+            operation("Moves a transaction to the trash."),
+        )
+        .get(
+            "/book/:book_name/transaction/:transaction_id",
+            get_transaction,
+            operation("A transaction.").returns::<finance_lib::Transaction>().tagged(),
+        )
+        .patch(
+            "/book/:book_name/transaction/:transaction_id",
+            patch_transaction,
+            operation("Updates some fields of a transaction.").tagged(),
+        )
+        .post(
+            "/book/:book_name/transaction/:transaction_id/post",
+            ledger::post_transaction,
+            operation("Posts a draft transaction, making it final in immutable books."),
+        )
+        .post(
+            "/book/:book_name/transaction/:transaction_id/reverse",
+            ledger::reverse_transaction,
+            operation("Books the reversal of a posted transaction and returns its id.")
+                .returns::<i64>(),
         )
-        .route(
+        .post(
+            "/book/:book_name/close",
+            ledger::close_period,
+            operation("Closes the periods of a book before a date and returns the id of the closing transaction, if one was booked.")
+                .returns::<Option<i64>>(),
+        )
+        .post(
             "/book/:book_name/transaction/:transaction_id/posting",
-            post(create_posting),
+            create_posting,
+            operation("Creates a posting and returns its id.").returns::<i64>(),
         )
-        .route(
+        .get(
             "/book/:book_name/transaction/:transaction_id/postings",
-            get(get_postings),
+            get_postings,
+            operation("Lists the ids of the postings of a transaction.").returns::<Vec<i64>>(),
+        )
+        .post(
+            "/book/:book_name/transaction/:transaction_id/posting/:posting_id/update",
+            update_posting,
+            operation("Updates a posting.").tagged(),
+        )
+        .delete(
+            "/book/:book_name/transaction/:transaction_id/posting/:posting_id",
+            delete_posting,
+            operation("Deletes a posting."),
+        )
+        .get(
+            "/book/:book_name/transaction/:transaction_id/posting/:posting_id",
+            get_posting,
+            operation("A posting.").returns::<finance_lib::Posting>().tagged(),
         )
-        .route(
+        .patch(
             "/book/:book_name/transaction/:transaction_id/posting/:posting_id",
-            delete(delete_posting).get(get_posting),
+            patch_posting,
+            operation("Updates some fields of a posting.").tagged(),
         )
-        .route(
+        .get(
             "/book/:book_name/account/:account_name/value",
-            get(account_value),
+            account_value,
+            operation("Balance of an account per currency, including budget postings.")
+                .returns::<Vec<finance_lib::CurrencyAmount>>(),
         )
-        .route(
+        .get(
+            "/book/:book_name/account/:account_name/register",
+            account_register,
+            operation("Postings to an account with their transactions, in the order of the journal.")
+                .returns::<Vec<finance_lib::RegisterEntry>>(),
+        )
+        .get(
+            "/book/:book_name/report/balances",
+            reports::balance_report,
+            operation("Balance of the accounts of the report at the end of each period.")
+                .returns::<Vec<finance_lib::BalancePoint>>(),
+        )
+        .get(
+            "/book/:book_name/report/flows",
+            reports::flow_report,
+            operation("Sums of the postings per period and account, for seeing where money went.")
+                .returns::<Vec<finance_lib::FlowAmount>>(),
+        )
+        .get(
             "/book/:book_name/account/:account_name/real_value",
-            get(real_account_value),
+            real_account_value,
+            operation("Balance of an account per currency, without budget postings.")
+                .returns::<Vec<finance_lib::CurrencyAmount>>(),
         )
-        .with_state(pool);
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    tracing::debug!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(router.into_make_service())
-        .await
-        .unwrap();
-    Ok(())
 }
 
-struct Claim {
-    user: User,
-}
-
-#[async_trait]
-impl FromRequestParts<ConnectionPool> for Claim {
-    type Rejection = Response;
-
-    async fn from_request_parts(
-        parts: &mut Parts,
-        pool: &ConnectionPool,
-    ) -> Result<Self, Self::Rejection> {
-        if let Ok(token) = AuthBearer::from_request_parts(parts, pool).await {
-            let conn = &mut get_connection(pool)?;
-            let mut result = users::table
-                .filter(users::dsl::bearer.eq(&token.0))
-                .load::<User>(conn)
-                .expect("Error loading Users");
-            return if result.len() == 1 {
-                let claim = Claim {
-                    user: result.remove(0),
-                };
-                Ok(claim)
-            } else {
-                Err((StatusCode::FORBIDDEN, "Authentication failed.").into_response())
-            };
-        }
-        Err((StatusCode::FORBIDDEN, "Not authenticated.").into_response())
-    }
+fn router(pool: ConnectionPool, oidc: Arc<login::Oidc>) -> Router {
+    api()
+        .into_router()
+        .layer(Extension(oidc))
+        .layer(middleware::map_response(error::json_rejections))
+        .with_state(pool)
 }
 
-async fn root(claim: Claim) -> impl IntoResponse {
+async fn root(claim: Claim<CanRead>) -> impl IntoResponse {
     format!("Hello {}!", claim.user.name)
 }
 
 async fn create_book(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     State(pool): State<ConnectionPool>,
     Json(user_book): Json<finance_lib::Book>,
 ) -> Result<Response, Response> {
@@ -148,80 +496,321 @@ async fn create_book(
             user_name: &claim.user.name,
         },
     );
-    let result = diesel::insert_into(books::table)
-        .values(&book)
-        .execute(conn);
-    if let Err(e) = result {
-        if let diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) = e {
-            return Err((
-                StatusCode::CONFLICT,
-                format!("Book '{}' already exists.", book.name),
+    let membership = BookMember {
+        book_name: book.name.clone(),
+        owner_name: claim.user.name.clone(),
+        member_name: claim.user.name.clone(),
+        role: BookRole::Owner.to_string(),
+    };
+    let result = conn.transaction(|conn| {
+        diesel::insert_into(books::table)
+            .values(&book)
+            .execute(conn)?;
+        diesel::insert_into(book_members::table)
+            .values(&membership)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book.name,
+            AuditEntity::Book,
+            &book.name,
+            &book.to_user_struct(),
+        )
+    });
+    match result {
+        Ok(()) => Ok(format!("Book '{}' created.", book.name).into_response()),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(trash::name_taken(
+                conn,
+                &claim.user.name,
+                &book.name,
+                AuditEntity::Book,
+                &book.name,
+            ))
+        }
+        // The user was deleted while the request ran.
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            Err(
+                Error::NotFound(format!("User '{}' does not exist.", claim.user.name))
+                    .into_response(),
             )
-                .into_response());
         }
-        Err((StatusCode::INTERNAL_SERVER_ERROR, "Error").into_response())
-    } else {
-        Ok(format!("Book '{}' created.", book.name).into_response())
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn update_book(
-    claim: Claim,
+    claim: Claim<IsBookOwner>,
     State(pool): State<ConnectionPool>,
     Path(book_name): Path<String>,
+    if_match: IfMatch,
     Json(user_book): Json<finance_lib::Book>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let book = Book::from_user_struct(
+    let mut book = Book::from_user_struct(
         &user_book,
         AddedInformationForBook {
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
         },
     );
-    let result = diesel::update(books::table)
-        .set(book)
-        .filter(
+    let result = conn.transaction(|conn| {
+        let query = books::table.filter(
             books::dsl::name
-                .eq(book_name)
-                .and(books::dsl::user_name.eq(claim.user.name)),
-        )
-        .execute(conn);
+                .eq(&book_name)
+                .and(books::dsl::user_name.eq(claim.owner())),
+        );
+        let before = db::for_update!(query).first::<Book>(conn)?;
+        if let Err(response) = if_match.check(&before.to_user_struct()) {
+            return Ok(Err(response));
+        }
+        book.immutable = user_book.immutable.unwrap_or(before.immutable);
+        book.lock_date = before.lock_date;
+        if before.immutable && !book.immutable {
+            return Ok(Err(Error::InvalidField(
+                "immutable",
+                "Immutable books cannot be made mutable again.".to_owned(),
+            )
+            .into_response()));
+        }
+        let updated = if book.name == book_name {
+            diesel::update(books::table)
+                .set(&book)
+                .filter(
+                    books::dsl::name
+                        .eq(&book_name)
+                        .and(books::dsl::user_name.eq(claim.owner())),
+                )
+                .execute(conn)?
+        } else {
+            move_book(conn, &book, &book_name, before.id)?
+        };
+        audit::updated(
+            conn,
+            &claim,
+            &book.name,
+            AuditEntity::Book,
+            &book_name,
+            &before.to_user_struct(),
+            &book.to_user_struct(),
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(updated))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(1)) => Ok(etag::updated(&book.to_user_struct())),
+        Ok(Err(response)) => Err(response),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(trash::name_taken(
+                conn,
+                claim.owner(),
+                &book.name,
+                AuditEntity::Book,
+                &book.name,
+            ))
+        }
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+/// Renames a book by moving its rows to a copy of it under the new name, which takes over the id
+/// of the book. Renaming the book itself would cascade to the postings along two paths, directly
+/// and through their accounts and currencies, which MySQL refuses.
+fn move_book(conn: &mut DbConnection, book: &Book, from: &str, id: i64) -> QueryResult<usize> {
+    let owner = &book.user_name;
+    let to = book.name.as_str().into_sql::<diesel::sql_types::Text>();
+    diesel::insert_into(books::table)
+        .values(book)
+        .execute(conn)?;
+    diesel::insert_into(accounts::table)
+        .values(
+            accounts::table
+                .select((
+                    accounts::dsl::name,
+                    accounts::dsl::description,
+                    accounts::dsl::user_name,
+                    to,
+                    accounts::dsl::deleted_at,
+                ))
+                .filter(
+                    accounts::dsl::book_name
+                        .eq(from)
+                        .and(accounts::dsl::user_name.eq(owner)),
+                ),
+        )
+        .into_columns((
+            accounts::dsl::name,
+            accounts::dsl::description,
+            accounts::dsl::user_name,
+            accounts::dsl::book_name,
+            accounts::dsl::deleted_at,
+        ))
+        .execute(conn)?;
+    diesel::insert_into(currencies::table)
+        .values(
+            currencies::table
+                .select((
+                    currencies::dsl::symbol,
+                    currencies::dsl::decimal_points,
+                    currencies::dsl::description,
+                    currencies::dsl::user_name,
+                    to,
+                    currencies::dsl::deleted_at,
+                ))
+                .filter(
+                    currencies::dsl::book_name
+                        .eq(from)
+                        .and(currencies::dsl::user_name.eq(owner)),
+                ),
+        )
+        .into_columns((
+            currencies::dsl::symbol,
+            currencies::dsl::decimal_points,
+            currencies::dsl::description,
+            currencies::dsl::user_name,
+            currencies::dsl::book_name,
+            currencies::dsl::deleted_at,
+        ))
+        .execute(conn)?;
+    // SQLite has moved the postings along with their transactions already.
+    diesel::update(transactions::table)
+        .set(transactions::dsl::book_name.eq(&book.name))
+        .filter(
+            transactions::dsl::book_name
+                .eq(from)
+                .and(transactions::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::update(postings::table)
+        .set(postings::dsl::book_name.eq(&book.name))
+        .filter(
+            postings::dsl::book_name
+                .eq(from)
+                .and(postings::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::update(book_members::table)
+        .set(book_members::dsl::book_name.eq(&book.name))
+        .filter(
+            book_members::dsl::book_name
+                .eq(from)
+                .and(book_members::dsl::owner_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::delete(accounts::table)
+        .filter(
+            accounts::dsl::book_name
+                .eq(from)
+                .and(accounts::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::delete(currencies::table)
+        .filter(
+            currencies::dsl::book_name
+                .eq(from)
+                .and(currencies::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::delete(books::table)
+        .filter(
+            books::dsl::name
+                .eq(from)
+                .and(books::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)?;
+    diesel::update(books::table)
+        .set(books::dsl::id.eq(id))
+        .filter(
+            books::dsl::name
+                .eq(&book.name)
+                .and(books::dsl::user_name.eq(owner)),
+        )
+        .execute(conn)
+}
+
+/// Changes only the given fields. Without `If-Match` the book is expected unchanged since it was
+/// read here, so concurrent changes are never overwritten.
+async fn patch_book(
+    claim: Claim<IsBookOwner>,
+    State(pool): State<ConnectionPool>,
+    Path(book_name): Path<String>,
+    if_match: IfMatch,
+    Json(patch): Json<BookPatch>,
+) -> Result<Response, Response> {
+    let mut user_book = found(
+        books::table
+            .filter(
+                books::dsl::name
+                    .eq(&book_name)
+                    .and(books::dsl::user_name.eq(claim.owner())),
+            )
+            .first::<Book>(&mut get_connection(&pool)?),
+        format!("Book '{}' does not exist.", book_name),
+    )?
+    .to_user_struct();
+    let if_match = if_match.or(etag::of(&user_book));
+    patch.apply(&mut user_book);
+    update_book(
+        claim,
+        State(pool),
+        Path(book_name),
+        if_match,
+        Json(user_book),
+    )
+    .await
+}
+
 async fn delete_book(
-    claim: Claim,
+    claim: Claim<IsBookOwner>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let result = diesel::delete(books::table)
-        .filter(
-            books::dsl::name
-                .eq(&book_name)
-                .and(books::dsl::user_name.eq(&claim.user.name)),
-        )
-        .execute(conn);
-    if let Ok(amount) = result {
-        if amount > 0 {
-            Ok(format!("Book {} deleted", &book_name).into_response())
-        } else {
-            Err((
-                StatusCode::NOT_FOUND,
-                format!("Book {} does not exist.", &book_name),
+    let result = conn.transaction(|conn| {
+        let before = books::table
+            .filter(
+                books::dsl::name
+                    .eq(&book_name)
+                    .and(books::dsl::user_name.eq(claim.owner()))
+                    .and(books::dsl::deleted_at.is_null()),
             )
-                .into_response())
+            .first::<Book>(conn)
+            .optional()?;
+        // Everything in the book stays in place until the book is purged from the trash.
+        let deleted = diesel::update(books::table)
+            .set(books::dsl::deleted_at.eq(Utc::now().naive_utc()))
+            .filter(
+                books::dsl::name
+                    .eq(&book_name)
+                    .and(books::dsl::user_name.eq(claim.owner()))
+                    .and(books::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Book,
+                &book_name,
+                &before.to_user_struct(),
+            )?;
         }
-    } else {
-        Err((StatusCode::INTERNAL_SERVER_ERROR, "Error").into_response())
+        Ok::<_, diesel::result::Error>(deleted)
+    });
+    match result {
+        Ok(amount) if amount > 0 => {
+            Ok(format!("Book '{}' moved to the trash.", &book_name).into_response())
+        }
+        Ok(_) => {
+            Err(Error::NotFound(format!("Book '{}' does not exist.", &book_name)).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_book(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -230,34 +819,51 @@ async fn get_book(
         .filter(
             books::dsl::name
                 .eq(&book_name)
-                .and(books::dsl::user_name.eq(&claim.user.name)),
+                .and(books::dsl::user_name.eq(claim.owner())),
         )
         .load::<Book>(conn);
-    if let Ok(queried) = result {
-        if queried.len() == 1 {
-            Ok(Json(queried[0].to_user_struct()).into_response())
-        } else {
-            Err((StatusCode::NOT_FOUND).into_response())
+    match result {
+        Ok(queried) if queried.len() == 1 => Ok(etag::tagged(queried[0].to_user_struct())),
+        Ok(_) => {
+            Err(Error::NotFound(format!("Book '{}' does not exist.", book_name)).into_response())
         }
-    } else {
-        Err((StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
-async fn get_books(claim: Claim, State(pool): State<ConnectionPool>) -> Result<Response, Response> {
+async fn get_books(
+    claim: Claim<CanRead>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let result = books::table
-        .select(books::dsl::name)
-        .filter(books::dsl::user_name.eq(claim.user.name))
-        .load::<String>(conn);
+    let result = book_members::table
+        .inner_join(
+            books::table.on(books::dsl::name
+                .eq(book_members::dsl::book_name)
+                .and(books::dsl::user_name.eq(book_members::dsl::owner_name))),
+        )
+        .select((book_members::dsl::book_name, books::dsl::id))
+        .filter(
+            book_members::dsl::member_name
+                .eq(&claim.user.name)
+                .and(books::dsl::deleted_at.is_null()),
+        )
+        .load::<(String, i64)>(conn);
     match result {
-        Ok(list) => Ok(Json(list).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(list) => {
+            let list: Vec<_> = list
+                .into_iter()
+                .filter(|(_, book_id)| claim.allows_book(*book_id))
+                .map(|(book_name, _)| book_name)
+                .collect();
+            Ok(Json(list).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn create_currency(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     State(pool): State<ConnectionPool>,
     Path(book_name): Path<String>,
     Json(user_currency): Json<finance_lib::Currency>,
@@ -265,63 +871,193 @@ async fn create_currency(
     let server_currency = Currency::from_user_struct(
         &user_currency,
         UserAndBookInfo {
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
             book_name: &book_name,
         },
     );
     let conn = &mut get_connection(&pool)?;
-    let result = diesel::insert_into(currencies::table)
-        .values(server_currency)
-        .execute(conn);
+    let result = conn.transaction(|conn| {
+        diesel::insert_into(currencies::table)
+            .values(&server_currency)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Currency,
+            &user_currency.symbol,
+            &server_currency.to_user_struct(),
+        )
+    });
     if let Err(e) = result {
-        if let diesel::result::Error::DatabaseError(database_error_kind, _) = e {
+        if let diesel::result::Error::DatabaseError(database_error_kind, _) = &e {
             match database_error_kind {
                 DatabaseErrorKind::ForeignKeyViolation => {
-                    return Err((StatusCode::BAD_REQUEST, "Book does not exist.").into_response())
+                    return Err(
+                        Error::NotFound(format!("Book '{}' does not exist.", book_name))
+                            .into_response(),
+                    )
                 }
                 DatabaseErrorKind::UniqueViolation => {
-                    return Err((StatusCode::CONFLICT, "Currency alread exists.").into_response())
+                    return Err(trash::name_taken(
+                        conn,
+                        claim.owner(),
+                        &book_name,
+                        AuditEntity::Currency,
+                        &user_currency.symbol,
+                    ))
                 }
                 _ => {}
             }
         }
-        Err((StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        Err(Error::from(e).into_response())
     } else {
         Ok(().into_response())
     }
 }
 
+/// Updates a currency. Renaming it moves all of its postings along.
 async fn update_currency(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     State(pool): State<ConnectionPool>,
     Path((book_name, symbol_name)): Path<(String, String)>,
+    if_match: IfMatch,
     Json(user_currency): Json<finance_lib::Currency>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
     let currency = Currency::from_user_struct(
         &user_currency,
         UserAndBookInfo {
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
             book_name: &book_name,
         },
     );
-    let result = diesel::update(currencies::table)
-        .set(currency)
-        .filter(
+    let result = conn.transaction(|conn| {
+        let query = currencies::table.filter(
             currencies::dsl::book_name
-                .eq(book_name)
-                .and(currencies::dsl::user_name.eq(claim.user.name))
-                .and(currencies::dsl::symbol.eq(symbol_name)),
-        )
-        .execute(conn);
+                .eq(&book_name)
+                .and(currencies::dsl::user_name.eq(claim.owner()))
+                .and(currencies::dsl::symbol.eq(&symbol_name))
+                .and(currencies::dsl::deleted_at.is_null()),
+        );
+        let before = db::for_update!(query).first::<Currency>(conn)?;
+        if let Err(response) = if_match.check(&before.to_user_struct()) {
+            return Ok(Err(response));
+        }
+        let postings = integrity::referencing_postings(
+            conn,
+            claim.owner(),
+            &book_name,
+            Reference::Currency,
+            &symbol_name,
+        )?;
+        let updated = diesel::update(currencies::table)
+            .set(&currency)
+            .filter(
+                currencies::dsl::book_name
+                    .eq(&book_name)
+                    .and(currencies::dsl::user_name.eq(claim.owner()))
+                    .and(currencies::dsl::symbol.eq(&symbol_name))
+                    .and(currencies::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        if user_currency.symbol != symbol_name {
+            integrity::rewrite(
+                conn,
+                &claim,
+                &book_name,
+                Reference::Currency,
+                postings,
+                &user_currency.symbol,
+            )?;
+        }
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Currency,
+            &symbol_name,
+            &before.to_user_struct(),
+            &currency.to_user_struct(),
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(updated))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(1)) => Ok(etag::updated(&currency.to_user_struct())),
+        Ok(Err(response)) => Err(response),
+        Err(diesel::result::Error::NotFound) => Err(Error::NotFound(format!(
+            "Currency '{}' does not exist.",
+            symbol_name
+        ))
+        .into_response()),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(trash::name_taken(
+                conn,
+                claim.owner(),
+                &book_name,
+                AuditEntity::Currency,
+                &user_currency.symbol,
+            ))
+        }
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+/// The route from before the symbol of the currency was part of the path. It takes the symbol
+/// from the body, so it cannot rename a currency.
+async fn update_currency_by_body(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+    Path(book_name): Path<String>,
+    if_match: IfMatch,
+    Json(user_currency): Json<finance_lib::Currency>,
+) -> Result<Response, Response> {
+    let symbol_name = user_currency.symbol.clone();
+    update_currency(
+        claim,
+        State(pool),
+        Path((book_name, symbol_name)),
+        if_match,
+        Json(user_currency),
+    )
+    .await
+}
+
+async fn patch_currency(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+    Path((book_name, symbol_name)): Path<(String, String)>,
+    if_match: IfMatch,
+    Json(patch): Json<CurrencyPatch>,
+) -> Result<Response, Response> {
+    let mut user_currency = found(
+        currencies::table
+            .filter(
+                currencies::dsl::book_name
+                    .eq(&book_name)
+                    .and(currencies::dsl::user_name.eq(claim.owner()))
+                    .and(currencies::dsl::symbol.eq(&symbol_name))
+                    .and(currencies::dsl::deleted_at.is_null()),
+            )
+            .first::<Currency>(&mut get_connection(&pool)?),
+        format!("Currency '{}' does not exist.", symbol_name),
+    )?
+    .to_user_struct();
+    let if_match = if_match.or(etag::of(&user_currency));
+    patch.apply(&mut user_currency);
+    update_currency(
+        claim,
+        State(pool),
+        Path((book_name, symbol_name)),
+        if_match,
+        Json(user_currency),
+    )
+    .await
+}
+
 async fn get_currency(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, currency_symbol)): Path<(String, String)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -331,23 +1067,22 @@ async fn get_currency(
             currencies::dsl::symbol
                 .eq(&currency_symbol)
                 .and(currencies::dsl::book_name.eq(&book_name))
-                .and(currencies::dsl::user_name.eq(&claim.user.name)),
+                .and(currencies::dsl::user_name.eq(claim.owner()))
+                .and(currencies::dsl::deleted_at.is_null()),
         )
         .load::<Currency>(conn);
-    if let Ok(list) = result {
-        if list.len() == 1 {
-            let currency = &list[0];
-            Ok(Json(currency.to_user_struct()).into_response())
-        } else {
-            Err((StatusCode::NOT_FOUND).into_response())
-        }
-    } else {
-        Err((StatusCode::INTERNAL_SERVER_ERROR).into_response())
+    match result {
+        Ok(list) if list.len() == 1 => Ok(etag::tagged(list[0].to_user_struct())),
+        Ok(_) => Err(
+            Error::NotFound(format!("Currency '{}' does not exist.", currency_symbol))
+                .into_response(),
+        ),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_currencies(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -356,43 +1091,102 @@ async fn get_currencies(
         .select(currencies::dsl::symbol)
         .filter(
             currencies::dsl::user_name
-                .eq(claim.user.name)
-                .and(currencies::dsl::book_name.eq(book_name)),
+                .eq(claim.owner())
+                .and(currencies::dsl::book_name.eq(book_name))
+                .and(currencies::dsl::deleted_at.is_null()),
         )
         .load::<String>(conn);
     match result {
         Ok(list) => Ok(Json(list).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn delete_currency(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     Path((book_name, currency_symbol)): Path<(String, String)>,
+    Query(options): Query<DeleteOptions>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let result = diesel::delete(currencies::table)
-        .filter(
-            currencies::dsl::user_name
-                .eq(&claim.user.name)
-                .and(currencies::dsl::book_name.eq(&book_name))
-                .and(currencies::dsl::symbol.eq(&currency_symbol)),
-        )
-        .execute(conn);
-    if let Ok(deleted_amount) = result {
-        if deleted_amount >= 1 {
-            Ok(().into_response())
-        } else {
-            Err((StatusCode::NOT_FOUND).into_response())
+    let result = conn.transaction(|conn| {
+        let before = currencies::table
+            .filter(
+                currencies::dsl::user_name
+                    .eq(claim.owner())
+                    .and(currencies::dsl::book_name.eq(&book_name))
+                    .and(currencies::dsl::symbol.eq(&currency_symbol))
+                    .and(currencies::dsl::deleted_at.is_null()),
+            )
+            .first::<Currency>(conn)
+            .optional()?;
+        if before.is_some() {
+            if let Err(response) = integrity::release(
+                conn,
+                &claim,
+                &book_name,
+                Reference::Currency,
+                &currency_symbol,
+                options.reassign_to.as_ref(),
+            )? {
+                return Ok(Err(response));
+            }
         }
-    } else {
-        Err((StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        let deleted = diesel::update(currencies::table)
+            .set(currencies::dsl::deleted_at.eq(Utc::now().naive_utc()))
+            .filter(
+                currencies::dsl::user_name
+                    .eq(claim.owner())
+                    .and(currencies::dsl::book_name.eq(&book_name))
+                    .and(currencies::dsl::symbol.eq(&currency_symbol))
+                    .and(currencies::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Currency,
+                &currency_symbol,
+                &before.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(deleted))
+    });
+    match result {
+        Ok(Ok(deleted_amount)) if deleted_amount >= 1 => Ok(().into_response()),
+        Ok(Ok(_)) => Err(Error::NotFound(format!(
+            "Currency '{}' does not exist.",
+            currency_symbol
+        ))
+        .into_response()),
+        Ok(Err(response)) => Err(response),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+/// Folds the currency into another one with the same decimal points and deletes it.
+async fn merge_currency(
+    claim: Claim<CanAdmin>,
+    Path((book_name, currency_symbol)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+    Json(merge): Json<Merge>,
+) -> Result<Response, Response> {
+    let options = DeleteOptions {
+        reassign_to: Some(merge.into),
+    };
+    delete_currency(
+        claim,
+        Path((book_name, currency_symbol)),
+        Query(options),
+        State(pool),
+    )
+    .await
+}
+
 async fn create_account(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
     Json(user_account): Json<finance_lib::Account>,
@@ -402,43 +1196,241 @@ async fn create_account(
         &user_account,
         UserAndBookInfo {
             book_name: &book_name,
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
         },
     );
-    let result = diesel::insert_into(accounts::table)
-        .values(&account)
-        .execute(conn);
+    let result = conn.transaction(|conn| {
+        let inserted = diesel::insert_into(accounts::table)
+            .values(&account)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Account,
+            &account.name,
+            &account.to_user_struct(),
+        )?;
+        Ok(inserted)
+    });
     match result {
         Ok(1) => Ok(().into_response()),
         Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
-            Err((StatusCode::CONFLICT).into_response())
+            Err(trash::name_taken(
+                conn,
+                claim.owner(),
+                &book_name,
+                AuditEntity::Account,
+                &account.name,
+            ))
         }
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(_) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
+
 async fn delete_account(
-    claim: Claim,
+    claim: Claim<CanAdmin>,
     Path((book_name, account_name)): Path<(String, String)>,
+    Query(options): Query<DeleteOptions>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let result = diesel::delete(accounts::table)
-        .filter(
+    let result = conn.transaction(|conn| {
+        let before = accounts::table
+            .filter(
+                accounts::dsl::user_name
+                    .eq(claim.owner())
+                    .and(accounts::dsl::name.eq(&account_name))
+                    .and(accounts::dsl::book_name.eq(&book_name))
+                    .and(accounts::dsl::deleted_at.is_null()),
+            )
+            .first::<Account>(conn)
+            .optional()?;
+        if before.is_some() {
+            if let Err(response) = integrity::release(
+                conn,
+                &claim,
+                &book_name,
+                Reference::Account,
+                &account_name,
+                options.reassign_to.as_ref(),
+            )? {
+                return Ok(Err(response));
+            }
+        }
+        let deleted = diesel::update(accounts::table)
+            .set(accounts::dsl::deleted_at.eq(Utc::now().naive_utc()))
+            .filter(
+                accounts::dsl::user_name
+                    .eq(claim.owner())
+                    .and(accounts::dsl::name.eq(&account_name))
+                    .and(accounts::dsl::book_name.eq(&book_name))
+                    .and(accounts::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Account,
+                &account_name,
+                &before.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(deleted))
+    });
+    match result {
+        Ok(Ok(1)) => Ok(().into_response()),
+        Ok(Ok(_)) => Err(
+            Error::NotFound(format!("Account '{}' does not exist.", account_name)).into_response(),
+        ),
+        Ok(Err(response)) => Err(response),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Updates the description of an account or renames it, which moves all of its postings along.
+async fn update_account(
+    claim: Claim<CanAdmin>,
+    Path((book_name, account_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
+    Json(user_account): Json<finance_lib::Account>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let query = accounts::table.filter(
             accounts::dsl::user_name
-                .eq(claim.user.name)
-                .and(accounts::dsl::name.eq(account_name))
-                .and(accounts::dsl::book_name.eq(book_name)),
-        )
-        .execute(conn);
+                .eq(claim.owner())
+                .and(accounts::dsl::name.eq(&account_name))
+                .and(accounts::dsl::book_name.eq(&book_name))
+                .and(accounts::dsl::deleted_at.is_null()),
+        );
+        let before = db::for_update!(query).first::<Account>(conn).optional()?;
+        let before = match before {
+            Some(before) => before,
+            None => {
+                return Ok(Err(Error::NotFound(format!(
+                    "Account '{}' does not exist.",
+                    account_name
+                ))
+                .into_response()))
+            }
+        };
+        if let Err(response) = if_match.check(&before.to_user_struct()) {
+            return Ok(Err(response));
+        }
+        let postings = integrity::referencing_postings(
+            conn,
+            claim.owner(),
+            &book_name,
+            Reference::Account,
+            &account_name,
+        )?;
+        diesel::update(accounts::table)
+            .set((
+                accounts::dsl::name.eq(&user_account.name),
+                accounts::dsl::description.eq(&user_account.description),
+            ))
+            .filter(
+                accounts::dsl::user_name
+                    .eq(claim.owner())
+                    .and(accounts::dsl::name.eq(&account_name))
+                    .and(accounts::dsl::book_name.eq(&book_name)),
+            )
+            .execute(conn)?;
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Account,
+            &account_name,
+            &before.to_user_struct(),
+            &user_account,
+        )?;
+        if user_account.name != account_name {
+            integrity::rewrite(
+                conn,
+                &claim,
+                &book_name,
+                Reference::Account,
+                postings,
+                &user_account.name,
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(()))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        Ok(_) => Err((StatusCode::NOT_FOUND).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(())) => Ok(etag::updated(&user_account)),
+        Ok(Err(response)) => Err(response),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(trash::name_taken(
+                conn,
+                claim.owner(),
+                &book_name,
+                AuditEntity::Account,
+                &user_account.name,
+            ))
+        }
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+async fn patch_account(
+    claim: Claim<CanAdmin>,
+    Path((book_name, account_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
+    Json(patch): Json<AccountPatch>,
+) -> Result<Response, Response> {
+    let mut user_account = found(
+        accounts::table
+            .filter(
+                accounts::dsl::user_name
+                    .eq(claim.owner())
+                    .and(accounts::dsl::name.eq(&account_name))
+                    .and(accounts::dsl::book_name.eq(&book_name))
+                    .and(accounts::dsl::deleted_at.is_null()),
+            )
+            .first::<Account>(&mut get_connection(&pool)?),
+        format!("Account '{}' does not exist.", account_name),
+    )?
+    .to_user_struct();
+    let if_match = if_match.or(etag::of(&user_account));
+    patch.apply(&mut user_account);
+    update_account(
+        claim,
+        Path((book_name, account_name)),
+        State(pool),
+        if_match,
+        Json(user_account),
+    )
+    .await
+}
+
+/// Folds the account into another one and deletes it.
+async fn merge_account(
+    claim: Claim<CanAdmin>,
+    Path((book_name, account_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+    Json(merge): Json<Merge>,
+) -> Result<Response, Response> {
+    let options = DeleteOptions {
+        reassign_to: Some(merge.into),
+    };
+    delete_account(
+        claim,
+        Path((book_name, account_name)),
+        Query(options),
+        State(pool),
+    )
+    .await
+}
+
 async fn get_account(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, account_name)): Path<(String, String)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -446,26 +1438,30 @@ async fn get_account(
     let result = accounts::table
         .filter(
             accounts::dsl::user_name
-                .eq(claim.user.name)
-                .and(accounts::dsl::name.eq(account_name))
-                .and(accounts::dsl::book_name.eq(book_name)),
+                .eq(claim.owner())
+                .and(accounts::dsl::name.eq(&account_name))
+                .and(accounts::dsl::book_name.eq(book_name))
+                .and(accounts::dsl::deleted_at.is_null()),
         )
         .load::<Account>(conn);
 
     match result {
         Ok(accounts) => {
             if !accounts.is_empty() {
-                Ok(Json(accounts[0].to_user_struct()).into_response())
+                Ok(etag::tagged(accounts[0].to_user_struct()))
             } else {
-                Err((StatusCode::NOT_FOUND).into_response())
+                Err(
+                    Error::NotFound(format!("Account '{}' does not exist.", account_name))
+                        .into_response(),
+                )
             }
         }
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_accounts(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -474,100 +1470,246 @@ async fn get_accounts(
         .select(accounts::dsl::name)
         .filter(
             accounts::dsl::user_name
-                .eq(claim.user.name)
-                .and(accounts::dsl::book_name.eq(book_name)),
+                .eq(claim.owner())
+                .and(accounts::dsl::book_name.eq(book_name))
+                .and(accounts::dsl::deleted_at.is_null()),
         )
         .load::<String>(conn);
     match result {
         Ok(list) => Ok(Json(list).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn create_transaction(
-    claim: Claim,
+    claim: Claim<CanWriteTransactions>,
     Path(book_name): Path<String>,
     State(pool): State<ConnectionPool>,
+    idempotency_key: IdempotencyKey,
     Json(user_transaction): Json<finance_lib::NewTransaction>,
 ) -> Result<Response, Response> {
     let mut conn = get_connection(&pool)?;
+    let request = ("transaction", &book_name, &user_transaction);
+    if let Some(transaction_id) = idempotency_key.replay(&mut conn, &claim.user.name, &request)? {
+        return Ok(Json(transaction_id).into_response());
+    }
     let transaction = match Transaction::from_new_user_struct(
         &user_transaction,
         UserAndBookInfo {
             book_name: &book_name,
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
         },
     ) {
         Ok(t) => t,
-        _ => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => {
+            tracing::error!("could not create the transaction: {}", e);
+            return Err(Error::Internal.into_response());
+        }
     };
 
-    let result = diesel::insert_into(transactions::table)
-        .values(&transaction)
-        .execute(&mut conn);
+    let result = conn.transaction(|conn| {
+        if let Err(refusal) = ledger::check_change(
+            conn,
+            claim.owner(),
+            &book_name,
+            transaction.id,
+            &[transaction.time()],
+        )? {
+            return Ok(Err(refusal));
+        }
+        let inserted = diesel::insert_into(transactions::table)
+            .values(&transaction)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Transaction,
+            &transaction.id.to_string(),
+            &transaction.to_user_struct(),
+        )?;
+        idempotency_key.record(conn, &claim.user.name, &request, transaction.id)?;
+        Ok(Ok(inserted))
+    });
 
     match result {
-        Ok(1) => Ok((Json::from(transaction.id)).into_response()),
-        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, e)) => {
-            Err((StatusCode::BAD_REQUEST, e.message().to_string()).into_response())
+        Ok(Ok(1)) => Ok((Json::from(transaction.id)).into_response()),
+        Ok(Err(refusal)) => Err(refusal.into_response()),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            Err(Error::NotFound(format!("Book '{}' does not exist.", book_name)).into_response())
+        }
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(idempotency::in_progress())
         }
-        Err(e) => Err((e.to_string()).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(error) => Err(Error::from(error).into_response()),
     }
 }
 
 async fn update_transaction(
-    claim: Claim,
+    claim: Claim<CanWriteTransactions>,
     Path((book_name, transaction_id)): Path<(String, i64)>,
     State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
     Json(user_transaction): Json<finance_lib::Transaction>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
-    let transaction = Transaction::from_user_struct(
+    let mut transaction = Transaction::from_user_struct(
         &user_transaction,
         UserAndBookInfo {
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
             book_name: &book_name,
         },
     );
-    let result = diesel::update(transactions::table)
-        .set(transaction)
-        .filter(
-            transactions::dsl::user_name
-                .eq(claim.user.name)
-                .and(transactions::dsl::book_name.eq(book_name))
-                .and(transactions::dsl::id.eq(transaction_id)),
-        )
-        .execute(conn);
+    let result = conn.transaction(|conn| {
+        if let Err(refusal) = ledger::check_change(
+            conn,
+            claim.owner(),
+            &book_name,
+            transaction_id,
+            &[transaction.time()],
+        )? {
+            return Ok(Err(refusal.into_response()));
+        }
+        let before = transactions::table
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .first::<Transaction>(conn)?;
+        if let Err(response) = if_match.check(&before.to_user_struct()) {
+            return Ok(Err(response));
+        }
+        transaction.posted = before.posted;
+        transaction.reverses = before.reverses;
+        let updated = diesel::update(transactions::table)
+            .set(&transaction)
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Transaction,
+            &transaction_id.to_string(),
+            &before.to_user_struct(),
+            &transaction.to_user_struct(),
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(updated))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(1)) => Ok(etag::updated(&transaction.to_user_struct())),
+        Ok(Err(response)) => Err(response),
+        Err(diesel::result::Error::NotFound) => Err(Error::NotFound(format!(
+            "Transaction {} does not exist.",
+            transaction_id
+        ))
+        .into_response()),
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+async fn patch_transaction(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, transaction_id)): Path<(String, i64)>,
+    State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
+    Json(patch): Json<TransactionPatch>,
+) -> Result<Response, Response> {
+    let mut user_transaction = found(
+        transactions::table
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .first::<Transaction>(&mut get_connection(&pool)?),
+        format!("Transaction {} does not exist.", transaction_id),
+    )?
+    .to_user_struct();
+    let if_match = if_match.or(etag::of(&user_transaction));
+    patch.apply(&mut user_transaction);
+    update_transaction(
+        claim,
+        Path((book_name, transaction_id)),
+        State(pool),
+        if_match,
+        Json(user_transaction),
+    )
+    .await
+}
+
 async fn delete_transaction(
-    claim: Claim,
+    claim: Claim<CanWriteTransactions>,
     Path((book_name, transaction_id)): Path<(String, i64)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let mut conn = get_connection(&pool)?;
-    let result = diesel::delete(transactions::table)
-        .filter(
-            transactions::dsl::user_name
-                .eq(claim.user.name)
-                .and(transactions::dsl::book_name.eq(book_name))
-                .and(transactions::dsl::id.eq(transaction_id)),
-        )
-        .execute(&mut conn);
+    let result = conn.transaction(|conn| {
+        if let Err(refusal) =
+            ledger::check_change(conn, claim.owner(), &book_name, transaction_id, &[])?
+        {
+            return Ok(Err(refusal));
+        }
+        let before = transactions::table
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .first::<Transaction>(conn)
+            .optional()?;
+        // The postings stay with the transaction in the trash.
+        let deleted = diesel::update(transactions::table)
+            .set(transactions::dsl::deleted_at.eq(Utc::now().naive_utc()))
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::id.eq(transaction_id))
+                    .and(transactions::dsl::deleted_at.is_null()),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Transaction,
+                &transaction_id.to_string(),
+                &before.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(deleted))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        Ok(_) => Err((StatusCode::NOT_FOUND).into_response()),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(1)) => Ok(().into_response()),
+        Ok(Ok(_)) => Err(Error::NotFound(format!(
+            "Transaction {} does not exist.",
+            transaction_id
+        ))
+        .into_response()),
+        Ok(Err(refusal)) => Err(refusal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_transaction(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, transaction_id)): Path<(String, i64)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -575,26 +1717,32 @@ async fn get_transaction(
     let result = transactions::table
         .filter(
             transactions::dsl::user_name
-                .eq(claim.user.name)
+                .eq(claim.owner())
                 .and(transactions::dsl::book_name.eq(book_name))
-                .and(transactions::dsl::id.eq(transaction_id)),
+                .and(transactions::dsl::id.eq(transaction_id))
+                .and(transactions::dsl::deleted_at.is_null()),
         )
         .load::<Transaction>(&mut conn);
     match result {
         Ok(accounts) => {
             if !accounts.is_empty() {
-                Ok(Json(accounts[0].to_user_struct()).into_response())
+                Ok(etag::tagged(accounts[0].to_user_struct()))
             } else {
-                Err((StatusCode::NOT_FOUND).into_response())
+                Err(
+                    Error::NotFound(format!("Transaction {} does not exist.", transaction_id))
+                        .into_response(),
+                )
             }
         }
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+/// Ids of the transactions in the order they happened.
 async fn get_transactions(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path(book_name): Path<String>,
+    Query(page): Query<Page>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let conn = &mut get_connection(&pool)?;
@@ -602,72 +1750,293 @@ async fn get_transactions(
         .select(transactions::dsl::id)
         .filter(
             transactions::dsl::user_name
-                .eq(claim.user.name)
-                .and(transactions::dsl::book_name.eq(book_name)),
+                .eq(claim.owner())
+                .and(transactions::dsl::book_name.eq(book_name))
+                .and(transactions::dsl::deleted_at.is_null()),
         )
+        .order((transactions::dsl::time.asc(), transactions::dsl::id.asc()))
+        .offset(page.offset.unwrap_or(0))
+        .limit(page.limit.unwrap_or(i64::MAX))
         .load::<i64>(conn);
     match result {
         Ok(list) => Ok(Json(list).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn create_posting(
-    claim: Claim,
+    claim: Claim<CanWriteTransactions>,
     Path((book_name, transaction_id)): Path<(String, i64)>,
     State(pool): State<ConnectionPool>,
+    idempotency_key: IdempotencyKey,
     Json(user_posting): Json<finance_lib::NewPosting>,
 ) -> Result<Response, Response> {
     let mut conn = get_connection(&pool)?;
+    let request = ("posting", &book_name, transaction_id, &user_posting);
+    if let Some(posting_id) = idempotency_key.replay(&mut conn, &claim.user.name, &request)? {
+        return Ok(Json(posting_id).into_response());
+    }
     let posting = match Posting::from_new_user_struct(
         &user_posting,
         AddedInformationForPosting {
-            user_name: &claim.user.name,
+            user_name: claim.owner(),
             transaction_id: &transaction_id,
             book_name: &book_name,
         },
     ) {
         Ok(p) => p,
-        _ => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => {
+            tracing::error!("could not create the posting: {}", e);
+            return Err(Error::Internal.into_response());
+        }
     };
-    let result = diesel::insert_into(postings::table)
-        .values(&posting)
-        .execute(&mut conn);
+    let result = conn.transaction(|conn| {
+        let transaction = trash::live_transactions(claim.owner(), &book_name)
+            .filter(transactions::dsl::id.eq(transaction_id))
+            .first::<i64>(conn)
+            .optional()?;
+        if transaction.is_none() {
+            return Ok(Ok(0));
+        }
+        if let Err(refusal) = ledger::check_change(
+            conn,
+            claim.owner(),
+            &book_name,
+            transaction_id,
+            posting.valuta.as_slice(),
+        )? {
+            return Ok(Err(refusal.into_response()));
+        }
+        if let Err(response) = integrity::check_posting(conn, claim.owner(), &book_name, &posting)?
+        {
+            return Ok(Err(response));
+        }
+        let inserted = diesel::insert_into(postings::table)
+            .values(&posting)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Posting,
+            &posting.id.to_string(),
+            &posting.to_user_struct(),
+        )?;
+        idempotency_key.record(conn, &claim.user.name, &request, posting.id)?;
+        Ok(Ok(inserted))
+    });
     match result {
-        Ok(1) => Ok((Json::from(posting.id)).into_response()),
+        Ok(Ok(1)) => Ok((Json::from(posting.id)).into_response()),
+        Ok(Ok(0)) => Err(Error::NotFound(format!(
+            "Transaction {} does not exist.",
+            transaction_id
+        ))
+        .into_response()),
+        Ok(Err(response)) => Err(response),
         Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
-            Err((StatusCode::BAD_REQUEST).into_response())
+            Err(Error::Invalid("The posting refers to an unknown book.".to_owned()).into_response())
         }
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(idempotency::in_progress())
+        }
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
 
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+async fn update_posting(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, transaction_id, posting_id)): Path<(String, i64, i64)>,
+    State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
+    Json(user_posting): Json<finance_lib::Posting>,
+) -> Result<Response, Response> {
+    let mut conn = get_connection(&pool)?;
+    let mut posting = Posting::from_user_struct(
+        &user_posting,
+        AddedInformationForPosting {
+            user_name: claim.owner(),
+            transaction_id: &transaction_id,
+            book_name: &book_name,
+        },
+    );
+    posting.id = posting_id;
+    let result = conn.transaction(|conn| {
+        let query = postings::table.filter(
+            postings::dsl::user_name
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
+                .and(postings::dsl::transaction_id.eq(transaction_id))
+                .and(postings::dsl::id.eq(posting_id))
+                .and(
+                    postings::dsl::transaction_id
+                        .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                ),
+        );
+        let before = db::for_update!(query).first::<Posting>(conn).optional()?;
+        let before = match before {
+            Some(before) => before,
+            None => {
+                return Ok(Err(Error::NotFound(format!(
+                    "Posting {} does not exist.",
+                    posting_id
+                ))
+                .into_response()))
+            }
+        };
+        if let Err(response) = if_match.check(&before.to_user_struct()) {
+            return Ok(Err(response));
+        }
+        // Moving a posting out of or into a closed period are both changes of that period.
+        let dates: Vec<_> = before.valuta.into_iter().chain(posting.valuta).collect();
+        if let Err(refusal) =
+            ledger::check_change(conn, claim.owner(), &book_name, transaction_id, &dates)?
+        {
+            return Ok(Err(refusal.into_response()));
+        }
+        if let Err(response) = integrity::check_posting(conn, claim.owner(), &book_name, &posting)?
+        {
+            return Ok(Err(response));
+        }
+        diesel::update(postings::table)
+            .set((
+                postings::dsl::valuta.eq(&posting.valuta),
+                postings::dsl::account_name.eq(&posting.account_name),
+                postings::dsl::currency.eq(&posting.currency),
+                postings::dsl::amount.eq(posting.amount),
+                postings::dsl::budget.eq(posting.budget),
+            ))
+            .filter(
+                postings::dsl::user_name
+                    .eq(claim.owner())
+                    .and(postings::dsl::book_name.eq(&book_name))
+                    .and(postings::dsl::id.eq(posting_id)),
+            )
+            .execute(conn)?;
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Posting,
+            &posting_id.to_string(),
+            &before.to_user_struct(),
+            &posting.to_user_struct(),
+        )?;
+        Ok::<_, diesel::result::Error>(Ok(()))
+    });
+    match result {
+        Ok(Ok(())) => Ok(etag::updated(&posting.to_user_struct())),
+        Ok(Err(response)) => Err(response),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
+async fn patch_posting(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, transaction_id, posting_id)): Path<(String, i64, i64)>,
+    State(pool): State<ConnectionPool>,
+    if_match: IfMatch,
+    Json(patch): Json<PostingPatch>,
+) -> Result<Response, Response> {
+    let mut user_posting = found(
+        postings::table
+            .filter(
+                postings::dsl::user_name
+                    .eq(claim.owner())
+                    .and(postings::dsl::book_name.eq(&book_name))
+                    .and(postings::dsl::transaction_id.eq(transaction_id))
+                    .and(postings::dsl::id.eq(posting_id))
+                    .and(
+                        postings::dsl::transaction_id
+                            .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                    ),
+            )
+            .first::<Posting>(&mut get_connection(&pool)?),
+        format!("Posting {} does not exist.", posting_id),
+    )?
+    .to_user_struct();
+    let if_match = if_match.or(etag::of(&user_posting));
+    patch.apply(&mut user_posting);
+    update_posting(
+        claim,
+        Path((book_name, transaction_id, posting_id)),
+        State(pool),
+        if_match,
+        Json(user_posting),
+    )
+    .await
+}
+
 async fn delete_posting(
-    claim: Claim,
+    claim: Claim<CanWriteTransactions>,
     Path((book_name, transaction_id, posting_id)): Path<(String, i64, i64)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
     let mut conn = get_connection(&pool)?;
-    let result = diesel::delete(postings::table)
-        .filter(
-            postings::dsl::user_name.eq(claim.user.name).and(
-                postings::dsl::book_name
-                    .eq(book_name)
+    let result = conn.transaction(|conn| {
+        let before = postings::table
+            .filter(
+                postings::dsl::user_name
+                    .eq(claim.owner())
+                    .and(postings::dsl::book_name.eq(&book_name))
                     .and(postings::dsl::transaction_id.eq(transaction_id))
-                    .and(postings::dsl::id.eq(posting_id)),
-            ),
-        )
-        .execute(&mut conn);
+                    .and(postings::dsl::id.eq(posting_id))
+                    .and(
+                        postings::dsl::transaction_id
+                            .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                    ),
+            )
+            .first::<Posting>(conn)
+            .optional()?;
+        let valuta = before.as_ref().and_then(|posting| posting.valuta);
+        if let Err(refusal) = ledger::check_change(
+            conn,
+            claim.owner(),
+            &book_name,
+            transaction_id,
+            valuta.as_slice(),
+        )? {
+            return Ok(Err(refusal));
+        }
+        let deleted = diesel::delete(postings::table)
+            .filter(
+                postings::dsl::user_name.eq(claim.owner()).and(
+                    postings::dsl::book_name
+                        .eq(&book_name)
+                        .and(postings::dsl::transaction_id.eq(transaction_id))
+                        .and(postings::dsl::id.eq(posting_id))
+                        .and(
+                            postings::dsl::transaction_id
+                                .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                        ),
+                ),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                &claim,
+                &book_name,
+                AuditEntity::Posting,
+                &posting_id.to_string(),
+                &before.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(Ok(deleted))
+    });
     match result {
-        Ok(1) => Ok(().into_response()),
-        Ok(0) => Err((StatusCode::NOT_FOUND).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Ok(Ok(1)) => Ok(().into_response()),
+        Ok(Ok(0)) => {
+            Err(Error::NotFound(format!("Posting {} does not exist.", posting_id)).into_response())
+        }
+        Ok(Err(refusal)) => Err(refusal.into_response()),
+        Ok(Ok(_)) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_posting(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, transaction_id, posting_id)): Path<(String, i64, i64)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -675,26 +2044,33 @@ async fn get_posting(
     let result = postings::table
         .filter(
             postings::dsl::user_name
-                .eq(claim.user.name)
-                .and(postings::dsl::book_name.eq(book_name))
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
                 .and(postings::dsl::transaction_id.eq(transaction_id))
-                .and(postings::dsl::id.eq(posting_id)),
+                .and(postings::dsl::id.eq(posting_id))
+                .and(
+                    postings::dsl::transaction_id
+                        .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                ),
         )
         .load::<Posting>(&mut conn);
     match result {
         Ok(postings) => {
             if !postings.is_empty() {
-                Ok(Json(postings[0].to_user_struct()).into_response())
+                Ok(etag::tagged(postings[0].to_user_struct()))
             } else {
-                Err((StatusCode::NOT_FOUND).into_response())
+                Err(
+                    Error::NotFound(format!("Posting {} does not exist.", posting_id))
+                        .into_response(),
+                )
             }
         }
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn get_postings(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, transaction_id)): Path<(String, i64)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -703,19 +2079,23 @@ async fn get_postings(
         .select(postings::dsl::id)
         .filter(
             postings::dsl::user_name
-                .eq(claim.user.name)
-                .and(postings::dsl::book_name.eq(book_name))
-                .and(postings::dsl::transaction_id.eq(transaction_id)),
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
+                .and(postings::dsl::transaction_id.eq(transaction_id))
+                .and(
+                    postings::dsl::transaction_id
+                        .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                ),
         )
         .load::<i64>(conn);
     match result {
         Ok(list) => Ok(Json(list).into_response()),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 
 async fn real_account_value(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, account_name)): Path<(String, String)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -724,10 +2104,14 @@ async fn real_account_value(
         .group_by(postings::dsl::currency)
         .filter(
             postings::dsl::user_name
-                .eq(claim.user.name)
-                .and(postings::dsl::book_name.eq(book_name))
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
                 .and(postings::dsl::account_name.eq(account_name))
-                .and(postings::dsl::budget.eq(false)),
+                .and(postings::dsl::budget.eq(false))
+                .and(
+                    postings::dsl::transaction_id
+                        .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                ),
         )
         .select((postings::dsl::currency, sum(postings::dsl::amount)))
         .load::<CurrencyAmount>(conn);
@@ -736,11 +2120,11 @@ async fn real_account_value(
             let user_structs: Vec<_> = list.iter().map(|i| i.to_user_struct()).collect();
             Ok(Json(user_structs).into_response())
         }
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
     }
 }
 async fn account_value(
-    claim: Claim,
+    claim: Claim<CanRead>,
     Path((book_name, account_name)): Path<(String, String)>,
     State(pool): State<ConnectionPool>,
 ) -> Result<Response, Response> {
@@ -749,9 +2133,13 @@ async fn account_value(
         .group_by(postings::dsl::currency)
         .filter(
             postings::dsl::user_name
-                .eq(claim.user.name)
-                .and(postings::dsl::book_name.eq(book_name))
-                .and(postings::dsl::account_name.eq(account_name)),
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
+                .and(postings::dsl::account_name.eq(account_name))
+                .and(
+                    postings::dsl::transaction_id
+                        .eq_any(trash::live_transactions(claim.owner(), &book_name)),
+                ),
         )
         .select((postings::dsl::currency, sum(postings::dsl::amount)))
         .load::<CurrencyAmount>(conn);
@@ -760,6 +2148,61 @@ async fn account_value(
             let user_structs: Vec<_> = list.iter().map(|i| i.to_user_struct()).collect();
             Ok(Json(user_structs).into_response())
         }
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Postings to an account along with their transactions, in the order of the journal.
+async fn account_register(
+    claim: Claim<CanRead>,
+    Path((book_name, account_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let postings = postings::table
+        .filter(
+            postings::dsl::user_name
+                .eq(claim.owner())
+                .and(postings::dsl::book_name.eq(&book_name))
+                .and(postings::dsl::account_name.eq(&account_name)),
+        )
+        .order_by(postings::dsl::id)
+        .load::<Posting>(conn);
+    let postings = match postings {
+        Ok(postings) => postings,
+        Err(error) => return Err(Error::from(error).into_response()),
+    };
+    let transaction_ids: Vec<i64> = postings.iter().map(|p| p.transaction_id).collect();
+    let transactions = transactions::table
+        .filter(
+            transactions::dsl::user_name
+                .eq(claim.owner())
+                .and(transactions::dsl::book_name.eq(&book_name))
+                .and(transactions::dsl::deleted_at.is_null())
+                .and(transactions::dsl::id.eq_any(transaction_ids)),
+        )
+        .order_by((transactions::dsl::time, transactions::dsl::id))
+        .load::<Transaction>(conn);
+    let transactions = match transactions {
+        Ok(transactions) => transactions,
+        Err(error) => return Err(Error::from(error).into_response()),
+    };
+
+    let mut by_transaction: HashMap<i64, Vec<&Posting>> = HashMap::new();
+    for posting in &postings {
+        by_transaction
+            .entry(posting.transaction_id)
+            .or_default()
+            .push(posting);
+    }
+    let mut entries = vec![];
+    for transaction in &transactions {
+        for posting in by_transaction.remove(&transaction.id).unwrap_or_default() {
+            entries.push(finance_lib::RegisterEntry {
+                transaction: transaction.to_user_struct(),
+                posting: posting.to_user_struct(),
+            });
+        }
     }
+    Ok(Json(entries).into_response())
 }
@@ -0,0 +1,222 @@
+use crate::audit;
+use crate::auth::{CanRead, Claim, IsBookMember, IsBookOwner};
+use crate::error::Error;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use finance_lib::{AuditEntity, BookRole};
+
+pub async fn get_members(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = book_members::table
+        .filter(
+            book_members::dsl::book_name
+                .eq(book_name)
+                .and(book_members::dsl::owner_name.eq(claim.owner())),
+        )
+        .load::<BookMember>(conn);
+    match result {
+        Ok(list) => {
+            let user_structs: Vec<_> = list.iter().map(|m| m.to_user_struct()).collect();
+            Ok(Json(user_structs).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Invites a user into the book. Members reach it by its name, along with the name of its owner if
+/// they have access to other books of that name.
+pub async fn add_member(
+    claim: Claim<IsBookOwner>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+    Json(user_member): Json<finance_lib::BookMember>,
+) -> Result<Response, Response> {
+    if user_member.role == BookRole::Owner {
+        return Err(
+            Error::InvalidField("role", "A book has exactly one owner.".to_owned()).into_response(),
+        );
+    }
+    let conn = &mut get_connection(&pool)?;
+    let membership = BookMember {
+        book_name,
+        owner_name: claim.owner().clone(),
+        member_name: user_member.user_name.clone(),
+        role: user_member.role.to_string(),
+    };
+    let result = conn.transaction(|conn| {
+        let inserted = diesel::insert_into(book_members::table)
+            .values(&membership)
+            .execute(conn)?;
+        audit::created(
+            conn,
+            &claim,
+            &membership.book_name,
+            AuditEntity::Member,
+            &membership.member_name,
+            &membership.to_user_struct(),
+        )?;
+        Ok(inserted)
+    });
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            Err(
+                Error::NotFound(format!("User '{}' does not exist.", user_member.user_name))
+                    .into_response(),
+            )
+        }
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(Error::AlreadyExists(format!(
+                "User '{}' is already a member of the book.",
+                user_member.user_name
+            ))
+            .into_response())
+        }
+        Ok(_) => Err(Error::Internal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn update_member(
+    claim: Claim<IsBookOwner>,
+    Path((book_name, user_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+    Json(user_member): Json<finance_lib::BookMember>,
+) -> Result<Response, Response> {
+    if user_member.role == BookRole::Owner || &user_name == claim.owner() {
+        return Err(
+            Error::InvalidField("role", "A book has exactly one owner.".to_owned()).into_response(),
+        );
+    }
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let before = book_members::table
+            .filter(
+                book_members::dsl::book_name
+                    .eq(&book_name)
+                    .and(book_members::dsl::owner_name.eq(claim.owner()))
+                    .and(book_members::dsl::member_name.eq(&user_name)),
+            )
+            .first::<BookMember>(conn)
+            .optional()?;
+        let before = match before {
+            Some(before) => before,
+            None => return Ok(None),
+        };
+        diesel::update(book_members::table)
+            .set(book_members::dsl::role.eq(user_member.role.to_string()))
+            .filter(
+                book_members::dsl::book_name
+                    .eq(&book_name)
+                    .and(book_members::dsl::owner_name.eq(claim.owner()))
+                    .and(book_members::dsl::member_name.eq(&user_name)),
+            )
+            .execute(conn)?;
+        let after = finance_lib::BookMember {
+            user_name: user_name.clone(),
+            role: user_member.role,
+        };
+        audit::updated(
+            conn,
+            &claim,
+            &book_name,
+            AuditEntity::Member,
+            &user_name,
+            &before.to_user_struct(),
+            &after,
+        )?;
+        Ok::<_, diesel::result::Error>(Some(()))
+    });
+    match result {
+        Ok(Some(())) => Ok(().into_response()),
+        Ok(None) => Err(Error::NotFound(format!(
+            "User '{}' is not a member of the book.",
+            user_name
+        ))
+        .into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Removes a member from the book. The owner cannot be removed, see [`leave_book`] for members
+/// removing themselves.
+pub async fn remove_member(
+    claim: Claim<IsBookOwner>,
+    Path((book_name, user_name)): Path<(String, String)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    remove(&claim, &book_name, &user_name, &pool)
+}
+
+/// Removes the user of the token from a book shared with them. A token that can only read cannot
+/// do this.
+pub async fn leave_book(
+    claim: Claim<IsBookMember>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    remove(&claim, &book_name, &claim.user.name, &pool)
+}
+
+#[allow(clippy::result_large_err)]
+fn remove<C>(
+    claim: &Claim<C>,
+    book_name: &str,
+    user_name: &str,
+    pool: &ConnectionPool,
+) -> Result<Response, Response> {
+    if user_name == claim.owner() {
+        return Err(
+            Error::Invalid("The owner cannot be removed from the book.".to_owned()).into_response(),
+        );
+    }
+    let conn = &mut get_connection(pool)?;
+    let result = conn.transaction(|conn| {
+        let before = book_members::table
+            .filter(
+                book_members::dsl::book_name
+                    .eq(book_name)
+                    .and(book_members::dsl::owner_name.eq(claim.owner()))
+                    .and(book_members::dsl::member_name.eq(user_name)),
+            )
+            .first::<BookMember>(conn)
+            .optional()?;
+        let deleted = diesel::delete(book_members::table)
+            .filter(
+                book_members::dsl::book_name
+                    .eq(book_name)
+                    .and(book_members::dsl::owner_name.eq(claim.owner()))
+                    .and(book_members::dsl::member_name.eq(user_name)),
+            )
+            .execute(conn)?;
+        if let Some(before) = before {
+            audit::deleted(
+                conn,
+                claim,
+                book_name,
+                AuditEntity::Member,
+                user_name,
+                &before.to_user_struct(),
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(deleted)
+    });
+    match result {
+        Ok(1) => Ok(().into_response()),
+        Ok(_) => Err(
+            Error::NotFound(format!("User '{}' is not a member of the book.", user_name))
+                .into_response(),
+        ),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
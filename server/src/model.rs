@@ -2,19 +2,176 @@ use crate::schema::*;
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use std::error::Error;
+use std::sync::PoisonError;
 
-#[derive(Queryable)]
+#[derive(Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = users, treat_none_as_null = true)]
 pub struct User {
+    /// Users are not renamed, the audit log is kept under their name.
+    #[diesel(skip_update)]
     pub name: String,
-    pub bearer: Option<String>,
+    pub admin: bool,
+    pub disabled: bool,
+    /// Left out of changesets, passwords are only set through their own routes.
+    #[diesel(skip_update)]
+    pub password_hash: Option<String>,
+    pub oidc_subject: Option<String>,
+}
+
+impl ToUserStruct for User {
+    type UserStruct = finance_lib::User;
+
+    fn to_user_struct(&self) -> Self::UserStruct {
+        finance_lib::User {
+            name: self.name.clone(),
+            admin: self.admin,
+            disabled: self.disabled,
+            oidc_subject: self.oidc_subject.clone(),
+        }
+    }
+}
+
+impl<'a> FromUserStruct<'a> for User {
+    type UserStruct = finance_lib::User;
+    type AddedInformation = ();
+
+    fn from_user_struct(
+        user_struct: &Self::UserStruct,
+        _added_information: Self::AddedInformation,
+    ) -> Self {
+        Self {
+            name: user_struct.name.clone(),
+            admin: user_struct.admin,
+            disabled: user_struct.disabled,
+            password_hash: None,
+            oidc_subject: user_struct.oidc_subject.clone(),
+        }
+    }
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = tokens)]
+pub struct Token {
+    pub id: i64,
+    pub user_name: String,
+    pub name: String,
+    pub token_hash: String,
+    pub created: NaiveDateTime,
+    pub expires: Option<NaiveDateTime>,
+    pub last_used: Option<NaiveDateTime>,
+    pub revoked: bool,
+    pub capability: String,
+}
+
+impl Token {
+    /// Unknown values fall back to the least privileged capability.
+    pub fn capability(&self) -> finance_lib::Capability {
+        self.capability
+            .parse()
+            .unwrap_or(finance_lib::Capability::Read)
+    }
+}
+
+impl ToUserStruct for Token {
+    type UserStruct = finance_lib::Token;
+
+    fn to_user_struct(&self) -> Self::UserStruct {
+        finance_lib::Token {
+            id: self.id,
+            name: self.name.clone(),
+            created: self.created,
+            expires: self.expires,
+            last_used: self.last_used,
+            revoked: self.revoked,
+            capability: self.capability(),
+            books: None,
+        }
+    }
+}
+
+pub struct AddedInformationForToken<'a> {
+    pub user_name: &'a String,
+    pub token_hash: &'a String,
+}
+
+impl<'a> FromNewUserStruct<'a> for Token {
+    type AddedInformation = AddedInformationForToken<'a>;
+    type NewUserStruct = finance_lib::NewToken;
+
+    fn from_new_user_struct(
+        new_user_struct: &Self::NewUserStruct,
+        added_information: Self::AddedInformation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let id = crate::SNOWFLAKE_GENERATOR.lock()?.real_time_generate();
+        Ok(Self {
+            id,
+            user_name: added_information.user_name.clone(),
+            name: new_user_struct.name.clone(),
+            token_hash: added_information.token_hash.clone(),
+            created: Utc::now().naive_utc(),
+            expires: new_user_struct.expires,
+            last_used: None,
+            revoked: false,
+            capability: new_user_struct
+                .capability
+                .unwrap_or(finance_lib::Capability::Admin)
+                .to_string(),
+        })
+    }
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = token_books)]
+pub struct TokenBook {
+    pub token_id: i64,
+    pub book_id: i64,
+}
+
+/// Membership of a user in a book. Rows of a book are keyed by the name of its owner, so members
+/// access them through `owner_name`.
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = book_members)]
+pub struct BookMember {
+    pub book_name: String,
+    pub owner_name: String,
+    pub member_name: String,
+    pub role: String,
+}
+
+impl BookMember {
+    /// Unknown values fall back to the least privileged role.
+    pub fn role(&self) -> finance_lib::BookRole {
+        self.role.parse().unwrap_or(finance_lib::BookRole::Viewer)
+    }
+}
+
+impl ToUserStruct for BookMember {
+    type UserStruct = finance_lib::BookMember;
+
+    fn to_user_struct(&self) -> Self::UserStruct {
+        finance_lib::BookMember {
+            user_name: self.member_name.clone(),
+            role: self.role(),
+        }
+    }
 }
 
 #[derive(Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = books)]
+#[diesel(table_name = books, treat_none_as_null = true)]
 pub struct Book {
     pub name: String,
     pub user_name: String,
     pub description: Option<String>,
+    pub immutable: bool,
+    /// Only changed by closing a period.
+    #[diesel(skip_update)]
+    pub lock_date: Option<NaiveDateTime>,
+    /// Set while the row is in the trash.
+    #[diesel(skip_update)]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Stays the same when the book is renamed, the audit log is kept under it.
+    #[diesel(skip_update)]
+    pub id: i64,
 }
 
 impl ToUserStruct for Book {
@@ -24,6 +181,8 @@ impl ToUserStruct for Book {
         finance_lib::Book {
             name: self.name.clone(),
             description: self.description.clone(),
+            immutable: Some(self.immutable),
+            lock_date: self.lock_date,
         }
     }
 }
@@ -43,18 +202,28 @@ impl<'a> FromUserStruct<'a> for Book {
             name: user_struct.name.clone(),
             user_name: added_information.user_name.clone(),
             description: user_struct.description.clone(),
+            immutable: user_struct.immutable.unwrap_or(false),
+            lock_date: None,
+            deleted_at: None,
+            id: crate::SNOWFLAKE_GENERATOR
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .real_time_generate(),
         }
     }
 }
 
 #[derive(Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = currencies)]
+#[diesel(table_name = currencies, treat_none_as_null = true)]
 pub struct Currency {
     symbol: String,
     decimal_points: i32,
     description: Option<String>,
     user_name: String,
     book_name: String,
+    /// Set while the row is in the trash.
+    #[diesel(skip_update)]
+    deleted_at: Option<NaiveDateTime>,
 }
 
 impl ToUserStruct for Currency {
@@ -87,6 +256,7 @@ impl<'a> FromUserStruct<'a> for Currency {
             decimal_points: user_struct.decimal_points,
             description: user_struct.description.clone(),
             symbol: user_struct.symbol.clone(),
+            deleted_at: None,
         }
     }
 }
@@ -98,6 +268,7 @@ pub struct Account {
     pub description: Option<String>,
     pub user_name: String,
     pub book_name: String,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 impl ToUserStruct for Account {
@@ -122,18 +293,33 @@ impl<'a> FromUserStruct<'a> for Account {
             description: user_struct.description.clone(),
             book_name: added_information.book_name.clone(),
             user_name: added_information.user_name.clone(),
+            deleted_at: None,
         }
     }
 }
 
 #[derive(Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = transactions)]
+#[diesel(table_name = transactions, treat_none_as_null = true)]
 pub struct Transaction {
     pub id: i64,
     time: NaiveDateTime,
     pub description: Option<String>,
     pub book_name: String,
     pub user_name: String,
+    /// Only changed through the post route, posted transactions of immutable books are final.
+    #[diesel(skip_update)]
+    pub posted: bool,
+    #[diesel(skip_update)]
+    pub reverses: Option<i64>,
+    /// Set while the row is in the trash.
+    #[diesel(skip_update)]
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+impl Transaction {
+    pub fn time(&self) -> NaiveDateTime {
+        self.time
+    }
 }
 
 impl ToUserStruct for Transaction {
@@ -143,6 +329,8 @@ impl ToUserStruct for Transaction {
             id: self.id,
             time: Some(self.time),
             description: self.description.clone(),
+            posted: Some(self.posted),
+            reverses: self.reverses,
         }
     }
 }
@@ -161,6 +349,9 @@ impl<'a> FromUserStruct<'a> for Transaction {
             description: user_struct.description.clone(),
             book_name: added_information.book_name.clone(),
             user_name: added_information.user_name.clone(),
+            posted: user_struct.posted.unwrap_or(false),
+            reverses: user_struct.reverses,
+            deleted_at: None,
         }
     }
 }
@@ -182,11 +373,14 @@ impl<'a> FromNewUserStruct<'a> for Transaction {
             description: new_user_struct.description.clone(),
             book_name: added_information.book_name.clone(),
             user_name: added_information.user_name.clone(),
+            posted: new_user_struct.posted.unwrap_or(false),
+            reverses: None,
+            deleted_at: None,
         })
     }
 }
 
-#[derive(Queryable, Insertable)]
+#[derive(Queryable, Insertable, Clone)]
 #[diesel(table_name = postings)]
 pub struct Posting {
     pub id: i64,
@@ -308,3 +502,53 @@ where
         added_information: Self::AddedInformation,
     ) -> Result<Self, Box<dyn Error>>;
 }
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub book_name: String,
+    pub owner_name: String,
+    pub time: NaiveDateTime,
+    pub actor: String,
+    pub token_id: Option<i64>,
+    pub entity: String,
+    pub entity_id: String,
+    pub action: String,
+    pub before_value: Option<String>,
+    pub after_value: Option<String>,
+    pub previous_hash: Option<String>,
+    pub hash: String,
+    /// `None` for entries of books purged before books had ids.
+    pub book_id: Option<i64>,
+}
+
+impl ToUserStruct for AuditLogEntry {
+    type UserStruct = finance_lib::AuditEntry;
+
+    fn to_user_struct(&self) -> Self::UserStruct {
+        let parse = |value: &Option<String>| {
+            value
+                .as_ref()
+                .and_then(|value| serde_json::from_str(value).ok())
+        };
+        finance_lib::AuditEntry {
+            id: self.id,
+            time: self.time,
+            actor: self.actor.clone(),
+            token_id: self.token_id,
+            entity: self
+                .entity
+                .parse()
+                .unwrap_or(finance_lib::AuditEntity::Book),
+            entity_id: self.entity_id.clone(),
+            action: self
+                .action
+                .parse()
+                .unwrap_or(finance_lib::AuditAction::Update),
+            before: parse(&self.before_value),
+            after: parse(&self.after_value),
+            hash: self.hash.clone(),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! OpenAPI document of the server. Routes are registered through [`Api`], which adds them to the
+//! router and documents them from the extractors of their handler, so the document cannot drift
+//! from what the server actually serves.
+
+use crate::auth::{Claim, RequiredCapability};
+use crate::etag::IfMatch;
+use crate::idempotency::{self, IdempotencyKey};
+use crate::ConnectionPool;
+use axum::extract::{Path, Query, State};
+use axum::handler::Handler;
+use axum::routing::{get, on, MethodFilter};
+use axum::{Extension, Json, Router};
+use finance_lib::schema::{json, Components, Map, Schema, Value};
+use finance_lib::ApiError;
+
+/// What the extractors of a handler tell about its route.
+pub struct OperationParts<'a> {
+    path_parameters: Vec<&'a str>,
+    parameters: Vec<Value>,
+    body: Option<Value>,
+    requirement: Option<String>,
+    components: &'a mut Components,
+}
+
+/// An extractor documenting the part of the request it reads.
+pub trait DocumentedExtractor {
+    fn document(parts: &mut OperationParts);
+}
+
+impl<C: RequiredCapability> DocumentedExtractor for Claim<C> {
+    fn document(parts: &mut OperationParts) {
+        let mut requirement = format!("Needs a token with the `{}` capability", C::CAPABILITY);
+        if C::SERVER_ADMIN {
+            requirement.push_str(" of a server administrator");
+        }
+        if parts.path_parameters.contains(&"book_name") {
+            requirement.push_str(&format!(" and the `{}` role in the book", C::ROLE));
+            parts.parameters.push(json!({
+                "name": "owner",
+                "in": "query",
+                "required": false,
+                "description": "Owner of the book, needed if several users share a book of that \
+                    name.",
+                "schema": String::reference(parts.components),
+            }));
+        }
+        requirement.push('.');
+        parts.requirement = Some(requirement);
+    }
+}
+
+/// Types of path parameters, in the order of the path.
+pub trait PathParameters {
+    fn schemas(components: &mut Components) -> Vec<Value>;
+}
+
+impl PathParameters for String {
+    fn schemas(components: &mut Components) -> Vec<Value> {
+        vec![String::reference(components)]
+    }
+}
+
+impl PathParameters for i64 {
+    fn schemas(components: &mut Components) -> Vec<Value> {
+        vec![i64::reference(components)]
+    }
+}
+
+impl<A: Schema, B: Schema> PathParameters for (A, B) {
+    fn schemas(components: &mut Components) -> Vec<Value> {
+        vec![A::reference(components), B::reference(components)]
+    }
+}
+
+impl<A: Schema, B: Schema, C: Schema> PathParameters for (A, B, C) {
+    fn schemas(components: &mut Components) -> Vec<Value> {
+        vec![
+            A::reference(components),
+            B::reference(components),
+            C::reference(components),
+        ]
+    }
+}
+
+impl<T: PathParameters> DocumentedExtractor for Path<T> {
+    fn document(parts: &mut OperationParts) {
+        let schemas = T::schemas(parts.components);
+        for (name, schema) in parts.path_parameters.iter().zip(schemas) {
+            parts.parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": schema,
+            }));
+        }
+    }
+}
+
+impl<T: Schema> DocumentedExtractor for Query<T> {
+    fn document(parts: &mut OperationParts) {
+        let schema = T::schema(parts.components);
+        let required = schema["required"].as_array().cloned().unwrap_or_default();
+        if let Some(properties) = schema["properties"].as_object() {
+            for (name, property) in properties {
+                parts.parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&json!(name)),
+                    "schema": property,
+                }));
+            }
+        }
+    }
+}
+
+impl<T: Schema> DocumentedExtractor for Json<T> {
+    fn document(parts: &mut OperationParts) {
+        parts.body = Some(json!({
+            "required": true,
+            "content": { "application/json": { "schema": T::reference(parts.components) } },
+        }));
+    }
+}
+
+impl<S> DocumentedExtractor for State<S> {
+    fn document(_: &mut OperationParts) {}
+}
+
+impl<T> DocumentedExtractor for Extension<T> {
+    fn document(_: &mut OperationParts) {}
+}
+
+impl DocumentedExtractor for IfMatch {
+    fn document(parts: &mut OperationParts) {
+        parts.parameters.push(json!({
+            "name": "If-Match",
+            "in": "header",
+            "required": false,
+            "description": "ETag of the entity as it was read, the change is refused with 412 if \
+                            it changed since. Without it the last writer wins.",
+            "schema": { "type": "string" },
+        }));
+    }
+}
+
+impl DocumentedExtractor for IdempotencyKey {
+    fn document(parts: &mut OperationParts) {
+        parts.parameters.push(json!({
+            "name": idempotency::HEADER,
+            "in": "header",
+            "required": false,
+            "description": "Chosen by the client, retries with the same key return the id created \
+                            the first time instead of creating another entity.",
+            "schema": { "type": "string", "maxLength": idempotency::MAX_KEY_LENGTH },
+        }));
+    }
+}
+
+/// A handler documenting its route from its extractors. Implemented like [`Handler`], for
+/// functions whose arguments all are [`DocumentedExtractor`]s.
+pub trait DocumentedHandler<T> {
+    fn document(parts: &mut OperationParts);
+}
+
+impl<F, Fut> DocumentedHandler<((),)> for F
+where
+    F: FnOnce() -> Fut,
+{
+    fn document(_: &mut OperationParts) {}
+}
+
+macro_rules! documented_handler {
+    ($($ty:ident),*) => {
+        impl<F, Fut, M, $($ty,)*> DocumentedHandler<(M, $($ty,)*)> for F
+        where
+            F: FnOnce($($ty,)*) -> Fut,
+            $($ty: DocumentedExtractor,)*
+        {
+            fn document(parts: &mut OperationParts) {
+                $($ty::document(parts);)*
+            }
+        }
+    };
+}
+
+documented_handler!(T1);
+documented_handler!(T1, T2);
+documented_handler!(T1, T2, T3);
+documented_handler!(T1, T2, T3, T4);
+documented_handler!(T1, T2, T3, T4, T5);
+documented_handler!(T1, T2, T3, T4, T5, T6);
+documented_handler!(T1, T2, T3, T4, T5, T6, T7);
+
+enum Body {
+    Empty,
+    Json(fn(&mut Components) -> Value),
+    Text,
+    Redirect,
+}
+
+/// Documentation of a route that cannot be told from the signature of its handler.
+pub struct Operation {
+    summary: &'static str,
+    body: Body,
+    tagged: bool,
+    deprecated: bool,
+}
+
+pub fn operation(summary: &'static str) -> Operation {
+    Operation {
+        summary,
+        body: Body::Empty,
+        tagged: false,
+        deprecated: false,
+    }
+}
+
+impl Operation {
+    pub fn returns<T: Schema>(mut self) -> Self {
+        self.body = Body::Json(T::reference);
+        self
+    }
+
+    pub fn returns_text(mut self) -> Self {
+        self.body = Body::Text;
+        self
+    }
+
+    pub fn redirects(mut self) -> Self {
+        self.body = Body::Redirect;
+        self
+    }
+
+    /// The response carries the tag of the entity in `ETag`.
+    pub fn tagged(mut self) -> Self {
+        self.tagged = true;
+        self
+    }
+
+    /// The route is kept for older clients only.
+    pub fn deprecated(mut self) -> Self {
+        self.deprecated = true;
+        self
+    }
+}
+
+/// Router along with the OpenAPI document of its routes.
+#[derive(Default)]
+pub struct Api {
+    router: Router<ConnectionPool>,
+    paths: Map<String, Value>,
+    components: Components,
+}
+
+impl Api {
+    pub fn get<H, T>(self, path: &str, handler: H, operation: Operation) -> Self
+    where
+        H: Handler<T, ConnectionPool> + DocumentedHandler<T>,
+        T: 'static,
+    {
+        self.route(MethodFilter::GET, "get", path, handler, operation)
+    }
+
+    pub fn post<H, T>(self, path: &str, handler: H, operation: Operation) -> Self
+    where
+        H: Handler<T, ConnectionPool> + DocumentedHandler<T>,
+        T: 'static,
+    {
+        self.route(MethodFilter::POST, "post", path, handler, operation)
+    }
+
+    pub fn patch<H, T>(self, path: &str, handler: H, operation: Operation) -> Self
+    where
+        H: Handler<T, ConnectionPool> + DocumentedHandler<T>,
+        T: 'static,
+    {
+        self.route(MethodFilter::PATCH, "patch", path, handler, operation)
+    }
+
+    pub fn delete<H, T>(self, path: &str, handler: H, operation: Operation) -> Self
+    where
+        H: Handler<T, ConnectionPool> + DocumentedHandler<T>,
+        T: 'static,
+    {
+        self.route(MethodFilter::DELETE, "delete", path, handler, operation)
+    }
+
+    fn route<H, T>(
+        mut self,
+        filter: MethodFilter,
+        method: &str,
+        path: &str,
+        handler: H,
+        operation: Operation,
+    ) -> Self
+    where
+        H: Handler<T, ConnectionPool> + DocumentedHandler<T>,
+        T: 'static,
+    {
+        let mut parts = OperationParts {
+            path_parameters: path
+                .split('/')
+                .filter_map(|segment| segment.strip_prefix(':'))
+                .collect(),
+            parameters: vec![],
+            body: None,
+            requirement: None,
+            components: &mut self.components,
+        };
+        H::document(&mut parts);
+        // Path parameters come first, in the order of the path.
+        parts
+            .parameters
+            .sort_by_key(|parameter| parameter["in"] != "path");
+        let documented = document(std::any::type_name::<H>(), operation, parts);
+
+        let openapi_path = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let item = self.paths.entry(openapi_path).or_insert_with(|| json!({}));
+        item[method] = documented;
+        self.router = self.router.route(path, on(filter, handler));
+        self
+    }
+
+    pub fn document(&self) -> Value {
+        json!({
+            "openapi": "3.1.0",
+            "info": { "title": "Finance", "version": env!("CARGO_PKG_VERSION") },
+            "paths": self.paths,
+            "components": {
+                "schemas": self.components,
+                "securitySchemes": { "token": { "type": "http", "scheme": "bearer" } },
+            },
+        })
+    }
+
+    /// The router of all routes, also serving the document at `/openapi.json`.
+    pub fn into_router(self) -> Router<ConnectionPool> {
+        let document = self.document();
+        self.router.route(
+            "/openapi.json",
+            get(move || {
+                let document = document.clone();
+                async move { Json(document) }
+            }),
+        )
+    }
+}
+
+fn document(handler: &str, operation: Operation, parts: OperationParts) -> Value {
+    let mut success = json!({ "description": "Success." });
+    let status = match operation.body {
+        Body::Empty => "200",
+        Body::Json(schema) => {
+            success["content"] =
+                json!({ "application/json": { "schema": schema(parts.components) } });
+            "200"
+        }
+        Body::Text => {
+            success["content"] = json!({ "text/plain": { "schema": { "type": "string" } } });
+            "200"
+        }
+        Body::Redirect => {
+            success["description"] = json!("Redirect.");
+            "303"
+        }
+    };
+    if operation.tagged {
+        success["headers"] = json!({
+            "ETag": {
+                "description": "Tag of the entity, to send in If-Match when changing it.",
+                "schema": { "type": "string" },
+            },
+        });
+    }
+    let error = json!({
+        "description": "Error.",
+        "content": { "application/json": { "schema": ApiError::reference(parts.components) } },
+    });
+
+    let mut documented = json!({
+        "operationId": handler.rsplit("::").next().unwrap_or(handler),
+        "summary": operation.summary,
+        "parameters": parts.parameters,
+        "responses": { status: success, "default": error },
+    });
+    if let Some(body) = parts.body {
+        documented["requestBody"] = body;
+    }
+    if operation.deprecated {
+        documented["deprecated"] = json!(true);
+    }
+    if let Some(requirement) = parts.requirement {
+        documented["description"] = json!(requirement);
+        documented["security"] = json!([{ "token": [] }]);
+    }
+    documented
+}
@@ -0,0 +1,341 @@
+//! Reports summing up the postings of a book over periods of time, for charts.
+
+use crate::auth::{CanRead, Claim};
+use crate::db::DbConnection;
+use crate::error::Error;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{Datelike, Duration, Months, NaiveDateTime, NaiveTime, Utc};
+use diesel::dsl::sum;
+use diesel::prelude::*;
+use finance_lib::{BalancePoint, FlowAmount, Interval, ReportQuery};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Entries are summed up per time, which is the valuta of a posting or the time of its transaction.
+diesel::allow_columns_to_appear_in_same_group_by_clause!(
+    postings::account_name,
+    postings::currency,
+    postings::valuta,
+    transactions::time,
+);
+
+/// Most periods a report may have.
+const MAX_PERIODS: usize = 1000;
+/// Periods before the current one a report covers by default.
+const DEFAULT_PERIODS: usize = 11;
+
+/// Start of the period containing `time`.
+pub fn period_start(interval: Interval, time: NaiveDateTime) -> NaiveDateTime {
+    let date = time.date();
+    let start = match interval {
+        Interval::Day => date,
+        Interval::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+        Interval::Month => date.with_day(1).unwrap_or(date),
+        Interval::Year => date.with_ordinal(1).unwrap_or(date),
+    };
+    start.and_time(NaiveTime::MIN)
+}
+
+/// Start of the period after the one starting at `start`.
+pub fn next_period(interval: Interval, start: NaiveDateTime) -> NaiveDateTime {
+    match interval {
+        Interval::Day => start + Duration::days(1),
+        Interval::Week => start + Duration::weeks(1),
+        Interval::Month => start + Months::new(1),
+        Interval::Year => start + Months::new(12),
+    }
+}
+
+/// Starts of the periods of the report along with its end, checking its range.
+fn periods(query: &ReportQuery) -> Result<(Vec<NaiveDateTime>, NaiveDateTime), Error> {
+    let interval = query.interval.unwrap_or_default();
+    let current = period_start(interval, Utc::now().naive_utc());
+    let to = match query.to {
+        Some(to) => to,
+        None => next_period(interval, current),
+    };
+    let mut start = match query.from {
+        Some(from) => period_start(interval, from),
+        None => {
+            let mut start = period_start(interval, to - Duration::seconds(1));
+            for _ in 0..DEFAULT_PERIODS {
+                start = period_start(interval, start - Duration::seconds(1));
+            }
+            start
+        }
+    };
+    if start >= to {
+        return Err(Error::InvalidField(
+            "from",
+            "The report has to start before it ends.".to_owned(),
+        ));
+    }
+    let mut periods = vec![];
+    while start < to {
+        if periods.len() == MAX_PERIODS {
+            return Err(Error::InvalidField(
+                "interval",
+                format!("A report may have at most {} periods.", MAX_PERIODS),
+            ));
+        }
+        periods.push(start);
+        start = next_period(interval, start);
+    }
+    Ok((periods, to))
+}
+
+/// Whether `account_name` is `account` or below it.
+pub fn is_below(account_name: &str, account: &str) -> bool {
+    account_name
+        .strip_prefix(account)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+/// Postings of an account in a currency at one time, summed up as the reports count them.
+pub struct Entry {
+    pub account_name: String,
+    pub currency: String,
+    pub amount: i64,
+    pub time: NaiveDateTime,
+}
+
+/// The postings of the live transactions of the book the report counts, joined with their
+/// transactions. The account is matched with `LIKE`, which lets through more than `is_below` on
+/// backends comparing without case, so the rows still have to be checked.
+macro_rules! counted_postings {
+    ($owner:expr, $book_name:expr, $query:expr) => {{
+        let budgets = match $query.budget {
+            Some(true) => vec![false, true],
+            _ => vec![false],
+        };
+        let (account, pattern) = match &$query.account {
+            Some(account) => (account.clone(), format!("{}:%", escape_like(account))),
+            None => (String::new(), "%".to_owned()),
+        };
+        postings::table
+            .inner_join(
+                transactions::table.on(transactions::dsl::id
+                    .eq(postings::dsl::transaction_id)
+                    .and(transactions::dsl::user_name.eq(postings::dsl::user_name))
+                    .and(transactions::dsl::book_name.eq(postings::dsl::book_name))),
+            )
+            .filter(
+                postings::dsl::user_name
+                    .eq($owner)
+                    .and(postings::dsl::book_name.eq($book_name))
+                    .and(transactions::dsl::deleted_at.is_null())
+                    .and(postings::dsl::budget.eq_any(budgets))
+                    .and(
+                        postings::dsl::account_name
+                            .eq(account)
+                            .or(postings::dsl::account_name.like(pattern).escape('!')),
+                    ),
+            )
+    }};
+}
+
+/// Whether a posting counts before or since `time`, at its valuta or else at the time of its
+/// transaction. Both are spelled out, as negating one would drop the postings without valuta.
+macro_rules! counted {
+    (before $time:expr) => {
+        postings::dsl::valuta.lt($time).or(postings::dsl::valuta
+            .is_null()
+            .and(transactions::dsl::time.lt($time)))
+    };
+    (since $time:expr) => {
+        postings::dsl::valuta.ge($time).or(postings::dsl::valuta
+            .is_null()
+            .and(transactions::dsl::time.ge($time)))
+    };
+}
+
+/// Escapes the wildcards of `LIKE` with `!`, which unlike a backslash needs no escaping in MySQL.
+fn escape_like(text: &str) -> String {
+    text.replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_")
+}
+
+/// Whether the report counts the postings of `account_name`.
+fn counts(query: &ReportQuery, account_name: &str) -> bool {
+    match &query.account {
+        Some(account) => is_below(account_name, account),
+        None => true,
+    }
+}
+
+/// Balances per currency of what was posted before `from`.
+fn opening(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    query: &ReportQuery,
+    from: NaiveDateTime,
+) -> QueryResult<BTreeMap<String, i64>> {
+    let sums = counted_postings!(owner, book_name, query)
+        .filter(counted!(before from))
+        .group_by((postings::dsl::account_name, postings::dsl::currency))
+        .select((
+            postings::dsl::account_name,
+            postings::dsl::currency,
+            sum(postings::dsl::amount),
+        ))
+        .load::<(String, String, Option<i64>)>(conn)?;
+    let mut opening = BTreeMap::new();
+    for (account_name, currency, amount) in sums {
+        if counts(query, &account_name) {
+            *opening.entry(currency).or_default() += amount.unwrap_or_default();
+        }
+    }
+    Ok(opening)
+}
+
+/// What was posted from `from` up to before `to`.
+fn entries(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    query: &ReportQuery,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> QueryResult<Vec<Entry>> {
+    let sums = counted_postings!(owner, book_name, query)
+        .filter(counted!(since from).and(counted!(before to)))
+        .group_by((
+            postings::dsl::account_name,
+            postings::dsl::currency,
+            postings::dsl::valuta,
+            transactions::dsl::time,
+        ))
+        .select((
+            postings::dsl::account_name,
+            postings::dsl::currency,
+            postings::dsl::valuta,
+            transactions::dsl::time,
+            sum(postings::dsl::amount),
+        ))
+        .load::<(
+            String,
+            String,
+            Option<NaiveDateTime>,
+            NaiveDateTime,
+            Option<i64>,
+        )>(conn)?;
+    Ok(sums
+        .into_iter()
+        .filter(|(account_name, ..)| counts(query, account_name))
+        .map(|(account_name, currency, valuta, time, amount)| Entry {
+            account_name,
+            currency,
+            amount: amount.unwrap_or_default(),
+            time: valuta.unwrap_or(time),
+        })
+        .collect())
+}
+
+/// Index of the period containing `time`, `None` before the first one.
+fn period_of(periods: &[NaiveDateTime], time: NaiveDateTime) -> Option<usize> {
+    periods
+        .partition_point(|start| *start <= time)
+        .checked_sub(1)
+}
+
+/// Balances at the end of each period, starting with the `opening` balances before the first.
+pub fn balances(
+    periods: &[NaiveDateTime],
+    opening: &BTreeMap<String, i64>,
+    entries: &[Entry],
+) -> Vec<BalancePoint> {
+    let currencies: BTreeSet<&str> = entries
+        .iter()
+        .map(|entry| entry.currency.as_str())
+        .chain(opening.keys().map(String::as_str))
+        .collect();
+    let mut changes: BTreeMap<(usize, &str), i64> = BTreeMap::new();
+    let mut opening: BTreeMap<&str, i64> = opening
+        .iter()
+        .map(|(currency, amount)| (currency.as_str(), *amount))
+        .collect();
+    for entry in entries {
+        let currency = entry.currency.as_str();
+        match period_of(periods, entry.time) {
+            Some(period) => *changes.entry((period, currency)).or_default() += entry.amount,
+            None => *opening.entry(currency).or_default() += entry.amount,
+        }
+    }
+    let mut points = vec![];
+    for (period, start) in periods.iter().enumerate() {
+        for currency in &currencies {
+            let balance = opening.entry(currency).or_default();
+            *balance += changes
+                .get(&(period, *currency))
+                .copied()
+                .unwrap_or_default();
+            points.push(BalancePoint {
+                period: *start,
+                currency: (*currency).to_owned(),
+                balance: *balance,
+            });
+        }
+    }
+    points
+}
+
+/// Sums per period, account cut off after `depth` levels and currency, leaving out zeros.
+pub fn flows(periods: &[NaiveDateTime], depth: usize, entries: &[Entry]) -> Vec<FlowAmount> {
+    let mut sums: BTreeMap<(usize, String, &str), i64> = BTreeMap::new();
+    for entry in entries {
+        if let Some(period) = period_of(periods, entry.time) {
+            let group: Vec<&str> = entry.account_name.split(':').take(depth).collect();
+            let key = (period, group.join(":"), entry.currency.as_str());
+            *sums.entry(key).or_default() += entry.amount;
+        }
+    }
+    sums.into_iter()
+        .filter(|(_, amount)| *amount != 0)
+        .map(|((period, account_name, currency), amount)| FlowAmount {
+            period: periods[period],
+            account_name,
+            currency: currency.to_owned(),
+            amount,
+        })
+        .collect()
+}
+
+pub async fn balance_report(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    Query(query): Query<ReportQuery>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let (periods, to) = periods(&query).map_err(IntoResponse::into_response)?;
+    let conn = &mut get_connection(&pool)?;
+    let opening = opening(conn, claim.owner(), &book_name, &query, periods[0])
+        .map_err(|error| Error::from(error).into_response())?;
+    let entries = entries(conn, claim.owner(), &book_name, &query, periods[0], to)
+        .map_err(|error| Error::from(error).into_response())?;
+    Ok(Json(balances(&periods, &opening, &entries)).into_response())
+}
+
+pub async fn flow_report(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    Query(query): Query<ReportQuery>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let depth = query.depth.unwrap_or(1);
+    if depth == 0 {
+        return Err(
+            Error::InvalidField("depth", "The depth has to be at least 1.".to_owned())
+                .into_response(),
+        );
+    }
+    let (periods, to) = periods(&query).map_err(IntoResponse::into_response)?;
+    let conn = &mut get_connection(&pool)?;
+    let entries = entries(conn, claim.owner(), &book_name, &query, periods[0], to)
+        .map_err(|error| Error::from(error).into_response())?;
+    Ok(Json(flows(&periods, depth, &entries)).into_response())
+}
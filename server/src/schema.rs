@@ -6,6 +6,35 @@ diesel::table! {
         description -> Nullable<Varchar>,
         user_name -> Varchar,
         book_name -> Varchar,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Bigint,
+        book_name -> Varchar,
+        owner_name -> Varchar,
+        time -> Timestamp,
+        actor -> Varchar,
+        token_id -> Nullable<Bigint>,
+        entity -> Varchar,
+        entity_id -> Varchar,
+        action -> Varchar,
+        before_value -> Nullable<Text>,
+        after_value -> Nullable<Text>,
+        previous_hash -> Nullable<Varchar>,
+        hash -> Varchar,
+        book_id -> Nullable<Bigint>,
+    }
+}
+
+diesel::table! {
+    book_members (book_name, owner_name, member_name) {
+        book_name -> Varchar,
+        owner_name -> Varchar,
+        member_name -> Varchar,
+        role -> Varchar,
     }
 }
 
@@ -14,6 +43,10 @@ diesel::table! {
         name -> Varchar,
         user_name -> Varchar,
         description -> Nullable<Varchar>,
+        immutable -> Bool,
+        lock_date -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+        id -> Bigint,
     }
 }
 
@@ -24,6 +57,17 @@ diesel::table! {
         description -> Nullable<Varchar>,
         user_name -> Varchar,
         book_name -> Varchar,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (user_name, idempotency_key) {
+        user_name -> Varchar,
+        idempotency_key -> Varchar,
+        request_hash -> Varchar,
+        created_id -> Bigint,
+        created -> Timestamp,
     }
 }
 
@@ -41,6 +85,27 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tokens (id) {
+        id -> Bigint,
+        user_name -> Varchar,
+        name -> Varchar,
+        token_hash -> Varchar,
+        created -> Timestamp,
+        expires -> Nullable<Timestamp>,
+        last_used -> Nullable<Timestamp>,
+        revoked -> Bool,
+        capability -> Varchar,
+    }
+}
+
+diesel::table! {
+    token_books (token_id, book_id) {
+        token_id -> Bigint,
+        book_id -> Bigint,
+    }
+}
+
 diesel::table! {
     transactions (id, book_name, user_name) {
         id -> Bigint,
@@ -48,27 +113,41 @@ diesel::table! {
         description -> Nullable<Varchar>,
         book_name -> Varchar,
         user_name -> Varchar,
+        posted -> Bool,
+        reverses -> Nullable<Bigint>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
 diesel::table! {
     users (name) {
         name -> Varchar,
-        bearer -> Nullable<Varchar>,
+        admin -> Bool,
+        disabled -> Bool,
+        password_hash -> Nullable<Varchar>,
+        oidc_subject -> Nullable<Varchar>,
     }
 }
 
 diesel::joinable!(accounts -> users (user_name));
 diesel::joinable!(books -> users (user_name));
 diesel::joinable!(currencies -> users (user_name));
+diesel::joinable!(idempotency_keys -> users (user_name));
 diesel::joinable!(postings -> users (user_name));
+diesel::joinable!(token_books -> tokens (token_id));
+diesel::joinable!(tokens -> users (user_name));
 diesel::joinable!(transactions -> users (user_name));
 
 diesel::allow_tables_to_appear_in_same_query!(
     accounts,
+    audit_log,
+    book_members,
     books,
     currencies,
+    idempotency_keys,
     postings,
+    token_books,
+    tokens,
     transactions,
     users,
 );
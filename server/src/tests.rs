@@ -0,0 +1,2345 @@
+//! Drives the router through the client of `finance_lib`. The router runs in-process against the
+//! migrated database of `DATABASE_URL`, every test works in books of its own user. With the feature
+//! sqlite every test gets a database file of its own instead. The tests run on every backend, to
+//! find where MySQL and PostgreSQL behave differently.
+//! Only the test of the OpenAPI document itself runs without a database.
+
+use crate::auth::{self, Claim, RequiredCapability};
+use crate::model::{self, FromNewUserStruct, FromUserStruct};
+use crate::schema::{audit_log, books, currencies, postings, tokens, transactions, users};
+use crate::{admin, api, db, login, router, trash, ConnectionPool, SNOWFLAKE_GENERATOR};
+use axum::extract::{FromRequestParts, Path, State};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Form, Json};
+use diesel::result::DatabaseErrorKind;
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use finance_lib::client::{ClientError, FinanceClient};
+use finance_lib::*;
+use openidconnect::core::{
+    CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields, CoreJsonWebKeySet, CoreJwsSigningAlgorithm,
+    CoreProviderMetadata, CoreResponseType, CoreRsaPrivateSigningKey, CoreSubjectIdentifierType,
+    CoreTokenResponse, CoreTokenType,
+};
+use openidconnect::{
+    AccessToken, Audience, AuthUrl, ClientId, ClientSecret, EmptyAdditionalClaims,
+    EmptyAdditionalProviderMetadata, EmptyExtraTokenFields, IssuerUrl, JsonWebKeySetUrl, Nonce,
+    PrivateSigningKey, RedirectUrl, ResponseTypes, StandardClaims, SubjectIdentifier, TokenUrl,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(feature = "sqlite"))]
+fn pool() -> ConnectionPool {
+    db::get_connection_pool()
+}
+
+#[cfg(feature = "sqlite")]
+fn pool() -> ConnectionPool {
+    let id = SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate();
+    let path = std::env::temp_dir().join(format!("finance-test-{}.db", id));
+    db::connection_pool(path.to_str().unwrap())
+}
+
+/// Serves the router on a free port and returns a client for a new administrator.
+async fn serve() -> FinanceClient {
+    serve_pool().await.0
+}
+
+/// Like [`serve`], with the pool of the database the router uses.
+async fn serve_pool() -> (FinanceClient, ConnectionPool) {
+    serve_with(login::Oidc::new(None)).await
+}
+
+/// Like [`serve_pool`], logging users in at the OpenID Connect provider of `oidc`.
+async fn serve_with(oidc: Arc<login::Oidc>) -> (FinanceClient, ConnectionPool) {
+    let pool = pool();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(router(pool.clone(), oidc).into_make_service());
+    tokio::spawn(server);
+    let id = SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate();
+    let created = admin::create_admin(&pool, &format!("test-{}", id)).unwrap();
+    let client = FinanceClient::new(url.parse().unwrap()).with_token(created.secret);
+    (client, pool)
+}
+
+async fn create_book(client: &FinanceClient, name: &str) {
+    client
+        .create_book(&Book {
+            name: name.to_owned(),
+            description: None,
+            immutable: None,
+            lock_date: None,
+        })
+        .await
+        .unwrap();
+    client
+        .create_currency(
+            name,
+            &Currency {
+                symbol: "EUR".to_owned(),
+                description: None,
+                decimal_points: 2,
+            },
+        )
+        .await
+        .unwrap();
+    for account in ["Cash", "Groceries"] {
+        client
+            .create_account(
+                name,
+                &Account {
+                    name: account.to_owned(),
+                    description: None,
+                },
+            )
+            .await
+            .unwrap();
+    }
+}
+
+/// Creates a user who is not an administrator, returns their name and a client for them.
+async fn create_user(client: &FinanceClient, prefix: &str) -> (String, FinanceClient) {
+    let id = SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate();
+    let name = format!("{}-{}", prefix, id);
+    let created = client
+        .create_user(&NewUser {
+            name: name.clone(),
+            admin: false,
+        })
+        .await
+        .unwrap();
+    (name, client.clone().with_token(created.secret))
+}
+
+/// A client whose token is limited to `capability`.
+async fn scoped(client: &FinanceClient, capability: Capability) -> FinanceClient {
+    let created = client
+        .create_token(&NewToken {
+            name: format!("{} only", capability),
+            expires: None,
+            capability: Some(capability),
+            books: None,
+        })
+        .await
+        .unwrap();
+    client.clone().with_token(created.secret)
+}
+
+fn posting(account_name: &str, amount: i32) -> NewPosting {
+    NewPosting {
+        valuta: None,
+        account_name: account_name.to_owned(),
+        currency: "EUR".to_owned(),
+        amount,
+        budget: false,
+    }
+}
+
+/// The code of the error a request was refused with.
+fn code<T>(result: Result<T, ClientError>) -> Option<ErrorCode> {
+    result.err().and_then(|error| error.code())
+}
+
+#[tokio::test]
+async fn books_postings_and_values() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    assert_eq!(client.get_books().await.unwrap(), vec!["Household"]);
+
+    let transaction_id = client
+        .create_transaction(
+            "Household",
+            &NewTransaction {
+                description: Some("Market".to_owned()),
+                time: None,
+                posted: None,
+            },
+        )
+        .await
+        .unwrap();
+    client
+        .create_posting("Household", transaction_id, &posting("Cash", -1250))
+        .await
+        .unwrap();
+    client
+        .create_posting("Household", transaction_id, &posting("Groceries", 1250))
+        .await
+        .unwrap();
+    assert_eq!(
+        client
+            .get_postings("Household", transaction_id)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert_eq!(value.len(), 1);
+    assert_eq!(value[0].amount, Some(-1250));
+
+    let register = client.account_register("Household", "Cash").await.unwrap();
+    assert_eq!(register.len(), 1);
+    assert_eq!(register[0].transaction.id, transaction_id);
+    assert_eq!(register[0].posting.amount, -1250);
+}
+
+#[tokio::test]
+async fn patches_detect_concurrent_changes() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let account = client.get_account("Household", "Cash").await.unwrap();
+
+    let patch = AccountPatch {
+        description: Some(Some("Wallet".to_owned())),
+        ..Default::default()
+    };
+    let etag = client
+        .patch_account("Household", "Cash", &patch, account.etag.as_deref())
+        .await
+        .unwrap();
+    let account = client.get_account("Household", "Cash").await.unwrap();
+    assert_eq!(account.description.as_deref(), Some("Wallet"));
+    assert_eq!(account.etag, etag);
+
+    // The tag read before the first patch is stale now.
+    let stale = client
+        .patch_account("Household", "Cash", &AccountPatch::default(), Some("\"0\""))
+        .await;
+    assert_eq!(
+        stale.err().and_then(|error| error.code()),
+        Some(ErrorCode::PreconditionFailed)
+    );
+
+    // A field patched to null is cleared, not left as it was.
+    let described = BookPatch {
+        description: Some(Some("Shared".to_owned())),
+        ..Default::default()
+    };
+    let cleared = BookPatch {
+        description: Some(None),
+        ..Default::default()
+    };
+    client
+        .patch_book("Household", &described, None)
+        .await
+        .unwrap();
+    client
+        .patch_book("Household", &cleared, None)
+        .await
+        .unwrap();
+    let book = client.get_book("Household").await.unwrap();
+    assert_eq!(book.description, None);
+
+    let described = CurrencyPatch {
+        description: Some(Some("Euro".to_owned())),
+        ..Default::default()
+    };
+    let cleared = CurrencyPatch {
+        description: Some(None),
+        ..Default::default()
+    };
+    client
+        .patch_currency("Household", "EUR", &described, None)
+        .await
+        .unwrap();
+    client
+        .patch_currency("Household", "EUR", &cleared, None)
+        .await
+        .unwrap();
+    let currency = client.get_currency("Household", "EUR").await.unwrap();
+    assert_eq!(currency.description, None);
+
+    let transaction_id = client
+        .create_transaction(
+            "Household",
+            &NewTransaction {
+                description: Some("Market".to_owned()),
+                time: None,
+                posted: None,
+            },
+        )
+        .await
+        .unwrap();
+    let cleared = TransactionPatch {
+        description: Some(None),
+        ..Default::default()
+    };
+    client
+        .patch_transaction("Household", transaction_id, &cleared, None)
+        .await
+        .unwrap();
+    let transaction = client
+        .get_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    assert_eq!(transaction.description, None);
+}
+
+#[tokio::test]
+async fn errors_carry_codes() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+
+    let missing = client.get_account("Household", "Savings").await;
+    assert_eq!(
+        missing.err().and_then(|error| error.code()),
+        Some(ErrorCode::NotFound)
+    );
+
+    let duplicate = client
+        .create_account(
+            "Household",
+            &Account {
+                name: "Cash".to_owned(),
+                description: None,
+            },
+        )
+        .await;
+    assert_eq!(
+        duplicate.err().and_then(|error| error.code()),
+        Some(ErrorCode::AlreadyExists)
+    );
+
+    let duplicate = client
+        .create_book(&Book {
+            name: "Household".to_owned(),
+            description: None,
+            immutable: None,
+            lock_date: None,
+        })
+        .await;
+    assert_eq!(
+        duplicate.err().and_then(|error| error.code()),
+        Some(ErrorCode::AlreadyExists)
+    );
+
+    let euro = Currency {
+        symbol: "EUR".to_owned(),
+        description: None,
+        decimal_points: 2,
+    };
+    let duplicate = client.create_currency("Household", &euro).await;
+    assert_eq!(
+        duplicate.err().and_then(|error| error.code()),
+        Some(ErrorCode::AlreadyExists)
+    );
+    let missing = client.create_currency("Savings", &euro).await;
+    assert_eq!(
+        missing.err().and_then(|error| error.code()),
+        Some(ErrorCode::NotFound)
+    );
+
+    let transaction_id = client
+        .create_transaction(
+            "Household",
+            &NewTransaction {
+                description: None,
+                time: None,
+                posted: None,
+            },
+        )
+        .await
+        .unwrap();
+    match client
+        .create_posting("Household", transaction_id, &posting("Savings", 100))
+        .await
+    {
+        Err(ClientError::Api(error)) => {
+            assert_eq!(error.code, ErrorCode::InvalidInput);
+            assert_eq!(error.details[0].field, "account_name");
+        }
+        _ => panic!("Postings to unknown accounts must be refused."),
+    }
+
+    let mut anonymous = client.clone();
+    anonymous.set_token(None);
+    assert_eq!(
+        anonymous
+            .get_books()
+            .await
+            .err()
+            .and_then(|error| error.code()),
+        Some(ErrorCode::NotAuthenticated)
+    );
+}
+
+/// Sends `body` as JSON to `path` and returns the status and error body it is refused with.
+async fn refusal(
+    client: &FinanceClient,
+    method: reqwest::Method,
+    path: &str,
+    body: &str,
+) -> (reqwest::StatusCode, ApiError) {
+    let response = reqwest::Client::new()
+        .request(method, client.base_url().join(path).unwrap())
+        .bearer_auth(client.token().unwrap())
+        .header("Content-Type", "application/json")
+        .body(body.to_owned())
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    let error = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+    (status, error)
+}
+
+#[tokio::test]
+async fn rejections_carry_codes_too() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let (post, get) = (reqwest::Method::POST, reqwest::Method::GET);
+
+    let (status, error) = refusal(&client, post.clone(), "book/", "{").await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+    let (status, error) = refusal(&client, post.clone(), "book/", r#"{"name": 1}"#).await;
+    assert!(status.is_client_error());
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+    let (status, error) = refusal(&client, get.clone(), "book/Household/transaction/x", "").await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+    let (status, error) = refusal(&client, get, "no/such/route", "").await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(error.code, ErrorCode::NotFound);
+
+    let (status, error) = refusal(&client, post, "book/", r#"{"name": "Household"}"#).await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+    assert_eq!(error.code, ErrorCode::AlreadyExists);
+    assert_eq!(error.message, "Book 'Household' already exists.");
+}
+
+#[tokio::test]
+async fn administrators_manage_users() {
+    let client = serve().await;
+    let (name, member) = create_user(&client, "member").await;
+    assert_eq!(code(member.get_users().await), Some(ErrorCode::Forbidden));
+
+    let id = SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate();
+    let subject = format!("subject-{}", id);
+    let mut user = User {
+        name: name.clone(),
+        admin: false,
+        disabled: false,
+        oidc_subject: Some(subject.clone()),
+    };
+    client.update_user(&name, &user).await.unwrap();
+    let updated = client.get_user(&name).await.unwrap();
+    assert_eq!(updated.oidc_subject, Some(subject));
+    user.oidc_subject = None;
+    client.update_user(&name, &user).await.unwrap();
+    let updated = client.get_user(&name).await.unwrap();
+    assert_eq!(updated.oidc_subject, None);
+
+    user.name = format!("renamed-{}", id);
+    assert_eq!(
+        code(client.update_user(&name, &user).await),
+        Some(ErrorCode::InvalidInput)
+    );
+    user.name = name.clone();
+    user.disabled = true;
+    client.update_user(&name, &user).await.unwrap();
+    assert_eq!(code(member.get_books().await), Some(ErrorCode::Forbidden));
+
+    client.delete_user(&name).await.unwrap();
+    assert_eq!(
+        code(client.get_user(&name).await),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn tokens_are_hashed_revoked_and_expire() {
+    let (client, pool) = serve_pool().await;
+    let (_, other) = create_user(&client, "other").await;
+    let new_token = |name: &str, expires| NewToken {
+        name: name.to_owned(),
+        expires,
+        capability: None,
+        books: None,
+    };
+    let in_an_hour = chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+    let laptop = client
+        .create_token(&new_token("laptop", Some(in_an_hour)))
+        .await
+        .unwrap();
+    assert_eq!(laptop.token.capability, Capability::Admin);
+    let laptop_client = client.clone().with_token(laptop.secret.clone());
+    laptop_client.hello().await.unwrap();
+    // Only the hash of the secret is stored.
+    let token_hash = tokens::table
+        .select(tokens::dsl::token_hash)
+        .filter(tokens::dsl::id.eq(laptop.token.id))
+        .first::<String>(&mut pool.get().unwrap())
+        .unwrap();
+    assert_ne!(token_hash, laptop.secret);
+    assert_eq!(token_hash, auth::hash_token(&laptop.secret));
+
+    let tokens = client.get_tokens().await.unwrap();
+    let listed = tokens
+        .iter()
+        .find(|token| token.id == laptop.token.id)
+        .unwrap();
+    assert!(listed.last_used.is_some());
+    assert!(tokens.len() >= 2);
+
+    let an_hour_ago = chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+    let expired = client
+        .create_token(&new_token("expired", Some(an_hour_ago)))
+        .await
+        .unwrap();
+    assert_eq!(
+        code(client.clone().with_token(expired.secret).hello().await),
+        Some(ErrorCode::NotAuthenticated)
+    );
+
+    // Users revoke their own tokens only.
+    assert_eq!(
+        code(other.revoke_token(laptop.token.id).await),
+        Some(ErrorCode::NotFound)
+    );
+    client.revoke_token(laptop.token.id).await.unwrap();
+    assert_eq!(
+        code(laptop_client.hello().await),
+        Some(ErrorCode::NotAuthenticated)
+    );
+    client.hello().await.unwrap();
+    assert_eq!(
+        code(client.clone().with_token("0".repeat(64)).hello().await),
+        Some(ErrorCode::NotAuthenticated)
+    );
+}
+
+#[tokio::test]
+async fn scoped_tokens_keep_to_their_capability_and_books() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    create_book(&client, "Work").await;
+    let draft = NewTransaction {
+        description: None,
+        time: None,
+        posted: None,
+    };
+    let savings = Account {
+        name: "Savings".to_owned(),
+        description: None,
+    };
+
+    let reader = scoped(&client, Capability::Read).await;
+    reader.get_book("Household").await.unwrap();
+    assert_eq!(
+        code(reader.create_transaction("Household", &draft).await),
+        Some(ErrorCode::Forbidden)
+    );
+    let writer = scoped(&client, Capability::WriteTransactions).await;
+    writer
+        .create_transaction("Household", &draft)
+        .await
+        .unwrap();
+    assert_eq!(
+        code(writer.create_account("Household", &savings).await),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(code(writer.get_tokens().await), Some(ErrorCode::Forbidden));
+
+    let household = client
+        .create_token(&NewToken {
+            name: "household".to_owned(),
+            expires: None,
+            capability: None,
+            books: Some(vec!["Household".to_owned()]),
+        })
+        .await
+        .unwrap();
+    let household = client.clone().with_token(household.secret);
+    household
+        .create_account("Household", &savings)
+        .await
+        .unwrap();
+    assert_eq!(
+        code(household.get_book("Work").await),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(
+        code(household.create_transaction("Work", &draft).await),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(household.get_books().await.unwrap(), vec!["Household"]);
+    // Book-scoped tokens cannot manage the user, or they could mint a token for every book.
+    let all_books = NewToken {
+        name: "all books".to_owned(),
+        expires: None,
+        capability: Some(Capability::Read),
+        books: None,
+    };
+    assert_eq!(
+        code(household.create_token(&all_books).await),
+        Some(ErrorCode::Forbidden)
+    );
+
+    // Scopes name the book, not every book of that name shared later.
+    let (user_name, user) = create_user(&client, "scoped").await;
+    let (other_name, other) = create_user(&client, "other").await;
+    create_book(&user, "Household").await;
+    let household = user
+        .create_token(&NewToken {
+            name: "household".to_owned(),
+            expires: None,
+            capability: None,
+            books: Some(vec!["Household".to_owned()]),
+        })
+        .await
+        .unwrap();
+    let household = user.clone().with_token(household.secret);
+    create_book(&other, "Household").await;
+    create_book(&other, "Private").await;
+    let editor = BookMember {
+        user_name: user_name.clone(),
+        role: BookRole::Editor,
+    };
+    other.add_member("Household", &editor).await.unwrap();
+    household.get_book("Household").await.unwrap();
+    assert_eq!(
+        code(
+            household
+                .clone()
+                .with_owner(&other_name)
+                .get_book("Household")
+                .await
+        ),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(household.get_books().await.unwrap(), vec!["Household"]);
+    // The scope is checked before the membership.
+    assert_eq!(
+        code(household.get_book("Private").await),
+        Some(ErrorCode::Forbidden)
+    );
+}
+
+#[tokio::test]
+async fn passwords_need_the_current_one_and_sessions_expire() {
+    let (client, pool) = serve_pool().await;
+    let (name, user) = create_user(&client, "password").await;
+    let change = |current_password: Option<&str>, password: &str| PasswordChange {
+        current_password: current_password.map(str::to_owned),
+        password: password.to_owned(),
+    };
+    // Users without a password set their first one.
+    user.change_password(&change(None, "first secret"))
+        .await
+        .unwrap();
+    assert_eq!(
+        code(user.change_password(&change(None, "second secret")).await),
+        Some(ErrorCode::InvalidInput)
+    );
+    assert_eq!(
+        code(
+            user.change_password(&change(Some("wrong secret"), "second secret"))
+                .await
+        ),
+        Some(ErrorCode::InvalidInput)
+    );
+    user.change_password(&change(Some("first secret"), "second secret"))
+        .await
+        .unwrap();
+
+    let login = |password: &str| Login {
+        name: name.clone(),
+        password: password.to_owned(),
+    };
+    assert_eq!(
+        code(client.login(&login("first secret")).await),
+        Some(ErrorCode::NotAuthenticated)
+    );
+    let session = client.login(&login("second secret")).await.unwrap();
+    assert!(session.token.expires.is_some());
+    let session_client = client.clone().with_token(session.secret);
+    session_client.hello().await.unwrap();
+    diesel::update(tokens::table)
+        .set(tokens::dsl::expires.eq(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)))
+        .filter(tokens::dsl::id.eq(session.token.id))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(
+        code(session_client.hello().await),
+        Some(ErrorCode::NotAuthenticated)
+    );
+    assert!(auth::purge_expired(&mut pool.get().unwrap()).unwrap() >= 1);
+    let tokens = user.get_tokens().await.unwrap();
+    assert!(tokens.iter().all(|token| token.id != session.token.id));
+}
+
+/// OpenID Connect provider issuing ID tokens for the codes registered with it.
+struct MockIssuer {
+    url: String,
+    key: CoreRsaPrivateSigningKey,
+    /// Subject and nonce of the login each code completes.
+    codes: Mutex<HashMap<String, (String, String)>>,
+}
+
+/// Serves a [`MockIssuer`] on a free port.
+fn serve_issuer() -> Arc<MockIssuer> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = Arc::new(MockIssuer {
+        url: format!("http://{}", listener.local_addr().unwrap()),
+        key: CoreRsaPrivateSigningKey::from_pem(
+            include_str!("../testdata/oidc_issuer_key.pem"),
+            None,
+        )
+        .unwrap(),
+        codes: Mutex::new(HashMap::new()),
+    });
+    let discovery = |State(issuer): State<Arc<MockIssuer>>| async move {
+        let metadata = CoreProviderMetadata::new(
+            IssuerUrl::new(issuer.url.clone()).unwrap(),
+            AuthUrl::new(format!("{}/authorize", issuer.url)).unwrap(),
+            JsonWebKeySetUrl::new(format!("{}/jwks", issuer.url)).unwrap(),
+            vec![ResponseTypes::new(vec![CoreResponseType::Code])],
+            vec![CoreSubjectIdentifierType::Public],
+            vec![CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256],
+            EmptyAdditionalProviderMetadata {},
+        )
+        .set_token_endpoint(Some(
+            TokenUrl::new(format!("{}/token", issuer.url)).unwrap(),
+        ));
+        Json(metadata)
+    };
+    let jwks = |State(issuer): State<Arc<MockIssuer>>| async move {
+        Json(CoreJsonWebKeySet::new(vec![issuer
+            .key
+            .as_verification_key()]))
+    };
+    let token = |State(issuer): State<Arc<MockIssuer>>,
+                 Form(form): Form<HashMap<String, String>>| async move {
+        assert!(form.contains_key("code_verifier"));
+        let (subject, nonce) = issuer.codes.lock().unwrap().remove(&form["code"]).unwrap();
+        let now = chrono::Utc::now();
+        let claims = CoreIdTokenClaims::new(
+            IssuerUrl::new(issuer.url.clone()).unwrap(),
+            vec![Audience::new("finance".to_owned())],
+            now + chrono::Duration::minutes(5),
+            now,
+            StandardClaims::new(SubjectIdentifier::new(subject)),
+            EmptyAdditionalClaims {},
+        )
+        .set_nonce(Some(Nonce::new(nonce)));
+        let id_token = CoreIdToken::new(
+            claims,
+            &issuer.key,
+            CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+            None,
+            None,
+        )
+        .unwrap();
+        Json(CoreTokenResponse::new(
+            AccessToken::new("access".to_owned()),
+            CoreTokenType::Bearer,
+            CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+        ))
+    };
+    let router = axum::Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/token", post(token))
+        .with_state(issuer.clone());
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service()),
+    );
+    issuer
+}
+
+#[tokio::test]
+async fn oidc_logins_issue_sessions_for_linked_users() {
+    let issuer = serve_issuer();
+    let (client, _) = serve_with(login::Oidc::new(Some(login::OidcConfig {
+        issuer_url: IssuerUrl::new(issuer.url.clone()).unwrap(),
+        client_id: ClientId::new("finance".to_owned()),
+        client_secret: Some(ClientSecret::new("secret".to_owned())),
+        redirect_url: RedirectUrl::new("http://localhost/callback".to_owned()).unwrap(),
+    })))
+    .await;
+    let (name, _) = create_user(&client, "oidc").await;
+    let linked = User {
+        name: name.clone(),
+        admin: false,
+        disabled: false,
+        oidc_subject: Some(name.clone()),
+    };
+    client.update_user(&name, &linked).await.unwrap();
+
+    // Starts a login, lets the provider complete it for `subject` and returns the callback.
+    let http = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let base_url = client.base_url().clone();
+    let log_in = |subject: String| {
+        let (http, base_url, issuer) = (http.clone(), base_url.clone(), issuer.clone());
+        async move {
+            let redirect = http
+                .get(base_url.join("login/oidc").unwrap())
+                .send()
+                .await
+                .unwrap();
+            let location = redirect.headers()["location"].to_str().unwrap();
+            let location = reqwest::Url::parse(location).unwrap();
+            let query: HashMap<_, _> = location.query_pairs().into_owned().collect();
+            assert!(location.as_str().starts_with(&issuer.url));
+            let code = SNOWFLAKE_GENERATOR
+                .lock()
+                .unwrap()
+                .real_time_generate()
+                .to_string();
+            issuer
+                .codes
+                .lock()
+                .unwrap()
+                .insert(code.clone(), (subject, query["nonce"].clone()));
+            let mut callback = base_url.join("login/oidc/callback").unwrap();
+            callback
+                .query_pairs_mut()
+                .append_pair("code", &code)
+                .append_pair("state", &query["state"]);
+            (http, callback)
+        }
+    };
+
+    let (http, callback) = log_in(name.clone()).await;
+    let response = http.get(callback.clone()).send().await.unwrap();
+    assert!(response.status().is_success());
+    let created: CreatedToken = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+    let session = client.clone().with_token(created.secret);
+    assert_eq!(session.hello().await.unwrap(), format!("Hello {}!", name));
+    // Each login is completed once.
+    let replayed = http.get(callback).send().await.unwrap();
+    assert_eq!(replayed.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let (http, callback) = log_in("unknown".to_owned()).await;
+    let response = http.get(callback).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn members_act_within_their_role() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let (viewer_name, viewer) = create_user(&client, "viewer").await;
+    let (editor_name, editor) = create_user(&client, "editor").await;
+    let (_, stranger) = create_user(&client, "stranger").await;
+    let member = |user_name: &String, role| BookMember {
+        user_name: user_name.clone(),
+        role,
+    };
+    client
+        .add_member("Household", &member(&viewer_name, BookRole::Viewer))
+        .await
+        .unwrap();
+    client
+        .add_member("Household", &member(&editor_name, BookRole::Viewer))
+        .await
+        .unwrap();
+    assert_eq!(
+        code(
+            client
+                .add_member("Household", &member(&editor_name, BookRole::Editor))
+                .await
+        ),
+        Some(ErrorCode::AlreadyExists)
+    );
+    client
+        .update_member(
+            "Household",
+            &editor_name,
+            &member(&editor_name, BookRole::Editor),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(viewer.get_books().await.unwrap(), vec!["Household"]);
+    assert_eq!(viewer.get_accounts("Household").await.unwrap().len(), 2);
+    let draft = NewTransaction {
+        description: None,
+        time: None,
+        posted: None,
+    };
+    assert_eq!(
+        code(viewer.create_transaction("Household", &draft).await),
+        Some(ErrorCode::Forbidden)
+    );
+    // What editors book lands in the book of the owner.
+    let transaction_id = book_purchase(&editor, "Household").await;
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert_eq!(value[0].amount, Some(-1250));
+    editor
+        .get_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    let (other_name, _) = create_user(&client, "other").await;
+    assert_eq!(
+        code(
+            editor
+                .add_member("Household", &member(&other_name, BookRole::Viewer))
+                .await
+        ),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(
+        code(
+            editor
+                .update_member(
+                    "Household",
+                    &viewer_name,
+                    &member(&viewer_name, BookRole::Editor)
+                )
+                .await
+        ),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(
+        code(stranger.get_book("Household").await),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn owners_tell_books_of_the_same_name_apart() {
+    let client = serve().await;
+    let (alice_name, alice) = create_user(&client, "alice").await;
+    let (bob_name, bob) = create_user(&client, "bob").await;
+    let (carol_name, carol) = create_user(&client, "carol").await;
+    create_book(&alice, "Household").await;
+    create_book(&bob, "Household").await;
+    let editor = |user_name: &String| BookMember {
+        user_name: user_name.clone(),
+        role: BookRole::Editor,
+    };
+    // Owning a book of that name does not keep alice out of the one of bob.
+    bob.add_member("Household", &editor(&alice_name))
+        .await
+        .unwrap();
+    for owner in [&alice, &bob] {
+        owner
+            .add_member("Household", &editor(&carol_name))
+            .await
+            .unwrap();
+    }
+    let transactions = |client: FinanceClient| async move {
+        client
+            .get_transactions("Household", Page::default())
+            .await
+            .map(|transactions| transactions.len())
+    };
+
+    // Their own book comes first, the other one is picked by its owner.
+    book_purchase(&alice, "Household").await;
+    book_purchase(&alice.clone().with_owner(&bob_name), "Household").await;
+    book_purchase(&alice.clone().with_owner(&bob_name), "Household").await;
+    assert_eq!(transactions(alice.clone()).await.unwrap(), 1);
+    assert_eq!(transactions(bob.clone()).await.unwrap(), 2);
+    // Without one of her own, carol has to name the owner.
+    assert_eq!(
+        code(transactions(carol.clone()).await),
+        Some(ErrorCode::InvalidInput)
+    );
+    let of_alice = carol.clone().with_owner(&alice_name);
+    assert_eq!(transactions(of_alice.clone()).await.unwrap(), 1);
+    let mut members: Vec<_> = of_alice
+        .get_members("Household")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|member| member.user_name)
+        .collect();
+    members.sort();
+    assert_eq!(members, [alice_name.clone(), carol_name.clone()]);
+
+    // Leaving one book keeps the membership in the other.
+    of_alice.leave_book("Household").await.unwrap();
+    assert_eq!(transactions(carol.clone()).await.unwrap(), 2);
+    assert_eq!(
+        code(transactions(carol.with_owner(&alice_name)).await),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn only_owners_remove_members() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let (viewer_name, viewer) = create_user(&client, "viewer").await;
+    let (editor_name, _) = create_user(&client, "editor").await;
+    for (user_name, role) in [
+        (&viewer_name, BookRole::Viewer),
+        (&editor_name, BookRole::Editor),
+    ] {
+        let member = BookMember {
+            user_name: user_name.clone(),
+            role,
+        };
+        client.add_member("Household", &member).await.unwrap();
+    }
+
+    let reader = scoped(&client, Capability::Read).await;
+    assert_eq!(
+        code(reader.remove_member("Household", &editor_name).await),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(
+        code(viewer.remove_member("Household", &editor_name).await),
+        Some(ErrorCode::Forbidden)
+    );
+    let viewer_reader = scoped(&viewer, Capability::Read).await;
+    assert_eq!(
+        code(viewer_reader.leave_book("Household").await),
+        Some(ErrorCode::Forbidden)
+    );
+    assert_eq!(
+        code(client.leave_book("Household").await),
+        Some(ErrorCode::InvalidInput)
+    );
+
+    viewer.leave_book("Household").await.unwrap();
+    client
+        .remove_member("Household", &editor_name)
+        .await
+        .unwrap();
+    let members = client.get_members("Household").await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].role, BookRole::Owner);
+    assert_eq!(
+        code(viewer.get_book("Household").await),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn immutable_books_only_take_reversals() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let immutable = BookPatch {
+        immutable: Some(true),
+        ..Default::default()
+    };
+    client
+        .patch_book("Household", &immutable, None)
+        .await
+        .unwrap();
+
+    let draft = NewTransaction {
+        description: Some("Market".to_owned()),
+        time: None,
+        posted: None,
+    };
+    let transaction_id = client
+        .create_transaction("Household", &draft)
+        .await
+        .unwrap();
+    client
+        .create_posting("Household", transaction_id, &posting("Cash", -1250))
+        .await
+        .unwrap();
+    client
+        .create_posting("Household", transaction_id, &posting("Groceries", 1250))
+        .await
+        .unwrap();
+    assert_eq!(
+        code(
+            client
+                .reverse_transaction("Household", transaction_id)
+                .await
+        ),
+        Some(ErrorCode::InvalidInput)
+    );
+
+    client
+        .post_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    let patch = TransactionPatch {
+        description: Some(Some("Bakery".to_owned())),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(
+            client
+                .patch_transaction("Household", transaction_id, &patch, None)
+                .await
+        ),
+        Some(ErrorCode::TransactionFinal)
+    );
+    assert_eq!(
+        code(client.delete_transaction("Household", transaction_id).await),
+        Some(ErrorCode::TransactionFinal)
+    );
+    assert_eq!(
+        code(
+            client
+                .create_posting("Household", transaction_id, &posting("Cash", 1))
+                .await
+        ),
+        Some(ErrorCode::TransactionFinal)
+    );
+
+    let reversal_id = client
+        .reverse_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    let reversal = client
+        .get_transaction("Household", reversal_id)
+        .await
+        .unwrap();
+    assert_eq!(reversal.posted, Some(true));
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert_eq!(value[0].amount, Some(0));
+    assert_eq!(
+        code(
+            client
+                .reverse_transaction("Household", transaction_id)
+                .await
+        ),
+        Some(ErrorCode::Conflict)
+    );
+    let mutable = BookPatch {
+        immutable: Some(false),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(client.patch_book("Household", &mutable, None).await),
+        Some(ErrorCode::InvalidInput)
+    );
+}
+
+/// Books a transaction moving 12.50 from Cash to Groceries and returns its id.
+async fn book_purchase(client: &FinanceClient, book_name: &str) -> i64 {
+    let transaction_id = client
+        .create_transaction(
+            book_name,
+            &NewTransaction {
+                description: Some("Market".to_owned()),
+                time: None,
+                posted: None,
+            },
+        )
+        .await
+        .unwrap();
+    for (account_name, amount) in [("Cash", -1250), ("Groceries", 1250)] {
+        client
+            .create_posting(book_name, transaction_id, &posting(account_name, amount))
+            .await
+            .unwrap();
+    }
+    transaction_id
+}
+
+#[tokio::test]
+async fn closed_periods_refuse_changes() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    for name in ["Equity", "Groceries:Bakery"] {
+        let account = Account {
+            name: name.to_owned(),
+            description: None,
+        };
+        client.create_account("Household", &account).await.unwrap();
+    }
+    let day = |month, day| {
+        chrono::NaiveDate::from_ymd_opt(2024, month, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    };
+    let dated = |time| NewTransaction {
+        description: Some("Market".to_owned()),
+        time: Some(time),
+        posted: None,
+    };
+    let january = client
+        .create_transaction("Household", &dated(day(1, 10)))
+        .await
+        .unwrap();
+    for (account_name, amount) in [
+        ("Cash", -1250),
+        ("Groceries", 1000),
+        ("Groceries:Bakery", 250),
+    ] {
+        client
+            .create_posting("Household", january, &posting(account_name, amount))
+            .await
+            .unwrap();
+    }
+
+    let mut close = PeriodClose {
+        lock_date: day(2, 1),
+        retained_earnings: Some("Savings".to_owned()),
+        closing_accounts: vec!["Groceries".to_owned()],
+    };
+    assert_eq!(
+        code(client.close_period("Household", &close).await),
+        Some(ErrorCode::InvalidInput)
+    );
+    close.retained_earnings = Some("Equity".to_owned());
+    let closing = client
+        .close_period("Household", &close)
+        .await
+        .unwrap()
+        .unwrap();
+    let closing = client.get_transaction("Household", closing).await.unwrap();
+    assert_eq!(closing.time, Some(day(2, 1) - chrono::Duration::seconds(1)));
+    assert_eq!(closing.posted, Some(true));
+    // Accounts below a closing account are closed with it.
+    for account_name in ["Groceries", "Groceries:Bakery"] {
+        let value = client
+            .real_account_value("Household", account_name)
+            .await
+            .unwrap();
+        assert_eq!(value[0].amount, Some(0));
+    }
+    let value = client.account_value("Household", "Equity").await.unwrap();
+    assert_eq!(value[0].amount, Some(1250));
+    let book = client.get_book("Household").await.unwrap();
+    assert_eq!(book.lock_date, Some(day(2, 1)));
+
+    let closed = Some(ErrorCode::PeriodClosed);
+    let renamed = TransactionPatch {
+        description: Some(Some("Bakery".to_owned())),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(
+            client
+                .patch_transaction("Household", january, &renamed, None)
+                .await
+        ),
+        closed
+    );
+    assert_eq!(
+        code(client.delete_transaction("Household", january).await),
+        closed
+    );
+    assert_eq!(
+        code(
+            client
+                .create_posting("Household", january, &posting("Cash", 1))
+                .await
+        ),
+        closed
+    );
+    assert_eq!(
+        code(
+            client
+                .create_transaction("Household", &dated(day(1, 20)))
+                .await
+        ),
+        closed
+    );
+    // Later transactions stay open, but cannot be moved into the closed period.
+    let march = client
+        .create_transaction("Household", &dated(day(3, 1)))
+        .await
+        .unwrap();
+    let backdated = TransactionPatch {
+        time: Some(day(1, 20)),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(
+            client
+                .patch_transaction("Household", march, &backdated, None)
+                .await
+        ),
+        closed
+    );
+    client
+        .patch_transaction("Household", march, &renamed, None)
+        .await
+        .unwrap();
+
+    // Periods are not reopened.
+    close.lock_date = day(1, 1);
+    close.retained_earnings = None;
+    assert_eq!(
+        code(client.close_period("Household", &close).await),
+        Some(ErrorCode::Conflict)
+    );
+}
+
+#[tokio::test]
+async fn used_accounts_and_currencies_are_kept() {
+    let (client, pool) = serve_pool().await;
+    create_book(&client, "Household").await;
+    let transaction_id = book_purchase(&client, "Household").await;
+
+    let unknown = client
+        .create_posting("Household", transaction_id, &posting("Savings", 1))
+        .await;
+    assert_eq!(code(unknown), Some(ErrorCode::InvalidInput));
+    let keep = DeleteOptions::default();
+    assert_eq!(
+        code(client.delete_account("Household", "Cash", &keep).await),
+        Some(ErrorCode::InUse)
+    );
+    assert_eq!(
+        code(client.delete_currency("Household", "EUR", &keep).await),
+        Some(ErrorCode::InUse)
+    );
+    // Postings in the trash still use them.
+    client
+        .delete_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        code(client.delete_account("Household", "Cash", &keep).await),
+        Some(ErrorCode::InUse)
+    );
+
+    // Books and users with postings are removed for good all the same.
+    let (owner_name, owner) = create_user(&client, "owner").await;
+    create_book(&owner, "Household").await;
+    book_purchase(&owner, "Household").await;
+    client.delete_user(&owner_name).await.unwrap();
+    book_purchase(&client, "Household").await;
+    client.delete_book("Household").await.unwrap();
+    diesel::update(books::table)
+        .set(
+            books::dsl::deleted_at.eq(chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)),
+        )
+        .filter(
+            books::dsl::name
+                .eq("Household")
+                .and(books::dsl::deleted_at.is_not_null()),
+        )
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    trash::purge(&mut pool.get().unwrap(), chrono::Duration::days(365)).unwrap();
+    assert!(client.get_book_trash().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn renames_move_postings() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let transaction_id = book_purchase(&client, "Household").await;
+
+    let wallet = AccountPatch {
+        name: Some("Wallet".to_owned()),
+        ..Default::default()
+    };
+    client
+        .patch_account("Household", "Cash", &wallet, None)
+        .await
+        .unwrap();
+    let euro = CurrencyPatch {
+        symbol: Some("EURO".to_owned()),
+        ..Default::default()
+    };
+    client
+        .patch_currency("Household", "EUR", &euro, None)
+        .await
+        .unwrap();
+    let home = BookPatch {
+        name: Some("Home".to_owned()),
+        ..Default::default()
+    };
+    client.patch_book("Household", &home, None).await.unwrap();
+    assert_eq!(
+        code(client.get_book("Household").await),
+        Some(ErrorCode::NotFound)
+    );
+
+    let mut postings = vec![];
+    for posting_id in client.get_postings("Home", transaction_id).await.unwrap() {
+        let posting = client
+            .get_posting("Home", transaction_id, posting_id)
+            .await
+            .unwrap();
+        postings.push(posting.value);
+    }
+    assert!(postings.iter().all(|posting| posting.currency == "EURO"));
+    let cash = postings
+        .iter()
+        .find(|posting| posting.amount == -1250)
+        .unwrap();
+    assert_eq!(cash.account_name, "Wallet");
+    // The postings record the renames of their account and currency.
+    let log = client
+        .get_entity_audit_log("Home", AuditEntity::Posting, &cash.id.to_string())
+        .await
+        .unwrap();
+    assert_eq!(log.len(), 3);
+
+    client
+        .merge_account(
+            "Home",
+            "Groceries",
+            &Merge {
+                into: "Wallet".to_owned(),
+            },
+        )
+        .await
+        .unwrap();
+    let value = client.account_value("Home", "Wallet").await.unwrap();
+    assert_eq!(value[0].amount, Some(0));
+    assert_eq!(client.get_accounts("Home").await.unwrap(), vec!["Wallet"]);
+}
+
+#[tokio::test]
+async fn merges_check_their_target() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    for (symbol, decimal_points) in [("USD", 2), ("JPY", 0)] {
+        let currency = Currency {
+            symbol: symbol.to_owned(),
+            description: None,
+            decimal_points,
+        };
+        client
+            .create_currency("Household", &currency)
+            .await
+            .unwrap();
+    }
+    let transaction_id = book_purchase(&client, "Household").await;
+    let into = |target: &str| Merge {
+        into: target.to_owned(),
+    };
+
+    for target in ["Cash", "Savings"] {
+        assert_eq!(
+            code(
+                client
+                    .merge_account("Household", "Cash", &into(target))
+                    .await
+            ),
+            Some(ErrorCode::InvalidInput)
+        );
+    }
+    assert_eq!(
+        code(
+            client
+                .merge_currency("Household", "EUR", &into("JPY"))
+                .await
+        ),
+        Some(ErrorCode::InvalidInput)
+    );
+    let to_groceries = AccountPatch {
+        name: Some("Groceries".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(
+            client
+                .patch_account("Household", "Cash", &to_groceries, None)
+                .await
+        ),
+        Some(ErrorCode::AlreadyExists)
+    );
+
+    client
+        .merge_currency("Household", "EUR", &into("USD"))
+        .await
+        .unwrap();
+    let mut currencies = client.get_currencies("Household").await.unwrap();
+    currencies.sort();
+    assert_eq!(currencies, vec!["JPY", "USD"]);
+    for posting_id in client
+        .get_postings("Household", transaction_id)
+        .await
+        .unwrap()
+    {
+        let posting = client
+            .get_posting("Household", transaction_id, posting_id)
+            .await
+            .unwrap();
+        assert_eq!(posting.currency, "USD");
+    }
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert_eq!(value[0].currency, "USD");
+    assert_eq!(value[0].amount, Some(-1250));
+}
+
+#[tokio::test]
+async fn trashed_names_are_held_until_restored() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let keep = DeleteOptions::default();
+    client
+        .delete_account("Household", "Cash", &keep)
+        .await
+        .unwrap();
+    client
+        .delete_currency("Household", "EUR", &keep)
+        .await
+        .unwrap();
+    let cash = Account {
+        name: "Cash".to_owned(),
+        description: None,
+    };
+    assert_eq!(
+        code(client.create_account("Household", &cash).await),
+        Some(ErrorCode::Conflict)
+    );
+    let to_cash = AccountPatch {
+        name: Some("Cash".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(
+            client
+                .patch_account("Household", "Groceries", &to_cash, None)
+                .await
+        ),
+        Some(ErrorCode::Conflict)
+    );
+    let euro = Currency {
+        symbol: "EUR".to_owned(),
+        description: None,
+        decimal_points: 2,
+    };
+    assert_eq!(
+        code(client.create_currency("Household", &euro).await),
+        Some(ErrorCode::Conflict)
+    );
+    // Names still in use are told apart from those in the trash.
+    assert_eq!(
+        code(
+            client
+                .create_account(
+                    "Household",
+                    &Account {
+                        name: "Groceries".to_owned(),
+                        description: None,
+                    },
+                )
+                .await
+        ),
+        Some(ErrorCode::AlreadyExists)
+    );
+
+    let trash = client.get_trash("Household").await.unwrap();
+    assert_eq!(trash.len(), 2);
+    client
+        .restore("Household", AuditEntity::Account, "Cash")
+        .await
+        .unwrap();
+    client
+        .restore("Household", AuditEntity::Currency, "EUR")
+        .await
+        .unwrap();
+    assert!(client.get_trash("Household").await.unwrap().is_empty());
+    book_purchase(&client, "Household").await;
+
+    client.delete_book("Household").await.unwrap();
+    let book = Book {
+        name: "Household".to_owned(),
+        description: None,
+        immutable: None,
+        lock_date: None,
+    };
+    assert_eq!(
+        code(client.create_book(&book).await),
+        Some(ErrorCode::Conflict)
+    );
+    assert_eq!(
+        code(client.get_book("Household").await),
+        Some(ErrorCode::NotFound)
+    );
+    client.restore_book("Household").await.unwrap();
+    let mut accounts = client.get_accounts("Household").await.unwrap();
+    accounts.sort();
+    assert_eq!(accounts, vec!["Cash", "Groceries"]);
+}
+
+#[tokio::test]
+async fn deleted_transactions_come_back_with_their_postings() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let transaction_id = book_purchase(&client, "Household").await;
+    client
+        .delete_transaction("Household", transaction_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        code(client.get_transaction("Household", transaction_id).await),
+        Some(ErrorCode::NotFound)
+    );
+    assert!(client
+        .get_transactions("Household", Page::default())
+        .await
+        .unwrap()
+        .is_empty());
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert!(value.iter().all(|amount| amount.amount.unwrap_or(0) == 0));
+    let trash = client.get_trash("Household").await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].entity, AuditEntity::Transaction);
+    assert_eq!(trash[0].entity_id, transaction_id.to_string());
+
+    client
+        .restore("Household", AuditEntity::Transaction, &trash[0].entity_id)
+        .await
+        .unwrap();
+    assert!(client.get_trash("Household").await.unwrap().is_empty());
+    assert_eq!(
+        client
+            .get_postings("Household", transaction_id)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    let value = client.account_value("Household", "Cash").await.unwrap();
+    assert_eq!(value[0].amount, Some(-1250));
+    assert_eq!(
+        code(
+            client
+                .restore("Household", AuditEntity::Transaction, &trash[0].entity_id)
+                .await
+        ),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn audit_log_is_append_only() {
+    let (client, pool) = serve_pool().await;
+    create_book(&client, "Household").await;
+    book_purchase(&client, "Household").await;
+    let log = client.all_audit_entries("Household", 100).await.unwrap();
+    let verification = client.verify_audit_log("Household").await.unwrap();
+    assert_eq!(verification.entries, log.len());
+    assert_eq!(verification.first_invalid_entry, None);
+
+    // With SQLite the pool has one connection, which the router needs back before the next request.
+    let mut pooled = pool.get().unwrap();
+    let conn = &mut *pooled;
+    let first = audit_log::table.filter(audit_log::dsl::id.eq(log[0].id));
+    let changed = diesel::update(first)
+        .set(audit_log::dsl::actor.eq("someone else"))
+        .execute(conn);
+    assert!(changed.is_err());
+    assert!(diesel::delete(first).execute(conn).is_err());
+
+    // Entries can only be added, a forged one breaks the chain.
+    let mut forged = first.first::<model::AuditLogEntry>(conn).unwrap();
+    forged.id = SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate();
+    forged.previous_hash = Some(log.last().unwrap().hash.clone());
+    diesel::insert_into(audit_log::table)
+        .values(&forged)
+        .execute(conn)
+        .unwrap();
+    drop(pooled);
+    let verification = client.verify_audit_log("Household").await.unwrap();
+    assert_eq!(verification.first_invalid_entry, Some(forged.id));
+
+    // The log stays with a renamed book, a new book of the old name starts its own.
+    let home = BookPatch {
+        name: Some("Home".to_owned()),
+        ..Default::default()
+    };
+    client.patch_book("Household", &home, None).await.unwrap();
+    assert_eq!(
+        client.verify_audit_log("Home").await.unwrap().entries,
+        log.len() + 2
+    );
+    create_book(&client, "Household").await;
+    let verification = client.verify_audit_log("Household").await.unwrap();
+    assert_eq!(verification.entries, 4);
+    assert_eq!(verification.first_invalid_entry, None);
+}
+
+/// `create_book` and `create_currency` tell duplicates and missing books apart by the kind of
+/// the database error, which every backend has to report alike.
+#[test]
+fn constraint_violations_keep_their_kind() {
+    let pool = pool();
+    let user_name = format!(
+        "test-{}",
+        SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate()
+    );
+    admin::create_admin(&pool, &user_name).unwrap();
+    let conn = &mut pool.get().unwrap();
+    let kind = |result: QueryResult<usize>| match result {
+        Err(diesel::result::Error::DatabaseError(kind, _)) => Some(kind),
+        _ => None,
+    };
+
+    let book = model::Book::from_user_struct(
+        &Book {
+            name: "Household".to_owned(),
+            description: None,
+            immutable: None,
+            lock_date: None,
+        },
+        model::AddedInformationForBook {
+            user_name: &user_name,
+        },
+    );
+    diesel::insert_into(books::table)
+        .values(&book)
+        .execute(conn)
+        .unwrap();
+    let duplicate = diesel::insert_into(books::table)
+        .values(&book)
+        .execute(conn);
+    assert_eq!(kind(duplicate), Some(DatabaseErrorKind::UniqueViolation));
+
+    let euro = |book_name| {
+        model::Currency::from_user_struct(
+            &Currency {
+                symbol: "EUR".to_owned(),
+                description: None,
+                decimal_points: 2,
+            },
+            model::UserAndBookInfo {
+                user_name: &user_name,
+                book_name,
+            },
+        )
+    };
+    let household = euro(&book.name);
+    diesel::insert_into(currencies::table)
+        .values(&household)
+        .execute(conn)
+        .unwrap();
+    let duplicate = diesel::insert_into(currencies::table)
+        .values(&household)
+        .execute(conn);
+    assert_eq!(kind(duplicate), Some(DatabaseErrorKind::UniqueViolation));
+    let missing = diesel::insert_into(currencies::table)
+        .values(&euro(&"Savings".to_owned()))
+        .execute(conn);
+    assert_eq!(kind(missing), Some(DatabaseErrorKind::ForeignKeyViolation));
+
+    let transaction = model::Transaction::from_new_user_struct(
+        &NewTransaction {
+            description: None,
+            time: None,
+            posted: None,
+        },
+        model::UserAndBookInfo {
+            user_name: &user_name,
+            book_name: &book.name,
+        },
+    )
+    .unwrap();
+    diesel::insert_into(transactions::table)
+        .values(&transaction)
+        .execute(conn)
+        .unwrap();
+    let unknown_account = model::Posting {
+        id: SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate(),
+        transaction_id: transaction.id,
+        valuta: None,
+        book_name: book.name.clone(),
+        user_name: user_name.clone(),
+        account_name: "Savings".to_owned(),
+        currency: "EUR".to_owned(),
+        amount: 1250,
+        budget: false,
+    };
+    let missing = diesel::insert_into(postings::table)
+        .values(&unknown_account)
+        .execute(conn);
+    assert_eq!(kind(missing), Some(DatabaseErrorKind::ForeignKeyViolation));
+}
+
+/// Authenticates `secret` the way a request outside of a book would.
+async fn claim<C: RequiredCapability>(pool: &ConnectionPool, secret: &str) -> Claim<C> {
+    let request = axum::http::Request::builder()
+        .header("Authorization", format!("Bearer {}", secret))
+        .body(())
+        .unwrap();
+    let (mut parts, _) = request.into_parts();
+    match Claim::from_request_parts(&mut parts, pool).await {
+        Ok(claim) => claim,
+        Err(_) => panic!("the token was refused"),
+    }
+}
+
+/// The code of the error a handler responded with.
+async fn response_code(response: Result<Response, Response>) -> ErrorCode {
+    let body = hyper::body::to_bytes(response.unwrap_err().into_body())
+        .await
+        .unwrap();
+    serde_json::from_slice::<ApiError>(&body).unwrap().code
+}
+
+/// Calls the handlers directly, the extractors would refuse the requests before they get to the
+/// database.
+#[tokio::test]
+async fn handlers_map_constraint_violations() {
+    let pool = pool();
+    let user_name = format!(
+        "test-{}",
+        SNOWFLAKE_GENERATOR.lock().unwrap().real_time_generate()
+    );
+    let created = admin::create_admin(&pool, &user_name).unwrap();
+    let book = || {
+        Json(Book {
+            name: "Household".to_owned(),
+            description: None,
+            immutable: None,
+            lock_date: None,
+        })
+    };
+    let claimed = claim(&pool, &created.secret).await;
+    crate::create_book(claimed, State(pool.clone()), book())
+        .await
+        .unwrap();
+    let claimed = claim(&pool, &created.secret).await;
+    let duplicate = crate::create_book(claimed, State(pool.clone()), book()).await;
+    assert_eq!(response_code(duplicate).await, ErrorCode::AlreadyExists);
+
+    let euro = || {
+        Json(Currency {
+            symbol: "EUR".to_owned(),
+            description: None,
+            decimal_points: 2,
+        })
+    };
+    let household = || Path("Household".to_owned());
+    let claimed = claim(&pool, &created.secret).await;
+    crate::create_currency(claimed, State(pool.clone()), household(), euro())
+        .await
+        .unwrap();
+    let claimed = claim(&pool, &created.secret).await;
+    let duplicate = crate::create_currency(claimed, State(pool.clone()), household(), euro()).await;
+    assert_eq!(response_code(duplicate).await, ErrorCode::AlreadyExists);
+    let claimed = claim(&pool, &created.secret).await;
+    let missing = crate::create_currency(
+        claimed,
+        State(pool.clone()),
+        Path("Savings".to_owned()),
+        euro(),
+    )
+    .await;
+    assert_eq!(response_code(missing).await, ErrorCode::NotFound);
+
+    // The user disappears between authentication and the insert.
+    let claimed = claim(&pool, &created.secret).await;
+    diesel::delete(users::table.filter(users::dsl::name.eq(&user_name)))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    let orphan = crate::create_book(claimed, State(pool.clone()), book()).await;
+    assert_eq!(response_code(orphan).await, ErrorCode::NotFound);
+}
+
+#[tokio::test]
+async fn transactions_are_paged() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let mut created = vec![];
+    for _ in 0..5 {
+        let transaction_id = client
+            .create_transaction(
+                "Household",
+                &NewTransaction {
+                    description: None,
+                    time: None,
+                    posted: None,
+                },
+            )
+            .await
+            .unwrap();
+        created.push(transaction_id);
+    }
+
+    let first = client
+        .get_transactions(
+            "Household",
+            Page {
+                offset: None,
+                limit: Some(2),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(first, created[..2]);
+    assert_eq!(
+        client.all_transactions("Household", 2).await.unwrap(),
+        created
+    );
+}
+
+#[tokio::test]
+async fn retried_creates_are_idempotent() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let transaction = NewTransaction {
+        description: Some("Receipt".to_owned()),
+        time: None,
+        posted: None,
+    };
+    let first = client
+        .create_transaction_once("Household", &transaction, "receipt-1")
+        .await
+        .unwrap();
+    let retried = client
+        .create_transaction_once("Household", &transaction, "receipt-1")
+        .await
+        .unwrap();
+    assert_eq!(first, retried);
+    assert_eq!(
+        client.all_transactions("Household", 10).await.unwrap(),
+        vec![first]
+    );
+
+    let posting = posting("Cash", -500);
+    let posting_id = client
+        .create_posting_once("Household", first, &posting, "receipt-1-0")
+        .await
+        .unwrap();
+    let retried = client
+        .create_posting_once("Household", first, &posting, "receipt-1-0")
+        .await
+        .unwrap();
+    assert_eq!(posting_id, retried);
+
+    let other = NewTransaction {
+        description: Some("Another receipt".to_owned()),
+        ..transaction
+    };
+    match client
+        .create_transaction_once("Household", &other, "receipt-1")
+        .await
+    {
+        Err(ClientError::Api(error)) => assert_eq!(error.code, ErrorCode::Conflict),
+        _ => panic!("Reusing a key for a different request must be refused."),
+    }
+}
+
+#[tokio::test]
+async fn reports_sum_up_periods() {
+    let client = serve().await;
+    create_book(&client, "Household").await;
+    let date = |month, day| {
+        chrono::NaiveDate::from_ymd_opt(2023, month, day)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    };
+    for name in ["Cash:Wallet", "cash:Tips"] {
+        let account = Account {
+            name: name.to_owned(),
+            description: None,
+        };
+        client.create_account("Household", &account).await.unwrap();
+    }
+    // Before the report, counted into the opening balance of "Cash" but for "cash:Tips".
+    let december = chrono::NaiveDate::from_ymd_opt(2022, 12, 24)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    for (time, postings) in [
+        (december, [("Cash:Wallet", -200), ("cash:Tips", 200)]),
+        (date(1, 15), [("Cash", -1000), ("Groceries", 1000)]),
+        (date(2, 10), [("Cash", -500), ("Groceries", 500)]),
+    ] {
+        let transaction_id = client
+            .create_transaction(
+                "Household",
+                &NewTransaction {
+                    description: None,
+                    time: Some(time),
+                    posted: None,
+                },
+            )
+            .await
+            .unwrap();
+        for (account_name, amount) in postings {
+            let posting = posting(account_name, amount);
+            client
+                .create_posting("Household", transaction_id, &posting)
+                .await
+                .unwrap();
+        }
+    }
+    let query = ReportQuery {
+        from: Some(date(1, 1)),
+        to: Some(date(2, 28)),
+        account: Some("Cash".to_owned()),
+        ..Default::default()
+    };
+
+    let balances = client.balance_report("Household", &query).await.unwrap();
+    let balances: Vec<i64> = balances.iter().map(|point| point.balance).collect();
+    assert_eq!(balances, vec![-1200, -1700]);
+
+    let query = ReportQuery {
+        account: None,
+        ..query
+    };
+    let flows = client.flow_report("Household", &query).await.unwrap();
+    let flows: Vec<(&str, i64)> = flows
+        .iter()
+        .map(|flow| (flow.account_name.as_str(), flow.amount))
+        .collect();
+    assert_eq!(
+        flows,
+        vec![
+            ("Cash", -1000),
+            ("Groceries", 1000),
+            ("Cash", -500),
+            ("Groceries", 500)
+        ]
+    );
+
+    let query = ReportQuery {
+        depth: Some(0),
+        ..query
+    };
+    match client.flow_report("Household", &query).await {
+        Err(ClientError::Api(error)) => assert_eq!(error.code, ErrorCode::InvalidInput),
+        _ => panic!("Flows summed up by no levels must be refused."),
+    }
+}
+
+#[test]
+fn openapi_documents_handlers() {
+    let document = api().document();
+    let patch = &document["paths"]
+        ["/book/{book_name}/transaction/{transaction_id}/posting/{posting_id}"]["patch"];
+    assert_eq!(patch["operationId"], "patch_posting");
+    let parameters: Vec<_> = patch["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|parameter| parameter["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        parameters,
+        [
+            "book_name",
+            "transaction_id",
+            "posting_id",
+            "owner",
+            "If-Match"
+        ]
+    );
+    assert_eq!(
+        patch["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/PostingPatch"
+    );
+    assert_eq!(
+        document["components"]["schemas"]["PostingPatch"]["required"],
+        serde_json::json!([])
+    );
+    assert!(document["paths"]["/login"]["post"]["security"].is_null());
+
+    // Fields serde leaves out or writes as null match the schemas.
+    let schema = |name: &str| json!({ "$ref": format!("#/components/schemas/{}", name) });
+    let error = ApiError {
+        code: ErrorCode::NotFound,
+        message: "Gone.".to_owned(),
+        details: vec![],
+    };
+    conforms(&document, &schema("ApiError"), &json!(error)).unwrap();
+    let patch = PostingPatch {
+        valuta: Some(None),
+        amount: Some(5),
+        ..Default::default()
+    };
+    conforms(&document, &schema("PostingPatch"), &json!(patch)).unwrap();
+    let book = Book {
+        name: "Household".to_owned(),
+        description: None,
+        immutable: Some(true),
+        lock_date: None,
+    };
+    let get_book = &document["paths"]["/book/{book_name}"]["get"]["responses"]["200"];
+    conforms(
+        &document,
+        &get_book["content"]["application/json"]["schema"],
+        &json!(book),
+    )
+    .unwrap();
+    assert!(conforms(&document, &schema("Book"), &json!({ "name": 1 })).is_err());
+}
+
+/// Checks `value` against `schema` of the OpenAPI `document`, as far as the schemas of the API
+/// go. Objects must not have properties the schema leaves out.
+fn conforms(document: &Value, schema: &Value, value: &Value) -> Result<(), String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.rsplit('/').next().unwrap();
+        return conforms(document, &document["components"]["schemas"][name], value);
+    }
+    if let Some(schemas) = schema["anyOf"].as_array() {
+        return match schemas
+            .iter()
+            .any(|schema| conforms(document, schema, value).is_ok())
+        {
+            true => Ok(()),
+            false => Err(format!("{} matches none of {}", value, schema)),
+        };
+    }
+    let matches = match schema["type"].as_str() {
+        None => true,
+        Some("null") => value.is_null(),
+        Some("boolean") => value.is_boolean(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("string") => {
+            value.is_string()
+                && schema["enum"]
+                    .as_array()
+                    .is_none_or(|values| values.contains(value))
+        }
+        Some("array") => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| format!("{} is not an array", value))?;
+            return items
+                .iter()
+                .try_for_each(|item| conforms(document, &schema["items"], item));
+        }
+        Some("object") => {
+            let object = value
+                .as_object()
+                .ok_or_else(|| format!("{} is not an object", value))?;
+            for (name, property) in object {
+                let property_schema = schema["properties"]
+                    .get(name)
+                    .ok_or_else(|| format!("'{}' is not documented in {}", name, schema))?;
+                conforms(document, property_schema, property)?;
+            }
+            for name in schema["required"].as_array().unwrap() {
+                if !object.contains_key(name.as_str().unwrap()) {
+                    return Err(format!("{} lacks '{}'", value, name));
+                }
+            }
+            return Ok(());
+        }
+        Some(other) => return Err(format!("Unknown type '{}'.", other)),
+    };
+    match matches {
+        true => Ok(()),
+        false => Err(format!("{} is not {}", value, schema)),
+    }
+}
+
+/// Sends a request to `path`, which is `route` of the OpenAPI `document` filled in, and checks the
+/// response against the documented one. Returns the body, `Value::Null` unless it is JSON.
+async fn documented(
+    client: &FinanceClient,
+    document: &Value,
+    method: reqwest::Method,
+    route: &str,
+    path: &str,
+    body: Option<Value>,
+) -> Value {
+    let operation = &document["paths"][route][method.as_str().to_lowercase()];
+    assert!(
+        operation.is_object(),
+        "{} {} is not documented",
+        method,
+        route
+    );
+    let url = client.base_url().join(path).unwrap();
+    let mut request = reqwest::Client::new()
+        .request(method, url)
+        .bearer_auth(client.token().unwrap());
+    if let Some(body) = body {
+        request = request
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get("Content-Type")
+        .map(|value| value.to_str().unwrap().to_owned());
+    let bytes = response.bytes().await.unwrap();
+    let documented = match status.is_success() {
+        true => &operation["responses"][status.as_str()],
+        false => &operation["responses"]["default"],
+    };
+    let content = &documented["content"];
+    if let Some(json) = content.get("application/json") {
+        assert_eq!(
+            content_type.as_deref(),
+            Some("application/json"),
+            "{}",
+            route
+        );
+        let value: Value = serde_json::from_slice(&bytes).unwrap();
+        if let Err(e) = conforms(document, &json["schema"], &value) {
+            panic!("{} answered {}: {}", route, value, e);
+        }
+        value
+    } else if content.get("text/plain").is_some() {
+        assert!(
+            content_type.is_some_and(|value| value.starts_with("text/plain")),
+            "{}",
+            route
+        );
+        Value::Null
+    } else {
+        assert!(documented.is_object(), "{} answered {}", route, status);
+        assert!(bytes.is_empty(), "{} answered a body", route);
+        Value::Null
+    }
+}
+
+#[tokio::test]
+async fn responses_match_the_document() {
+    let client = serve().await;
+    let document = api().document();
+    let (get, post, delete) = (
+        reqwest::Method::GET,
+        reqwest::Method::POST,
+        reqwest::Method::DELETE,
+    );
+    let book = json!({ "name": "Household" });
+    documented(
+        &client,
+        &document,
+        post.clone(),
+        "/book/",
+        "book/",
+        Some(book),
+    )
+    .await;
+    let currency = json!({ "symbol": "EUR", "decimal_points": 2 });
+    documented(
+        &client,
+        &document,
+        post.clone(),
+        "/book/{book_name}/currency/",
+        "book/Household/currency/",
+        Some(currency),
+    )
+    .await;
+    for account in ["Cash", "Groceries"] {
+        documented(
+            &client,
+            &document,
+            post.clone(),
+            "/book/{book_name}/account/",
+            "book/Household/account/",
+            Some(json!({ "name": account })),
+        )
+        .await;
+    }
+    let transaction = documented(
+        &client,
+        &document,
+        post.clone(),
+        "/book/{book_name}/transaction",
+        "book/Household/transaction",
+        Some(json!({ "description": "Market" })),
+    )
+    .await;
+    let postings = format!("book/Household/transaction/{}/posting", transaction);
+    let mut posting_ids = Vec::new();
+    for (account_name, amount) in [("Cash", -1250), ("Groceries", 1250)] {
+        let posting = json!({
+            "account_name": account_name,
+            "currency": "EUR",
+            "amount": amount,
+            "budget": false,
+        });
+        let posting_id = documented(
+            &client,
+            &document,
+            post.clone(),
+            "/book/{book_name}/transaction/{transaction_id}/posting",
+            &postings,
+            Some(posting),
+        )
+        .await;
+        posting_ids.push(posting_id);
+    }
+    for (route, path) in [
+        ("/", ""),
+        ("/books/", "books/"),
+        ("/book/{book_name}", "book/Household"),
+        ("/book/{book_name}/members", "book/Household/members"),
+        ("/book/{book_name}/audit", "book/Household/audit"),
+        (
+            "/book/{book_name}/audit/verify",
+            "book/Household/audit/verify",
+        ),
+        (
+            "/book/{book_name}/transactions",
+            "book/Household/transactions",
+        ),
+        (
+            "/book/{book_name}/transaction/{transaction_id}",
+            &format!("book/Household/transaction/{}", transaction),
+        ),
+        (
+            "/book/{book_name}/account/{account_name}/register",
+            "book/Household/account/Cash/register",
+        ),
+        (
+            "/book/{book_name}/account/{account_name}/value",
+            "book/Household/account/Cash/value",
+        ),
+        (
+            "/book/{book_name}/report/flows",
+            "book/Household/report/flows",
+        ),
+        ("/tokens/", "tokens/"),
+        ("/users/", "users/"),
+    ] {
+        documented(&client, &document, get.clone(), route, path, None).await;
+    }
+    // Older clients update currencies without the symbol in the path.
+    let route = "/book/{book_name}/currency/update";
+    let currency = json!({ "symbol": "EUR", "description": "Euro", "decimal_points": 2 });
+    documented(
+        &client,
+        &document,
+        post.clone(),
+        route,
+        "book/Household/currency/update",
+        Some(currency),
+    )
+    .await;
+    assert_eq!(document["paths"][route]["post"]["deprecated"], true);
+    let currency = client.get_currency("Household", "EUR").await.unwrap();
+    assert_eq!(currency.description.as_deref(), Some("Euro"));
+    // What a deletion is said to do must match where the entity ends up.
+    let route = "/book/{book_name}/transaction/{transaction_id}/posting/{posting_id}";
+    let path = format!("{}/{}", postings, posting_ids[0]);
+    documented(&client, &document, delete.clone(), route, &path, None).await;
+    let trashed = client
+        .get_trash("Household")
+        .await
+        .unwrap()
+        .iter()
+        .any(|entry| entry.entity == AuditEntity::Posting);
+    let summary = document["paths"][route]["delete"]["summary"]
+        .as_str()
+        .unwrap();
+    assert_eq!(summary.contains("trash"), trashed, "{}", summary);
+    documented(
+        &client,
+        &document,
+        delete.clone(),
+        "/book/{book_name}/account/{account_name}",
+        "book/Household/account/Unknown",
+        None,
+    )
+    .await;
+    documented(
+        &client,
+        &document,
+        delete,
+        "/book/{book_name}",
+        "book/Household",
+        None,
+    )
+    .await;
+    documented(&client, &document, get, "/books/trash", "books/trash", None).await;
+}
@@ -0,0 +1,500 @@
+use crate::audit;
+use crate::auth::{self, CanAdmin, CanRead, CanWriteTransactions, Claim};
+use crate::db::{Backend, DbConnection};
+use crate::error::Error;
+use crate::idempotency;
+use crate::ledger;
+use crate::model::*;
+use crate::schema::*;
+use crate::{get_connection, ConnectionPool};
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use finance_lib::{AuditEntity, Capability, TrashEntry};
+use std::cmp::Reverse;
+use std::env;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+/// How often the trash is checked for entries older than the retention.
+const PURGE_INTERVAL_MINUTES: u64 = 60;
+
+/// Ids of the transactions of the book that are not in the trash. Postings of the other
+/// transactions are hidden with them.
+pub fn live_transactions<'a>(
+    owner: &'a String,
+    book_name: &'a String,
+) -> transactions::BoxedQuery<'a, Backend, BigInt> {
+    transactions::table
+        .select(transactions::dsl::id)
+        .filter(
+            transactions::dsl::user_name
+                .eq(owner)
+                .and(transactions::dsl::book_name.eq(book_name))
+                .and(transactions::dsl::deleted_at.is_null()),
+        )
+        .into_boxed()
+}
+
+/// Refuses a create or rename that collides with the name of a book, currency or account. Entries
+/// in the trash keep their name until they are restored or purged, which is a conflict rather
+/// than an existing entry. Books are looked up among those of `owner`, ignoring `book_name`.
+pub fn name_taken(
+    conn: &mut DbConnection,
+    owner: &String,
+    book_name: &String,
+    entity: AuditEntity,
+    name: &String,
+) -> Response {
+    let (label, trashed) = match entity {
+        AuditEntity::Book => (
+            "Book",
+            books::table
+                .select(books::dsl::name)
+                .filter(
+                    books::dsl::user_name
+                        .eq(owner)
+                        .and(books::dsl::name.eq(name))
+                        .and(books::dsl::deleted_at.is_not_null()),
+                )
+                .first::<String>(conn)
+                .optional(),
+        ),
+        AuditEntity::Currency => (
+            "Currency",
+            currencies::table
+                .select(currencies::dsl::symbol)
+                .filter(
+                    currencies::dsl::user_name
+                        .eq(owner)
+                        .and(currencies::dsl::book_name.eq(book_name))
+                        .and(currencies::dsl::symbol.eq(name))
+                        .and(currencies::dsl::deleted_at.is_not_null()),
+                )
+                .first::<String>(conn)
+                .optional(),
+        ),
+        _ => (
+            "Account",
+            accounts::table
+                .select(accounts::dsl::name)
+                .filter(
+                    accounts::dsl::user_name
+                        .eq(owner)
+                        .and(accounts::dsl::book_name.eq(book_name))
+                        .and(accounts::dsl::name.eq(name))
+                        .and(accounts::dsl::deleted_at.is_not_null()),
+                )
+                .first::<String>(conn)
+                .optional(),
+        ),
+    };
+    match trashed {
+        Ok(Some(_)) => Error::Conflict(format!(
+            "{} '{}' is in the trash. Restore it, or pick another name until it is purged.",
+            label, name
+        )),
+        Ok(None) => Error::AlreadyExists(format!("{} '{}' already exists.", label, name)),
+        Err(e) => Error::from(e),
+    }
+    .into_response()
+}
+
+/// How long deleted entries are kept, configured through `TRASH_RETENTION_DAYS`.
+fn retention() -> Duration {
+    let days = env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    Duration::days(days)
+}
+
+/// Permanently removes everything that has been in the trash for longer than `retention`. The
+/// database removes the rows beneath them, whose deletion the audit log already recorded. Only
+/// the postings of books go first, as their accounts and currencies cannot be deleted under them.
+pub fn purge(conn: &mut DbConnection, retention: Duration) -> QueryResult<usize> {
+    let cutoff = Utc::now().naive_utc() - retention;
+    conn.transaction(|conn| {
+        let purged_books = books::table
+            .select((books::dsl::name, books::dsl::user_name))
+            .filter(books::dsl::deleted_at.lt(cutoff))
+            .load::<(String, String)>(conn)?;
+        for (book_name, user_name) in &purged_books {
+            diesel::delete(postings::table)
+                .filter(
+                    postings::dsl::book_name
+                        .eq(book_name)
+                        .and(postings::dsl::user_name.eq(user_name)),
+                )
+                .execute(conn)?;
+        }
+        let transactions = diesel::delete(transactions::table)
+            .filter(transactions::dsl::deleted_at.lt(cutoff))
+            .execute(conn)?;
+        let accounts = diesel::delete(accounts::table)
+            .filter(accounts::dsl::deleted_at.lt(cutoff))
+            .execute(conn)?;
+        let currencies = diesel::delete(currencies::table)
+            .filter(currencies::dsl::deleted_at.lt(cutoff))
+            .execute(conn)?;
+        let books = diesel::delete(books::table)
+            .filter(books::dsl::deleted_at.lt(cutoff))
+            .execute(conn)?;
+        Ok(transactions + accounts + currencies + books)
+    })
+}
+
+/// Purges the trash, forgets old idempotency keys and deletes expired tokens every hour.
+pub async fn purge_periodically(pool: ConnectionPool) {
+    let retention = retention();
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_MINUTES * 60));
+    loop {
+        interval.tick().await;
+        let purged = match pool.get() {
+            Ok(mut conn) => purge(&mut conn, retention).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match purged {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} entries from the trash.", purged),
+            Err(e) => tracing::warn!("Could not purge the trash: {}", e),
+        }
+        let forgotten = match pool.get() {
+            Ok(mut conn) => idempotency::purge(&mut conn).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = forgotten {
+            tracing::warn!("Could not forget old idempotency keys: {}", e);
+        }
+        let expired = match pool.get() {
+            Ok(mut conn) => auth::purge_expired(&mut conn).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = expired {
+            tracing::warn!("Could not delete expired tokens: {}", e);
+        }
+    }
+}
+
+pub async fn get_trash(
+    claim: Claim<CanRead>,
+    Path(book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let currencies = currencies::table
+            .select((
+                currencies::dsl::symbol,
+                currencies::dsl::deleted_at.assume_not_null(),
+            ))
+            .filter(
+                currencies::dsl::user_name
+                    .eq(claim.owner())
+                    .and(currencies::dsl::book_name.eq(&book_name))
+                    .and(currencies::dsl::deleted_at.is_not_null()),
+            )
+            .load::<(String, NaiveDateTime)>(conn)?;
+        let accounts = accounts::table
+            .select((
+                accounts::dsl::name,
+                accounts::dsl::deleted_at.assume_not_null(),
+            ))
+            .filter(
+                accounts::dsl::user_name
+                    .eq(claim.owner())
+                    .and(accounts::dsl::book_name.eq(&book_name))
+                    .and(accounts::dsl::deleted_at.is_not_null()),
+            )
+            .load::<(String, NaiveDateTime)>(conn)?;
+        let transactions = transactions::table
+            .select((
+                transactions::dsl::id,
+                transactions::dsl::deleted_at.assume_not_null(),
+            ))
+            .filter(
+                transactions::dsl::user_name
+                    .eq(claim.owner())
+                    .and(transactions::dsl::book_name.eq(&book_name))
+                    .and(transactions::dsl::deleted_at.is_not_null()),
+            )
+            .load::<(i64, NaiveDateTime)>(conn)?;
+        let mut entries: Vec<_> = currencies
+            .into_iter()
+            .map(|(symbol, deleted_at)| (AuditEntity::Currency, symbol, deleted_at))
+            .chain(
+                accounts
+                    .into_iter()
+                    .map(|(name, deleted_at)| (AuditEntity::Account, name, deleted_at)),
+            )
+            .chain(
+                transactions
+                    .into_iter()
+                    .map(|(id, deleted_at)| (AuditEntity::Transaction, id.to_string(), deleted_at)),
+            )
+            .map(|(entity, entity_id, deleted_at)| TrashEntry {
+                entity,
+                entity_id,
+                deleted_at,
+            })
+            .collect();
+        entries.sort_by_key(|entry| Reverse(entry.deleted_at));
+        Ok::<_, diesel::result::Error>(entries)
+    });
+    match result {
+        Ok(entries) => Ok(Json(entries).into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+/// Takes a currency, account or transaction of the book out of the trash. Currencies and accounts
+/// require a token with the admin capability, like deleting them does.
+pub async fn restore(
+    claim: Claim<CanWriteTransactions>,
+    Path((book_name, entity, entity_id)): Path<(String, String, String)>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let entity = match entity.parse::<AuditEntity>() {
+        Ok(entity) => entity,
+        Err(e) => return Err(Error::Invalid(e).into_response()),
+    };
+    match entity {
+        AuditEntity::Currency | AuditEntity::Account
+            if claim.token.capability() < Capability::Admin =>
+        {
+            return Err(Error::Forbidden(format!(
+                "Token lacks the '{}' capability.",
+                Capability::Admin
+            ))
+            .into_response())
+        }
+        AuditEntity::Currency | AuditEntity::Account | AuditEntity::Transaction => {}
+        _ => {
+            return Err(Error::Invalid(
+                "Only currencies, accounts and transactions are kept in the trash of a book."
+                    .to_owned(),
+            )
+            .into_response())
+        }
+    }
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| match entity {
+        AuditEntity::Currency => restore_currency(conn, &claim, &book_name, &entity_id).map(Ok),
+        AuditEntity::Account => restore_account(conn, &claim, &book_name, &entity_id).map(Ok),
+        _ => match entity_id.parse::<i64>() {
+            Ok(transaction_id) => restore_transaction(conn, &claim, &book_name, transaction_id),
+            Err(_) => Ok(Ok(false)),
+        },
+    });
+    match result {
+        Ok(Ok(true)) => Ok(().into_response()),
+        Ok(Ok(false)) => Err(Error::NotFound(format!(
+            "{} '{}' is not in the trash.",
+            entity, entity_id
+        ))
+        .into_response()),
+        Ok(Err(refusal)) => Err(refusal.into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+fn restore_currency<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    symbol: &String,
+) -> QueryResult<bool> {
+    let restored = diesel::update(currencies::table)
+        .set(currencies::dsl::deleted_at.eq(None::<NaiveDateTime>))
+        .filter(
+            currencies::dsl::user_name
+                .eq(claim.owner())
+                .and(currencies::dsl::book_name.eq(book_name))
+                .and(currencies::dsl::symbol.eq(symbol))
+                .and(currencies::dsl::deleted_at.is_not_null()),
+        )
+        .execute(conn)?;
+    if restored == 0 {
+        return Ok(false);
+    }
+    let currency = currencies::table
+        .filter(
+            currencies::dsl::user_name
+                .eq(claim.owner())
+                .and(currencies::dsl::book_name.eq(book_name))
+                .and(currencies::dsl::symbol.eq(symbol)),
+        )
+        .first::<Currency>(conn)?;
+    audit::restored(
+        conn,
+        claim,
+        book_name,
+        AuditEntity::Currency,
+        symbol,
+        &currency.to_user_struct(),
+    )?;
+    Ok(true)
+}
+
+fn restore_account<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    account_name: &String,
+) -> QueryResult<bool> {
+    let restored = diesel::update(accounts::table)
+        .set(accounts::dsl::deleted_at.eq(None::<NaiveDateTime>))
+        .filter(
+            accounts::dsl::user_name
+                .eq(claim.owner())
+                .and(accounts::dsl::book_name.eq(book_name))
+                .and(accounts::dsl::name.eq(account_name))
+                .and(accounts::dsl::deleted_at.is_not_null()),
+        )
+        .execute(conn)?;
+    if restored == 0 {
+        return Ok(false);
+    }
+    let account = accounts::table
+        .filter(
+            accounts::dsl::user_name
+                .eq(claim.owner())
+                .and(accounts::dsl::book_name.eq(book_name))
+                .and(accounts::dsl::name.eq(account_name)),
+        )
+        .first::<Account>(conn)?;
+    audit::restored(
+        conn,
+        claim,
+        book_name,
+        AuditEntity::Account,
+        account_name,
+        &account.to_user_struct(),
+    )?;
+    Ok(true)
+}
+
+/// Restoring a transaction changes balances again, so closed periods refuse it.
+fn restore_transaction<C>(
+    conn: &mut DbConnection,
+    claim: &Claim<C>,
+    book_name: &String,
+    transaction_id: i64,
+) -> QueryResult<Result<bool, ledger::Refusal>> {
+    if let Err(refusal) = ledger::check_change(conn, claim.owner(), book_name, transaction_id, &[])?
+    {
+        return Ok(Err(refusal));
+    }
+    let restored = diesel::update(transactions::table)
+        .set(transactions::dsl::deleted_at.eq(None::<NaiveDateTime>))
+        .filter(
+            transactions::dsl::user_name
+                .eq(claim.owner())
+                .and(transactions::dsl::book_name.eq(book_name))
+                .and(transactions::dsl::id.eq(transaction_id))
+                .and(transactions::dsl::deleted_at.is_not_null()),
+        )
+        .execute(conn)?;
+    if restored == 0 {
+        return Ok(Ok(false));
+    }
+    let transaction = transactions::table
+        .filter(
+            transactions::dsl::user_name
+                .eq(claim.owner())
+                .and(transactions::dsl::book_name.eq(book_name))
+                .and(transactions::dsl::id.eq(transaction_id)),
+        )
+        .first::<Transaction>(conn)?;
+    audit::restored(
+        conn,
+        claim,
+        book_name,
+        AuditEntity::Transaction,
+        &transaction_id.to_string(),
+        &transaction.to_user_struct(),
+    )?;
+    Ok(Ok(true))
+}
+
+/// Lists the deleted books of the user. Deleted books are hidden from all book routes, and only
+/// their owner can see and restore them.
+pub async fn get_book_trash(
+    claim: Claim<CanAdmin>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = books::table
+        .select((books::dsl::name, books::dsl::deleted_at.assume_not_null()))
+        .filter(
+            books::dsl::user_name
+                .eq(&claim.user.name)
+                .and(books::dsl::deleted_at.is_not_null()),
+        )
+        .order(books::dsl::deleted_at.desc())
+        .load::<(String, NaiveDateTime)>(conn);
+    match result {
+        Ok(list) => {
+            let entries: Vec<_> = list
+                .into_iter()
+                .map(|(name, deleted_at)| TrashEntry {
+                    entity: AuditEntity::Book,
+                    entity_id: name,
+                    deleted_at,
+                })
+                .collect();
+            Ok(Json(entries).into_response())
+        }
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}
+
+pub async fn restore_book(
+    claim: Claim<CanAdmin>,
+    Path(deleted_book_name): Path<String>,
+    State(pool): State<ConnectionPool>,
+) -> Result<Response, Response> {
+    let conn = &mut get_connection(&pool)?;
+    let result = conn.transaction(|conn| {
+        let restored = diesel::update(books::table)
+            .set(books::dsl::deleted_at.eq(None::<NaiveDateTime>))
+            .filter(
+                books::dsl::name
+                    .eq(&deleted_book_name)
+                    .and(books::dsl::user_name.eq(&claim.user.name))
+                    .and(books::dsl::deleted_at.is_not_null()),
+            )
+            .execute(conn)?;
+        if restored == 0 {
+            return Ok(false);
+        }
+        let book = books::table
+            .filter(
+                books::dsl::name
+                    .eq(&deleted_book_name)
+                    .and(books::dsl::user_name.eq(&claim.user.name)),
+            )
+            .first::<Book>(conn)?;
+        audit::restored(
+            conn,
+            &claim,
+            &deleted_book_name,
+            AuditEntity::Book,
+            &deleted_book_name,
+            &book.to_user_struct(),
+        )?;
+        Ok::<_, diesel::result::Error>(true)
+    });
+    match result {
+        Ok(true) => Ok(().into_response()),
+        Ok(false) => Err(Error::NotFound(format!(
+            "Book '{}' is not in the trash.",
+            deleted_book_name
+        ))
+        .into_response()),
+        Err(e) => Err(Error::from(e).into_response()),
+    }
+}